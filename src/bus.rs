@@ -1,36 +1,242 @@
-use crate::{Exception, memory::Memory};
+use crate::{Exception, ExitReason, device::{Device, DeviceError}, memory::Memory};
+
+/// RAM の開始アドレス
+const MEMORY_BASE: u64 = 0x8000_0000;
+
+/// アドレスマップへの配置の失敗
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// サイズが 0 か、領域がアドレス空間の末尾を超えている
+    InvalidRange,
+    /// 既に配置されている領域 (base, size) と重なっている
+    Overlap { base: u64, size: u64 },
+}
+
+/// アドレスマップに配置されたもの
+enum Target {
+    /// RAM
+    Memory(Memory),
+    /// メモリマップド I/O デバイス
+    Device(Box<dyn Device>),
+}
+
+/// アドレスマップ上の領域
+struct Region {
+    /// 開始アドレス
+    base: u64,
+    /// サイズ (バイト数)
+    size: u64,
+    /// 配置されたもの
+    target: Target,
+    /// デバイスの割り込み線が接続された割り込み番号
+    irq: Option<u32>,
+}
 
 /// バス
 pub struct Bus {
-    /// メモリ
-    memory: Memory,
+    /// アドレスマップ (開始アドレスの昇順に並んでいる)
+    regions: Vec<Region>,
+    /// アドレスマップに配置せず、DMA のみで RAM にアクセスするデバイス (HTIF など)
+    attached: Vec<Box<dyn Device>>,
 }
 impl Bus {
-    /// 新しい Bus を作成します。
+    /// RAM を 0x8000_0000 に配置した、新しい Bus を作成します。
     pub fn new(memory: Memory) -> Self {
+        let mut bus = Self::empty();
+        bus.map_memory(MEMORY_BASE, memory).expect("空のバスには必ず配置できる");
+        bus
+    }
+
+    /// 何も配置されていない、新しい Bus を作成します。
+    pub fn empty() -> Self {
         Self {
-            memory
+            regions: Vec::new(),
+            attached: Vec::new(),
         }
     }
 
-    /// メモリからデータを読み込みます。
-    pub fn read(&self, addr: u64, size: u64) -> Result<u64, Exception> {
-        if addr >= 0x8000_0000 {
-            Ok(self.memory.read(addr - 0x8000_0000, size))
+    /// RAM をアドレスマップの base に配置します。
+    pub fn map_memory(&mut self, base: u64, memory: Memory) -> Result<(), MapError> {
+        let size = memory.size();
+        self.insert(Region { base, size, target: Target::Memory(memory), irq: None })
+    }
+
+    /// デバイスをアドレスマップの [base, base + size) に配置します。
+    pub fn map_device(&mut self, base: u64, size: u64, device: Box<dyn Device>) -> Result<(), MapError> {
+        self.insert(Region { base, size, target: Target::Device(device), irq: None })
+    }
+
+    /// デバイスをアドレスマップの [base, base + size) に配置し、その割り込み線を割り込み番号 irq に接続します。
+    pub fn map_device_with_irq(&mut self, base: u64, size: u64, irq: u32, device: Box<dyn Device>) -> Result<(), MapError> {
+        self.insert(Region { base, size, target: Target::Device(device), irq: Some(irq) })
+    }
+
+    /// デバイスを、アドレスマップに配置せずにバスに接続します。(DMA でのみ RAM にアクセスする)
+    pub fn attach_device(&mut self, device: Box<dyn Device>) {
+        self.attached.push(device);
+    }
+
+    /// 領域を、既存の領域と重ならないようにアドレスマップへ挿入します。
+    fn insert(&mut self, region: Region) -> Result<(), MapError> {
+        if region.size == 0 || region.base.checked_add(region.size).is_none() {
+            return Err(MapError::InvalidRange);
+        }
+
+        // NOTE: 挿入位置の前後の領域とだけ比較すれば、重なりを判定できる
+        let index = self.regions.partition_point(|r| r.base < region.base);
+        let neighbors = [index.checked_sub(1), Some(index)];
+        for other in neighbors.into_iter().flatten().filter_map(|i| self.regions.get(i)) {
+            if region.base < other.base + other.size && other.base < region.base + region.size {
+                return Err(MapError::Overlap { base: other.base, size: other.size });
+            }
+        }
+        self.regions.insert(index, region);
+        Ok(())
+    }
+
+    /// [addr, addr + size) を含む領域と、その領域内のオフセットを取得します。
+    fn find(&mut self, addr: u64, size: u64) -> Option<(&mut Target, u64)> {
+        let index = self.regions.partition_point(|r| r.base <= addr).checked_sub(1)?;
+        let region = &mut self.regions[index];
+        let offset = addr - region.base;
+        if offset.checked_add(size)? <= region.size {
+            Some((&mut region.target, offset))
         } else {
-            // TODO: UART とか将来あるかも
-            Err(Exception::InvalidMemoryAccess(addr))
+            None
         }
     }
 
-    /// メモリにデータを書き込みます。
+    /// バスからデータを読み込みます。
+    pub fn read(&mut self, addr: u64, size: u64) -> Result<u64, Exception> {
+        match self.find(addr, size) {
+            Some((Target::Memory(memory), offset)) => Ok(memory.read(offset, size)),
+            Some((Target::Device(device), offset)) => device.read(offset, size).map_err(|_| Exception::LoadAccessFault(addr)),
+            None => Err(Exception::LoadAccessFault(addr)),
+        }
+    }
+
+    /// バスにデータを書き込みます。
     pub fn write(&mut self, addr: u64, value: u64, size: u64) -> Result<(), Exception> {
-        if addr >= 0x8000_0000 {
-            self.memory.write(addr - 0x8000_0000, value, size);
-            Ok(())
-        } else {
-            // TODO: UART とか将来あるかも
-            Err(Exception::InvalidMemoryAccess(addr))
+        match self.find(addr, size) {
+            Some((Target::Memory(memory), offset)) => {
+                memory.write(offset, value, size);
+                Ok(())
+            },
+            Some((Target::Device(device), offset)) => device.write(offset, value, size).map_err(|_| Exception::StoreAccessFault(addr)),
+            None => Err(Exception::StoreAccessFault(addr)),
+        }
+    }
+
+    /// 配置されているすべてのデバイスの時間を 1 サイクル進めます。
+    pub fn tick(&mut self) {
+        // NOTE: DMA を行うデバイスには、自身を除いた領域へのアクセスを渡す
+        for index in 0..self.regions.len() {
+            let (before, rest) = self.regions.split_at_mut(index);
+            let (region, after) = rest.split_first_mut().expect("index は範囲内");
+            if let Target::Device(device) = &mut region.target {
+                device.tick();
+                device.dma(&mut Dma { regions: [before, after] });
+            }
         }
+        for device in self.attached.iter_mut() {
+            device.tick();
+            device.dma(&mut Dma { regions: [&mut self.regions, &mut []] });
+        }
+
+        // NOTE: アサートされている割り込み線を、割り込みコントローラ (PLIC) に伝える
+        let irqs: Vec<u32> = self.asserted_irqs().collect();
+        for region in self.regions.iter_mut() {
+            if let Target::Device(device) = &mut region.target {
+                device.set_irq_lines(&irqs);
+            }
+        }
+    }
+
+    /// 割り込み線がアサートされている割り込み番号を取得します。
+    pub fn asserted_irqs(&self) -> impl Iterator<Item = u32> + '_ {
+        self.regions.iter().filter_map(|region| match (&region.target, region.irq) {
+            (Target::Device(device), Some(irq)) if device.irq() => Some(irq),
+            _ => None,
+        })
+    }
+
+    /// 配置されているデバイスが CPU に伝えている割り込みを、mip のビットで取得します。
+    pub fn interrupts(&self) -> u64 {
+        self.devices().fold(0, |acc, device| acc | device.interrupts())
+    }
+
+    /// 配置されているデバイス (CLINT) の mtime を取得します。
+    pub fn mtime(&self) -> Option<u64> {
+        self.devices().find_map(|device| device.mtime())
+    }
+
+    /// デバイスから要求された、ゲストの実行を終了する理由を取得します。
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        self.devices().find_map(|device| device.exit_reason())
+    }
+
+    /// 接続されているすべてのデバイスを取得します。
+    fn devices(&self) -> impl Iterator<Item = &dyn Device> {
+        let mapped = self.regions.iter().filter_map(|region| match &region.target {
+            Target::Device(device) => Some(device.as_ref()),
+            Target::Memory(_) => None,
+        });
+        mapped.chain(self.attached.iter().map(|device| device.as_ref()))
+    }
+}
+
+/// デバイスから RAM へのアクセス (DMA)
+///
+/// アクセスできるのはアドレスマップに配置された RAM のみで、他のデバイスのレジスタにはアクセスできません。
+pub struct Dma<'a> {
+    /// アクセス元のデバイスを除いた領域 (それぞれ開始アドレスの昇順に並んでいる)
+    regions: [&'a mut [Region]; 2],
+}
+impl Dma<'_> {
+    /// [addr, addr + size) を含む RAM と、その RAM 内のオフセットを取得します。
+    fn memory(&mut self, addr: u64, size: u64) -> Result<(&mut Memory, u64), DeviceError> {
+        for regions in self.regions.iter_mut() {
+            let Some(index) = regions.partition_point(|r| r.base <= addr).checked_sub(1) else { continue };
+            let region = &mut regions[index];
+            let offset = addr - region.base;
+            if let Target::Memory(memory) = &mut region.target
+                && offset.checked_add(size).is_some_and(|end| end <= region.size)
+            {
+                return Ok((memory, offset));
+            }
+        }
+        Err(DeviceError)
+    }
+
+    /// [addr, addr + size) が 1 つの RAM に収まっているかを取得します。
+    pub fn contains(&mut self, addr: u64, size: u64) -> bool {
+        self.memory(addr, size).is_ok()
+    }
+
+    /// RAM から size バイトを読み込みます。
+    pub fn read(&mut self, addr: u64, size: u64) -> Result<u64, DeviceError> {
+        let (memory, offset) = self.memory(addr, size)?;
+        Ok(memory.read(offset, size))
+    }
+
+    /// RAM に size バイトを書き込みます。
+    pub fn write(&mut self, addr: u64, value: u64, size: u64) -> Result<(), DeviceError> {
+        let (memory, offset) = self.memory(addr, size)?;
+        memory.write(offset, value, size);
+        Ok(())
+    }
+
+    /// RAM から buf の長さ分のバイト列を読み込みます。
+    pub fn read_bytes(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), DeviceError> {
+        let (memory, offset) = self.memory(addr, buf.len() as u64)?;
+        memory.read_bytes(offset, buf);
+        Ok(())
+    }
+
+    /// RAM にバイト列を書き込みます。
+    pub fn write_bytes(&mut self, addr: u64, data: &[u8]) -> Result<(), DeviceError> {
+        let (memory, offset) = self.memory(addr, data.len() as u64)?;
+        memory.write_bytes(offset, data);
+        Ok(())
     }
 }
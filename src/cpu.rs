@@ -1,26 +1,56 @@
 mod csr;
 mod decode;
+mod float;
+mod mmu;
+mod tlb;
+mod trap;
 
-use crate::{Exception, Imm, Instruction, InstructionContext, RawInstruction, RawShortInstruction, RegIdx, XLEN, bus::Bus, cpu::csr::Csr};
+pub use tlb::TlbStats;
+
+use crate::{Exception, ExitReason, Imm, Instruction, InstructionContext, PrivilegeMode, RawInstruction, RawShortInstruction, RegIdx, TranslationMode, XLEN, bus::Bus, cpu::{csr::{CSR_SATP, CSR_TIME, Csr, mstatus::{TSR, TVM, TW}}, mmu::AccessType, tlb::Tlb}};
 
 /// CPU
 pub struct Cpu {
     /// レジスタ
     registers: [u64; 32],
+    /// 浮動小数点レジスタ (単精度の値は NaN Boxing して格納する)
+    fregisters: [u64; 32],
     /// プログラムカウンタ
     pc: u64,
+    /// 実行中の命令の次に実行する命令のアドレス (分岐・ジャンプ命令によって更新される)
+    next_pc: u64,
     /// バス
     bus: Bus,
     /// CSR レジスタ
     csr: Csr,
+    /// 現在の特権モード
+    mode: PrivilegeMode,
+    /// WFI によって割り込み待ち状態になっているか
+    waiting: bool,
+    /// デバイスから要求された、実行を終了した理由 (設定されると、以降のサイクルでは何もしない)
+    exit_reason: Option<ExitReason>,
+    /// LR 命令によって予約されたアドレス範囲 (アドレス, 幅)
+    reservation: Option<(u64, u64)>,
+    /// 命令フェッチ用の TLB
+    itlb: Tlb,
+    /// ロード・ストア用の TLB
+    dtlb: Tlb,
 }
 impl Cpu {
     pub fn new(bus: Bus) -> Self {
         Self {
             registers: [0; 32],
+            fregisters: [0; 32],
             pc: 0x8000_0000,
+            next_pc: 0x8000_0000,
             bus,
             csr: Csr::new(),
+            mode: PrivilegeMode::Machine,
+            waiting: false,
+            exit_reason: None,
+            reservation: None,
+            itlb: Tlb::new(),
+            dtlb: Tlb::new(),
         }
     }
 
@@ -33,7 +63,7 @@ impl Cpu {
         self.registers[index as usize]
     }
     /// レジスタに書き込みます。
-    pub fn write_register(&mut self, index: RegIdx, value: u64) -> () {
+    pub fn write_register(&mut self, index: RegIdx, value: u64) {
         if index == 0 {
             return;
         }
@@ -41,10 +71,65 @@ impl Cpu {
         self.registers[index as usize] = value;
     }
 
+    /// 浮動小数点レジスタを読み込みます。
+    pub fn read_fregister(&self, index: RegIdx) -> u64 {
+        self.fregisters[index as usize]
+    }
+    /// 浮動小数点レジスタに書き込みます。(mstatus.FS は Dirty になる)
+    pub fn write_fregister(&mut self, index: RegIdx, value: u64) {
+        self.fregisters[index as usize] = value;
+        self.csr.mark_fpu_dirty();
+    }
+
+    /// 現在の特権モードを取得します。
+    pub fn mode(&self) -> PrivilegeMode {
+        self.mode
+    }
+
+    /// satp.MODE に書き込める仮想アドレス変換方式を設定します。(既定ではすべての方式をサポートする)
+    ///
+    /// サポートしていない方式を satp に書き込んだ場合、書き込みは無視されます。
+    pub fn set_translation_modes(&mut self, modes: &[TranslationMode]) {
+        self.csr.set_translation_modes(modes);
+    }
+
+    /// 実装する PMP エントリの数 (0〜64, 既定は 16) を設定します。
+    ///
+    /// PMP エントリが 1 つでも実装されている場合、どのエントリにも一致しない S / U モードのアクセスはアクセスフォールトになります。
+    pub fn set_pmp_entries(&mut self, count: usize) {
+        self.csr.set_pmp_entries(count);
+    }
+
+    /// 命令フェッチ用の TLB のヒット・ミス回数を取得します。
+    pub fn instruction_tlb_stats(&self) -> TlbStats {
+        self.itlb.stats()
+    }
+    /// ロード・ストア用の TLB のヒット・ミス回数を取得します。
+    pub fn data_tlb_stats(&self) -> TlbStats {
+        self.dtlb.stats()
+    }
+
+    /// WFI によって割り込み待ち状態になっているかを取得します。
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// 実行が終了している場合、その理由を取得します。
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        self.exit_reason
+    }
+
     /// 命令をフェッチします。
     pub fn fetch(&mut self) -> Result<RawInstruction, Exception> {
-        let instruction = self.bus.read(self.pc, 4)? as RawInstruction;
-        Ok(instruction)
+        // NOTE: 圧縮命令はメモリの末尾に置かれることもあるので、まず下位 16bit だけを読み込む
+        let low = self.read_memory(self.pc, 2, AccessType::Instruction)?;
+        if low & 0b11 != 0b11 {
+            return Ok(low as RawInstruction);
+        }
+
+        let high_addr = self.pc.wrapping_add(2);
+        let high = self.read_memory(high_addr, 2, AccessType::Instruction)?;
+        Ok(((high << 16) | low) as RawInstruction)
     }
 
     pub fn decode(&self, instruction: RawInstruction) -> Result<InstructionContext, Exception> {
@@ -52,11 +137,13 @@ impl Cpu {
             Ok(InstructionContext {
                 instruction: decode::decode_compressed(instruction as RawShortInstruction)?,
                 next_pc: self.pc + 2,
+                raw: instruction,
             })
         } else {
             Ok(InstructionContext {
                 instruction: decode::decode(instruction)?,
                 next_pc: self.pc + 4,
+                raw: instruction,
             })
         }
     }
@@ -106,7 +193,7 @@ impl Cpu {
         self.write_register(rd, res as i64 as u64); // NOTE: 符号拡張
     }
 
-    /// B-Type (Branch) 演算用ヘルパー: 条件 (condition) が true なら、offset に基づき PC を更新します。
+    /// B-Type (Branch) 演算用ヘルパー: 条件 (condition) が true なら、offset に基づき分岐先を設定します。
     #[inline(always)]
     fn op_branch<F>(&mut self, rs1: RegIdx, rs2: RegIdx, offset: Imm, condition: F)
     where
@@ -115,7 +202,7 @@ impl Cpu {
         let val1 = self.read_register(rs1);
         let val2 = self.read_register(rs2);
         if condition(val1, val2) {
-            self.pc = self.pc.wrapping_add(offset as u64);
+            self.next_pc = self.pc.wrapping_add(offset as u64);
         }
     }
 
@@ -126,30 +213,105 @@ impl Cpu {
         F: FnOnce(u64) -> u64,
     {
         let addr = self.read_register(rs1).wrapping_add(offset as u64);
-        let val = self.bus.read(addr, width)?;
+        let val = self.read_memory(addr, width, AccessType::Load)?;
         self.write_register(rd, extend(val));
         Ok(())
     }
 
-    /// Store 命令用ヘルパー: アドレスを計算し、バスに書き込みます。
+    /// Store 命令用ヘルパー: アドレスを計算し、メモリに書き込みます。
     #[inline(always)]
     fn op_store(&mut self, rs1: RegIdx, rs2: RegIdx, offset: Imm, width: u64) -> Result<(), Exception> {
         let addr = self.read_register(rs1).wrapping_add(offset as u64);
         let val = self.read_register(rs2);
-        self.bus.write(addr, val, width)
+        self.write_memory(addr, val, width)?;
+        self.invalidate_reservation(addr, width);
+        Ok(())
+    }
+
+    /// 予約されたアドレス範囲に重なるストアが行われた場合、予約を破棄します。
+    fn invalidate_reservation(&mut self, addr: u64, width: u64) {
+        if let Some((reserved, reserved_width)) = self.reservation
+            && addr < reserved + reserved_width && reserved < addr + width {
+            self.reservation = None;
+        }
+    }
+
+    /// LR 命令用ヘルパー: アドレスを読み込んで rd に書き込み (W の場合は符号拡張)、そのアドレスを予約します。
+    #[inline(always)]
+    fn op_lr(&mut self, rd: RegIdx, rs1: RegIdx, width: u64) -> Result<(), Exception> {
+        let addr = self.read_register(rs1);
+        if !addr.is_multiple_of(width) {
+            return Err(Exception::LoadAddressMisaligned(addr));
+        }
+
+        let val = self.read_memory(addr, width, AccessType::Load)?;
+        self.write_register(rd, sign_extend(val, width));
+        self.reservation = Some((addr, width));
+        Ok(())
+    }
+
+    /// SC 命令用ヘルパー: アドレスが予約されていればストアして rd に 0 を、予約が無効ならストアせずに rd に 1 を書き込みます。
+    #[inline(always)]
+    fn op_sc(&mut self, rd: RegIdx, rs1: RegIdx, rs2: RegIdx, width: u64) -> Result<(), Exception> {
+        let addr = self.read_register(rs1);
+        if !addr.is_multiple_of(width) {
+            return Err(Exception::StoreAddressMisaligned(addr));
+        }
+
+        // NOTE: 成否にかかわらず、予約は破棄される
+        let reserved = self.reservation.take() == Some((addr, width));
+        if reserved {
+            self.write_memory(addr, self.read_register(rs2), width)?;
+        }
+        self.write_register(rd, if reserved { 0 } else { 1 });
+        Ok(())
+    }
+
+    /// AMO 命令用ヘルパー: アドレスの値を読み込んで rd に書き込み、その値と rs2 に op を適用した結果をストアします。
+    ///
+    /// W 命令の場合、読み込んだ値と rs2 は下位 32bit を符号拡張してから op に渡されます。
+    #[inline(always)]
+    fn op_amo<F>(&mut self, rd: RegIdx, rs1: RegIdx, rs2: RegIdx, width: u64, op: F) -> Result<(), Exception>
+    where
+        F: FnOnce(u64, u64) -> u64,
+    {
+        let addr = self.read_register(rs1);
+        if !addr.is_multiple_of(width) {
+            return Err(Exception::StoreAddressMisaligned(addr));
+        }
+
+        // NOTE: AMO の読み込みで発生したフォールトも、ストアのフォールトとして報告する
+        let paddr = self.translate(addr, width, AccessType::Store)?;
+        let val = sign_extend(self.bus.read(paddr, width).map_err(|_| Exception::StoreAccessFault(addr))?, width);
+        let src = sign_extend(self.read_register(rs2), width);
+        self.bus.write(paddr, op(val, src), width).map_err(|_| Exception::StoreAccessFault(addr))?;
+        self.invalidate_reservation(addr, width);
+        self.write_register(rd, val);
+        Ok(())
     }
 
-    /// Jump 命令用ヘルパー: rd に戻り先アドレスを書き込み、PC を target にジャンプします。
+    /// CSR 書き込み用ヘルパー: satp に書き込んだ場合は、TLB をすべて破棄します。
+    #[inline(always)]
+    fn write_csr(&mut self, csr: u16, val: u64) -> Result<(), Exception> {
+        self.csr.write(csr, val, self.mode)?;
+        if csr == CSR_SATP {
+            self.flush_tlb(None, None);
+        }
+        Ok(())
+    }
+
+    /// Jump 命令用ヘルパー: rd に戻り先アドレスを書き込み、target へのジャンプを設定します。
     #[inline(always)]
     fn op_jump(&mut self, ctx: InstructionContext, rd: RegIdx, target: u64) {
         self.write_register(rd, ctx.next_pc);
-        self.pc = target;
+        self.next_pc = target;
     }
 
     /// 命令を実行します。
     pub fn execute(&mut self, ctx: InstructionContext) -> Result<(), Exception> {
         let current_pc = self.pc;
-        let next_pc = ctx.next_pc;
+        let raw = ctx.raw;
+        self.next_pc = ctx.next_pc;
 
         match ctx.instruction {
             // NOTE: RV32I R-Type
@@ -167,7 +329,7 @@ impl Cpu {
             Instruction::MUL    { rd, rs1, rs2 } => self.op_rr(rd, rs1, rs2, |v1, v2| v1.wrapping_mul(v2)),
             Instruction::MULH   { rd, rs1, rs2 } => self.op_rr(rd, rs1, rs2, |v1, v2| ((v1 as i64 as i128).wrapping_mul(v2 as i64 as i128) >> XLEN) as u64),
             Instruction::MULHSU { rd, rs1, rs2 } => self.op_rr(rd, rs1, rs2, |v1, v2| ((v1 as i64 as i128).wrapping_mul(v2 as u128 as i128) >> XLEN) as u64),
-            Instruction::MULHU  { rd, rs1, rs2 } => self.op_rr(rd, rs1, rs2, |v1, v2| ((v1 as u64 as u128).wrapping_mul(v2 as u64 as u128) >> XLEN) as u64),
+            Instruction::MULHU  { rd, rs1, rs2 } => self.op_rr(rd, rs1, rs2, |v1, v2| ((v1 as u128).wrapping_mul(v2 as u128) >> XLEN) as u64),
             Instruction::DIV    { rd, rs1, rs2 } => self.op_rr(rd, rs1, rs2, |v1, v2| {
                 let dividend = v1 as i64;
                 let divisor = v2 as i64;
@@ -281,6 +443,97 @@ impl Cpu {
             // NOTE: RV64I S-Type
             Instruction::SD { rs1, rs2, offset } => self.op_store(rs1, rs2, offset, 8)?,
 
+            // NOTE: RV32A
+            Instruction::LRW      { rd, rs1 } => self.op_lr(rd, rs1, 4)?,
+            Instruction::SCW      { rd, rs1, rs2 } => self.op_sc(rd, rs1, rs2, 4)?,
+            Instruction::AMOSWAPW { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 4, |_, v2| v2)?,
+            Instruction::AMOADDW  { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 4, |v1, v2| v1.wrapping_add(v2))?,
+            Instruction::AMOXORW  { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 4, |v1, v2| v1 ^ v2)?,
+            Instruction::AMOANDW  { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 4, |v1, v2| v1 & v2)?,
+            Instruction::AMOORW   { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 4, |v1, v2| v1 | v2)?,
+            Instruction::AMOMINW  { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 4, |v1, v2| (v1 as i64).min(v2 as i64) as u64)?,
+            Instruction::AMOMAXW  { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 4, |v1, v2| (v1 as i64).max(v2 as i64) as u64)?,
+            // NOTE: 符号拡張しても 32bit 値同士の符号なしの大小関係は変わらない
+            Instruction::AMOMINUW { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 4, |v1, v2| v1.min(v2))?,
+            Instruction::AMOMAXUW { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 4, |v1, v2| v1.max(v2))?,
+            // NOTE: RV64A
+            Instruction::LRD      { rd, rs1 } => self.op_lr(rd, rs1, 8)?,
+            Instruction::SCD      { rd, rs1, rs2 } => self.op_sc(rd, rs1, rs2, 8)?,
+            Instruction::AMOSWAPD { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 8, |_, v2| v2)?,
+            Instruction::AMOADDD  { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 8, |v1, v2| v1.wrapping_add(v2))?,
+            Instruction::AMOXORD  { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 8, |v1, v2| v1 ^ v2)?,
+            Instruction::AMOANDD  { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 8, |v1, v2| v1 & v2)?,
+            Instruction::AMOORD   { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 8, |v1, v2| v1 | v2)?,
+            Instruction::AMOMIND  { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 8, |v1, v2| (v1 as i64).min(v2 as i64) as u64)?,
+            Instruction::AMOMAXD  { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 8, |v1, v2| (v1 as i64).max(v2 as i64) as u64)?,
+            Instruction::AMOMINUD { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 8, |v1, v2| v1.min(v2))?,
+            Instruction::AMOMAXUD { rd, rs1, rs2 } => self.op_amo(rd, rs1, rs2, 8, |v1, v2| v1.max(v2))?,
+
+            // NOTE: RV32F / RV64F
+            Instruction::FLW { rd, rs1, offset } => self.op_fp_load(rd, rs1, offset, 4, raw)?,
+            Instruction::FSW { rs1, rs2, offset } => self.op_fp_store(rs1, rs2, offset, 4, raw)?,
+            Instruction::FMADDS { rd, rs1, rs2, rs3, rm } => self.op_fp_r4::<f32, _>(rd, rs1, rs2, rs3, rm, raw, float::mul_add)?,
+            Instruction::FMSUBS { rd, rs1, rs2, rs3, rm } => self.op_fp_r4::<f32, _>(rd, rs1, rs2, rs3, rm, raw, |a, b, c, rm| float::mul_add(a, b, -c, rm))?,
+            Instruction::FNMSUBS { rd, rs1, rs2, rs3, rm } => self.op_fp_r4::<f32, _>(rd, rs1, rs2, rs3, rm, raw, |a, b, c, rm| float::mul_add(-a, b, c, rm))?,
+            Instruction::FNMADDS { rd, rs1, rs2, rs3, rm } => self.op_fp_r4::<f32, _>(rd, rs1, rs2, rs3, rm, raw, |a, b, c, rm| float::mul_add(-a, b, -c, rm))?,
+            Instruction::FADDS { rd, rs1, rs2, rm } => self.op_fp_rr::<f32, _>(rd, rs1, rs2, rm, raw, float::add)?,
+            Instruction::FSUBS { rd, rs1, rs2, rm } => self.op_fp_rr::<f32, _>(rd, rs1, rs2, rm, raw, float::sub)?,
+            Instruction::FMULS { rd, rs1, rs2, rm } => self.op_fp_rr::<f32, _>(rd, rs1, rs2, rm, raw, float::mul)?,
+            Instruction::FDIVS { rd, rs1, rs2, rm } => self.op_fp_rr::<f32, _>(rd, rs1, rs2, rm, raw, float::div)?,
+            Instruction::FSQRTS { rd, rs1, rm } => self.op_fp_unary::<f32, f32, _>(rd, rs1, rm, raw, float::sqrt)?,
+            Instruction::FSGNJS { rd, rs1, rs2 } => self.op_fp_sign::<f32, _>(rd, rs1, rs2, raw, |_, s2| s2)?,
+            Instruction::FSGNJNS { rd, rs1, rs2 } => self.op_fp_sign::<f32, _>(rd, rs1, rs2, raw, |_, s2| !s2)?,
+            Instruction::FSGNJXS { rd, rs1, rs2 } => self.op_fp_sign::<f32, _>(rd, rs1, rs2, raw, |s1, s2| s1 ^ s2)?,
+            Instruction::FMINS { rd, rs1, rs2 } => self.op_fp_rr::<f32, _>(rd, rs1, rs2, 0, raw, |a, b, _| float::min(a, b))?,
+            Instruction::FMAXS { rd, rs1, rs2 } => self.op_fp_rr::<f32, _>(rd, rs1, rs2, 0, raw, |a, b, _| float::max(a, b))?,
+            Instruction::FCVTWS { rd, rs1, rm } => self.op_fp_to_int::<f32>(rd, rs1, rm, raw, i32::MIN as i128, i32::MAX as i128, 4)?,
+            Instruction::FCVTWUS { rd, rs1, rm } => self.op_fp_to_int::<f32>(rd, rs1, rm, raw, 0, u32::MAX as i128, 4)?,
+            Instruction::FCVTLS { rd, rs1, rm } => self.op_fp_to_int::<f32>(rd, rs1, rm, raw, i64::MIN as i128, i64::MAX as i128, 8)?,
+            Instruction::FCVTLUS { rd, rs1, rm } => self.op_fp_to_int::<f32>(rd, rs1, rm, raw, 0, u64::MAX as i128, 8)?,
+            Instruction::FCVTSW { rd, rs1, rm } => self.op_fp_from_int::<f32, _>(rd, rs1, rm, raw, |v| v as i32 as i128)?,
+            Instruction::FCVTSWU { rd, rs1, rm } => self.op_fp_from_int::<f32, _>(rd, rs1, rm, raw, |v| v as u32 as i128)?,
+            Instruction::FCVTSL { rd, rs1, rm } => self.op_fp_from_int::<f32, _>(rd, rs1, rm, raw, |v| v as i64 as i128)?,
+            Instruction::FCVTSLU { rd, rs1, rm } => self.op_fp_from_int::<f32, _>(rd, rs1, rm, raw, |v| v as i128)?,
+            Instruction::FMVXW { rd, rs1 } => self.op_fp_move_to_int(rd, rs1, 4, raw)?,
+            Instruction::FMVWX { rd, rs1 } => self.op_fp_move_from_int(rd, rs1, 4, raw)?,
+            Instruction::FCLASSS { rd, rs1 } => self.op_fp_classify::<f32>(rd, rs1, raw)?,
+            Instruction::FEQS { rd, rs1, rs2 } => self.op_fp_compare::<f32, _>(rd, rs1, rs2, raw, float::eq)?,
+            Instruction::FLTS { rd, rs1, rs2 } => self.op_fp_compare::<f32, _>(rd, rs1, rs2, raw, float::lt)?,
+            Instruction::FLES { rd, rs1, rs2 } => self.op_fp_compare::<f32, _>(rd, rs1, rs2, raw, float::le)?,
+            // NOTE: RV32D / RV64D
+            Instruction::FLD { rd, rs1, offset } => self.op_fp_load(rd, rs1, offset, 8, raw)?,
+            Instruction::FSD { rs1, rs2, offset } => self.op_fp_store(rs1, rs2, offset, 8, raw)?,
+            Instruction::FMADDD { rd, rs1, rs2, rs3, rm } => self.op_fp_r4::<f64, _>(rd, rs1, rs2, rs3, rm, raw, float::mul_add)?,
+            Instruction::FMSUBD { rd, rs1, rs2, rs3, rm } => self.op_fp_r4::<f64, _>(rd, rs1, rs2, rs3, rm, raw, |a, b, c, rm| float::mul_add(a, b, -c, rm))?,
+            Instruction::FNMSUBD { rd, rs1, rs2, rs3, rm } => self.op_fp_r4::<f64, _>(rd, rs1, rs2, rs3, rm, raw, |a, b, c, rm| float::mul_add(-a, b, c, rm))?,
+            Instruction::FNMADDD { rd, rs1, rs2, rs3, rm } => self.op_fp_r4::<f64, _>(rd, rs1, rs2, rs3, rm, raw, |a, b, c, rm| float::mul_add(-a, b, -c, rm))?,
+            Instruction::FADDD { rd, rs1, rs2, rm } => self.op_fp_rr::<f64, _>(rd, rs1, rs2, rm, raw, float::add)?,
+            Instruction::FSUBD { rd, rs1, rs2, rm } => self.op_fp_rr::<f64, _>(rd, rs1, rs2, rm, raw, float::sub)?,
+            Instruction::FMULD { rd, rs1, rs2, rm } => self.op_fp_rr::<f64, _>(rd, rs1, rs2, rm, raw, float::mul)?,
+            Instruction::FDIVD { rd, rs1, rs2, rm } => self.op_fp_rr::<f64, _>(rd, rs1, rs2, rm, raw, float::div)?,
+            Instruction::FSQRTD { rd, rs1, rm } => self.op_fp_unary::<f64, f64, _>(rd, rs1, rm, raw, float::sqrt)?,
+            Instruction::FSGNJD { rd, rs1, rs2 } => self.op_fp_sign::<f64, _>(rd, rs1, rs2, raw, |_, s2| s2)?,
+            Instruction::FSGNJND { rd, rs1, rs2 } => self.op_fp_sign::<f64, _>(rd, rs1, rs2, raw, |_, s2| !s2)?,
+            Instruction::FSGNJXD { rd, rs1, rs2 } => self.op_fp_sign::<f64, _>(rd, rs1, rs2, raw, |s1, s2| s1 ^ s2)?,
+            Instruction::FMIND { rd, rs1, rs2 } => self.op_fp_rr::<f64, _>(rd, rs1, rs2, 0, raw, |a, b, _| float::min(a, b))?,
+            Instruction::FMAXD { rd, rs1, rs2 } => self.op_fp_rr::<f64, _>(rd, rs1, rs2, 0, raw, |a, b, _| float::max(a, b))?,
+            Instruction::FCVTWD { rd, rs1, rm } => self.op_fp_to_int::<f64>(rd, rs1, rm, raw, i32::MIN as i128, i32::MAX as i128, 4)?,
+            Instruction::FCVTWUD { rd, rs1, rm } => self.op_fp_to_int::<f64>(rd, rs1, rm, raw, 0, u32::MAX as i128, 4)?,
+            Instruction::FCVTLD { rd, rs1, rm } => self.op_fp_to_int::<f64>(rd, rs1, rm, raw, i64::MIN as i128, i64::MAX as i128, 8)?,
+            Instruction::FCVTLUD { rd, rs1, rm } => self.op_fp_to_int::<f64>(rd, rs1, rm, raw, 0, u64::MAX as i128, 8)?,
+            Instruction::FCVTDW { rd, rs1, rm } => self.op_fp_from_int::<f64, _>(rd, rs1, rm, raw, |v| v as i32 as i128)?,
+            Instruction::FCVTDWU { rd, rs1, rm } => self.op_fp_from_int::<f64, _>(rd, rs1, rm, raw, |v| v as u32 as i128)?,
+            Instruction::FCVTDL { rd, rs1, rm } => self.op_fp_from_int::<f64, _>(rd, rs1, rm, raw, |v| v as i64 as i128)?,
+            Instruction::FCVTDLU { rd, rs1, rm } => self.op_fp_from_int::<f64, _>(rd, rs1, rm, raw, |v| v as i128)?,
+            Instruction::FMVXD { rd, rs1 } => self.op_fp_move_to_int(rd, rs1, 8, raw)?,
+            Instruction::FMVDX { rd, rs1 } => self.op_fp_move_from_int(rd, rs1, 8, raw)?,
+            Instruction::FCLASSD { rd, rs1 } => self.op_fp_classify::<f64>(rd, rs1, raw)?,
+            Instruction::FEQD { rd, rs1, rs2 } => self.op_fp_compare::<f64, _>(rd, rs1, rs2, raw, float::eq)?,
+            Instruction::FLTD { rd, rs1, rs2 } => self.op_fp_compare::<f64, _>(rd, rs1, rs2, raw, float::lt)?,
+            Instruction::FLED { rd, rs1, rs2 } => self.op_fp_compare::<f64, _>(rd, rs1, rs2, raw, float::le)?,
+            Instruction::FCVTSD { rd, rs1, rm } => self.op_fp_unary::<f64, f32, _>(rd, rs1, rm, raw, float::convert)?,
+            Instruction::FCVTDS { rd, rs1, rm } => self.op_fp_unary::<f32, f64, _>(rd, rs1, rm, raw, float::convert)?,
+
             // NOTE: RV32I B-Type
             Instruction::BEQ  { rs1, rs2, offset } => self.op_branch(rs1, rs2, offset, |v1, v2| v1 == v2),
             Instruction::BNE  { rs1, rs2, offset } => self.op_branch(rs1, rs2, offset, |v1, v2| v1 != v2),
@@ -304,74 +557,171 @@ impl Cpu {
                 self.op_jump(ctx, rd, target);
             },
 
+            // NOTE: RV32I MISC-MEM / Zifencei
+            // NOTE: シングルコアで、メモリアクセスは常にプログラム順に完了するので、フェンスは何もしなくてよい
+            Instruction::FENCE { .. } | Instruction::FENCETSO | Instruction::PAUSE => {},
+            // NOTE: デコード済みの命令をキャッシュしておらず、毎回メモリからフェッチするので、書き換えた命令はすぐに見える
+            Instruction::FENCEI => {},
+
             // NOTE: RV32I System
-            Instruction::ECALL => {},
-            Instruction::EBREAK => {},
+            Instruction::ECALL => return Err(match self.mode {
+                PrivilegeMode::User => Exception::EnvironmentCallFromUMode,
+                PrivilegeMode::Supervisor => Exception::EnvironmentCallFromSMode,
+                PrivilegeMode::Machine => Exception::EnvironmentCallFromMMode,
+            }),
+            Instruction::EBREAK => return Err(Exception::Breakpoint(current_pc)),
             Instruction::CSRRW { rd, rs1, csr } => {
-                let old_value = if rd != 0 { self.csr.read(csr)? } else { 0 };
-                self.csr.write(csr, self.read_register(rs1));
+                let old_value = if rd != 0 { self.csr.read(csr, self.mode)? } else { 0 };
+                self.write_csr(csr, self.read_register(rs1))?;
                 self.write_register(rd, old_value);
             }
             Instruction::CSRRS { rd, rs1, csr } => {
-                let old_value = self.csr.read(csr)?;
+                let old_value = self.csr.read(csr, self.mode)?;
                 if rs1 != 0 {
-                    self.csr.write(csr, old_value | self.read_register(rs1));
+                    self.write_csr(csr, self.csr.read_for_update(csr, self.mode)? | self.read_register(rs1))?;
                 }
                 self.write_register(rd, old_value);
             }
             Instruction::CSRRC { rd, rs1, csr } => {
-                let old_value = self.csr.read(csr)?;
+                let old_value = self.csr.read(csr, self.mode)?;
                 if rs1 != 0 {
-                    self.csr.write(csr, old_value & !self.read_register(rs1));
+                    self.write_csr(csr, self.csr.read_for_update(csr, self.mode)? & !self.read_register(rs1))?;
                 }
                 self.write_register(rd, old_value);
             }
             Instruction::CSRRWI { rd, imm, csr } => {
-                let old_value = if rd != 0 { self.csr.read(csr)? } else { 0 };
-                self.csr.write(csr, imm as u64);
+                let old_value = if rd != 0 { self.csr.read(csr, self.mode)? } else { 0 };
+                self.write_csr(csr, imm as u64)?;
                 self.write_register(rd, old_value);
             }
             Instruction::CSRRSI { rd, imm, csr } => {
-                let old_value = self.csr.read(csr)?;
+                let old_value = self.csr.read(csr, self.mode)?;
                 if imm != 0 {
-                    self.csr.write(csr, old_value | (imm as u64));
+                    self.write_csr(csr, self.csr.read_for_update(csr, self.mode)? | (imm as u64))?;
                 }
                 self.write_register(rd, old_value);
             }
             Instruction::CSRRCI { rd, imm, csr } => {
-                let old_value = self.csr.read(csr)?;
+                let old_value = self.csr.read(csr, self.mode)?;
                 if imm != 0 {
-                    self.csr.write(csr, old_value & !(imm as u64));
+                    self.write_csr(csr, self.csr.read_for_update(csr, self.mode)? & !(imm as u64))?;
                 }
                 self.write_register(rd, old_value);
             }
 
+            // NOTE: 特権命令
+            Instruction::MRET => {
+                if self.mode < PrivilegeMode::Machine {
+                    return Err(Exception::UnknownInstruction(raw));
+                }
+                self.next_pc = self.return_from_machine_trap();
+            }
+            Instruction::SRET => {
+                // NOTE: mstatus.TSR = 1 の場合、S モードでの SRET は不正命令
+                if self.mode < PrivilegeMode::Supervisor
+                    || (self.mode == PrivilegeMode::Supervisor && self.csr.mstatus().get(TSR) != 0) {
+                    return Err(Exception::UnknownInstruction(raw));
+                }
+                self.next_pc = self.return_from_supervisor_trap();
+            }
+            Instruction::WFI => {
+                // NOTE: U モードでの WFI や、mstatus.TW = 1 の場合の M モード以外での WFI は不正命令
+                if self.mode == PrivilegeMode::User
+                    || (self.mode < PrivilegeMode::Machine && self.csr.mstatus().get(TW) != 0) {
+                    return Err(Exception::UnknownInstruction(raw));
+                }
+                // NOTE: 割り込みが保留されるまで、命令の実行を止める
+                self.waiting = true;
+            }
+            Instruction::SFENCEVMA { rs1, rs2 } => {
+                // NOTE: U モードでの SFENCE.VMA や、mstatus.TVM = 1 の場合の S モードでの SFENCE.VMA は不正命令
+                if self.mode == PrivilegeMode::User
+                    || (self.mode == PrivilegeMode::Supervisor && self.csr.mstatus().get(TVM) != 0) {
+                    return Err(Exception::UnknownInstruction(raw));
+                }
+                // NOTE: rs1 = x0 ならすべてのアドレス、rs2 = x0 ならすべてのアドレス空間が対象
+                let addr = (rs1 != 0).then(|| self.read_register(rs1));
+                let asid = (rs2 != 0).then(|| self.read_register(rs2) & 0xFFFF);
+                self.flush_tlb(addr, asid);
+            }
         }
 
-        if current_pc == self.pc {
-            // NOTE: 命令によって PC が更新されていなければ、次の命令へ進む
-            self.pc = next_pc;
-        }
+        // NOTE: 例外が発生せずに命令が完了した場合のみ、PC を進める (自分自身へのジャンプもあり得るので、必ず next_pc を使う)
+        self.pc = self.next_pc;
 
         Ok(())
     }
 
+    /// 1 命令を実行し、例外が発生した場合はトラップを処理します。
+    ///
+    /// 命令の実行前に割り込みを確認し、受け付け可能な割り込みがあればそのトラップを処理します。
     pub fn cycle(&mut self) {
-        // TODO: unwrap
-        let instruction = self.fetch().unwrap();
-        let ctx = self.decode(instruction).unwrap();
+        if self.exit_reason.is_some() {
+            return;
+        }
 
-        println!("Execute: {:?}", ctx);
+        // NOTE: デバイスは CPU と同じクロックで動く (WFI 中も止まらない)
+        self.bus.tick();
+        if let Some(reason) = self.bus.exit_reason() {
+            self.exit_reason = Some(reason);
+            return;
+        }
+        // NOTE: 割り込みコントローラの出力と CLINT の mtime を、mip と time に反映する
+        self.csr.set_interrupt_lines(self.bus.interrupts());
+        if let Some(mtime) = self.bus.mtime() {
+            self.csr.set(CSR_TIME, mtime);
+        }
 
-        if let Instruction::EBREAK = ctx.instruction {
-            panic!("EBREAK encountered. Halting execution."); // TODO: 暫定的処置
+        if self.waiting {
+            if !self.has_wakeup_interrupt() {
+                // NOTE: WFI 中は命令をフェッチせず、サイクル数だけ進める
+                self.csr.count(false);
+                return;
+            }
+            self.waiting = false;
         }
 
-        match self.execute(ctx) {
-            Ok(_) => {},
-            Err(e) => {
-                panic!("{:?}", e);
-            },
-        };
+        if let Some(interrupt) = self.pending_interrupt() {
+            self.csr.count(false);
+            self.handle_interrupt(interrupt);
+            return;
+        }
+
+        let result = self.step();
+        self.csr.count(result.is_ok());
+        if let Err(e) = result {
+            self.handle_exception(e);
+        }
+    }
+
+    /// 実行が終了するか、max_cycles サイクルが経過するまで実行します。
+    ///
+    /// 実行が終了した場合はその理由を、サイクル数の上限に達した場合は None を返します。
+    pub fn run(&mut self, max_cycles: u64) -> Option<ExitReason> {
+        for _ in 0..max_cycles {
+            self.cycle();
+            if self.exit_reason.is_some() {
+                break;
+            }
+        }
+        self.exit_reason
+    }
+
+    /// 命令をフェッチ・デコードし、実行します。
+    fn step(&mut self) -> Result<(), Exception> {
+        let instruction = self.fetch()?;
+        let ctx = self.decode(instruction)?;
+        self.execute(ctx).map_err(|e| match e {
+            // NOTE: CSR へのアクセス違反は不正命令例外なので、mtval には命令自体を報告する
+            Exception::InvalidCsrAccess(_) => Exception::UnknownInstruction(instruction),
+            e => e,
+        })
     }
 }
+
+/// width バイトの値を 64bit に符号拡張します。
+#[inline(always)]
+fn sign_extend(val: u64, width: u64) -> u64 {
+    let shift = 64 - width * 8;
+    (((val << shift) as i64) >> shift) as u64
+}
@@ -1,13 +1,91 @@
-mod mstatus;
+pub mod mip;
+pub mod mstatus;
+pub mod pmp;
 
-use crate::{Exception, cpu::csr::mstatus::Mstatus};
+use crate::{Exception, PrivilegeMode, TranslationMode, cpu::csr::{mip::{ALL_INTERRUPTS, MIP_WRITABLE, SEIP, SSIP, SUPERVISOR_INTERRUPTS}, mstatus::{FS, Mstatus, TVM}}};
 
+// NOTE: 浮動小数点 (fflags, frm は fcsr の一部を見せるビュー)
+pub const CSR_FFLAGS: u16 = 0x001;
+pub const CSR_FRM: u16 = 0x002;
+pub const CSR_FCSR: u16 = 0x003;
+// NOTE: マシンモード情報レジスタ (読み取り専用)
+pub const CSR_MVENDORID: u16 = 0xF11;
+pub const CSR_MARCHID: u16 = 0xF12;
+pub const CSR_MIMPID: u16 = 0xF13;
 pub const CSR_MHARTID: u16 = 0xF14;
-pub const CSR_MISA: u16 = 0x301;
+pub const CSR_MCONFIGPTR: u16 = 0xF15;
+// NOTE: マシンモードトラップ設定
 pub const CSR_MSTATUS: u16 = 0x300;
+pub const CSR_MISA: u16 = 0x301;
+pub const CSR_MEDELEG: u16 = 0x302;
+pub const CSR_MIDELEG: u16 = 0x303;
+pub const CSR_MIE: u16 = 0x304;
+pub const CSR_MTVEC: u16 = 0x305;
+pub const CSR_MCOUNTEREN: u16 = 0x306;
+pub const CSR_MENVCFG: u16 = 0x30A;
+pub const CSR_MCOUNTINHIBIT: u16 = 0x320;
+// NOTE: マシンモードトラップ処理
+pub const CSR_MSCRATCH: u16 = 0x340;
+pub const CSR_MEPC: u16 = 0x341;
+pub const CSR_MCAUSE: u16 = 0x342;
+pub const CSR_MTVAL: u16 = 0x343;
+pub const CSR_MIP: u16 = 0x344;
+// NOTE: マシンモードカウンタ
+pub const CSR_MCYCLE: u16 = 0xB00;
+pub const CSR_MINSTRET: u16 = 0xB02;
+// NOTE: スーパーバイザーモード
+pub const CSR_SSTATUS: u16 = 0x100;
+pub const CSR_SIE: u16 = 0x104;
+pub const CSR_STVEC: u16 = 0x105;
+pub const CSR_SCOUNTEREN: u16 = 0x106;
+pub const CSR_SSCRATCH: u16 = 0x140;
+pub const CSR_SEPC: u16 = 0x141;
+pub const CSR_SCAUSE: u16 = 0x142;
+pub const CSR_STVAL: u16 = 0x143;
+pub const CSR_SIP: u16 = 0x144;
+pub const CSR_SATP: u16 = 0x180;
+// NOTE: ユーザーモードカウンタ (読み取り専用)
+pub const CSR_CYCLE: u16 = 0xC00;
+pub const CSR_TIME: u16 = 0xC01;
+pub const CSR_INSTRET: u16 = 0xC02;
+
+/// mhpmcounter3..31, mhpmevent3..31, hpmcounter3..31 (実装していないので常に 0)
+const CSR_MHPMCOUNTERS: std::ops::RangeInclusive<u16> = 0xB03..=0xB1F;
+const CSR_MHPMEVENTS: std::ops::RangeInclusive<u16> = 0x323..=0x33F;
+const CSR_HPMCOUNTERS: std::ops::RangeInclusive<u16> = 0xC03..=0xC1F;
+/// pmpcfg0..15 (RV64 では奇数番目は存在しない), pmpaddr0..63
+const CSR_PMPCFGS: std::ops::RangeInclusive<u16> = 0x3A0..=0x3AF;
+const CSR_PMPADDRS: std::ops::RangeInclusive<u16> = 0x3B0..=0x3EF;
+
+/// fcsr の fflags フィールド
+const FCSR_FFLAGS: u64 = 0x1F;
+/// fcsr の frm フィールド
+const FCSR_FRM: u64 = 0b111 << 5;
+
+/// mcountinhibit の CY ビット
+const INHIBIT_CY: u64 = 1 << 0;
+/// mcountinhibit の IR ビット
+const INHIBIT_IR: u64 = 1 << 2;
 
 const MISA_64BIT: u64 = 2 << 62; // 32bit=1, 64bit=2
 
+/// medeleg で委譲可能な例外 (M モードからの ECALL (11) と予約済みの要因は委譲できない)
+const MEDELEG_MASK: u64 = 0b1011_0011_1111_1111;
+/// mideleg で委譲可能な割り込み (S モードの割り込みのみ)
+const MIDELEG_MASK: u64 = SUPERVISOR_INTERRUPTS;
+
+/// satp の MODE フィールド
+pub const SATP_MODE: u64 = 0xF << 60;
+/// satp の ASID フィールド (16bit すべて実装)
+pub const SATP_ASID: u64 = 0xFFFF << 44;
+/// satp の PPN フィールド
+pub const SATP_PPN: u64 = (1 << 44) - 1;
+/// satp.MODE = Bare (アドレス変換なし)
+const SATP_MODE_BARE: u64 = 0;
+
+/// menvcfg の ADUE ビット (Svadu: ハードウェアによる A / D ビットの更新を有効にする)
+const MENVCFG_ADUE: u64 = 1 << 61;
+
 /// MISA レジスタの CPU 拡張表現ビットを取得します。
 const fn ext(ext: u8) -> u64 {
     1 << (ext - b'A')
@@ -17,44 +95,258 @@ const fn ext(ext: u8) -> u64 {
 pub struct Csr {
     /// CSR レジスタの値
     data: [u64; 4096],
+    /// satp.MODE に書き込める仮想アドレス変換方式
+    translation_modes: Vec<TranslationMode>,
+    /// 実装されている PMP エントリの数
+    pmp_entries: usize,
+    /// 割り込みコントローラから駆動されている mip のビット
+    interrupt_lines: u64,
 }
 impl Csr {
     /// CSR レジスタ構造体を作成します。
     pub fn new() -> Self {
-        Self { data: [0; 4096] }
+        let mut csr = Self { data: [0; 4096], translation_modes: TranslationMode::ALL.to_vec(), pmp_entries: 16, interrupt_lines: 0 };
+        // NOTE: UXL, SXL などの固定値を反映させる
+        csr.data[CSR_MSTATUS as usize] = Mstatus::new(0).write(0, csr.extensions()).read();
+        csr
     }
 
     /// CSR レジスタの値を読み取ります。
-    pub fn read(&self, addr: u16) -> Result<u64, Exception> {
-        if addr as usize >= self.data.len() {
-            Err(Exception::InvalidCsrAccess(addr))
-        } else if addr == CSR_MHARTID {
-            Ok(0) // TODO: シングルコア
-        } else if addr == CSR_MISA {
-            Ok(MISA_64BIT | ext(b'I') | ext(b'M') | ext(b'C'))
-        } else if addr == CSR_MSTATUS {
-            Ok(Mstatus::new(self.data[addr as usize]).read())
-        } else {
-            Ok(self.data[addr as usize])
-        }
-        // TODO: marchid, mvendorid, mimpid, etc...
+    pub fn read(&self, addr: u16, mode: PrivilegeMode) -> Result<u64, Exception> {
+        self.check_access(addr, mode, false)?;
+
+        Ok(match addr {
+            CSR_MVENDORID | CSR_MARCHID | CSR_MIMPID | CSR_MCONFIGPTR => 0,
+            CSR_MHARTID => 0, // TODO: シングルコア
+            CSR_MISA => MISA_64BIT | ext(b'I') | ext(b'M') | ext(b'A') | ext(b'F') | ext(b'D') | ext(b'C') | ext(b'S') | ext(b'U'),
+            CSR_MSTATUS => Mstatus::new(self.data[addr as usize]).read(),
+            CSR_FFLAGS => self.data[CSR_FCSR as usize] & FCSR_FFLAGS,
+            CSR_FRM => (self.data[CSR_FCSR as usize] & FCSR_FRM) >> 5,
+            // NOTE: sstatus, sie, sip は mstatus, mie, mip の一部を見せるビュー
+            CSR_SSTATUS => self.mstatus().read_supervisor(),
+            CSR_SIE => self.data[CSR_MIE as usize] & self.data[CSR_MIDELEG as usize],
+            CSR_MIP => self.mip(),
+            CSR_SIP => self.mip() & self.data[CSR_MIDELEG as usize],
+            CSR_CYCLE => self.data[CSR_MCYCLE as usize],
+            CSR_INSTRET => self.data[CSR_MINSTRET as usize],
+            _ if CSR_MHPMCOUNTERS.contains(&addr)
+                || CSR_MHPMEVENTS.contains(&addr)
+                || CSR_HPMCOUNTERS.contains(&addr) => 0,
+            _ => self.data[addr as usize],
+        })
+    }
+    /// CSRRS, CSRRC などの読み込み-変更-書き込みで、書き込む値の元にする CSR レジスタの値を読み取ります。
+    ///
+    /// mip の SEIP は、割り込みコントローラからの信号を含まない、ソフトウェアから書き込んだビットのみを使います。
+    pub fn read_for_update(&self, addr: u16, mode: PrivilegeMode) -> Result<u64, Exception> {
+        let value = self.read(addr, mode)?;
+        Ok(match addr {
+            CSR_MIP => (value & !SEIP) | (self.data[CSR_MIP as usize] & SEIP),
+            _ => value,
+        })
     }
     /// CSR レジスタに値を書き込みます。
-    pub fn write(&mut self, addr: u16, val: u64) {
-        if addr as usize >= self.data.len() {
-            return;
+    pub fn write(&mut self, addr: u16, val: u64, mode: PrivilegeMode) -> Result<(), Exception> {
+        self.check_access(addr, mode, true)?;
+
+        self.data[addr as usize] = match addr {
+            // NOTE: 書き込みは無視する (WARL)
+            CSR_MISA => return Ok(()),
+            _ if CSR_MHPMCOUNTERS.contains(&addr) || CSR_MHPMEVENTS.contains(&addr) => return Ok(()),
+            CSR_MSTATUS => Mstatus::new(self.data[addr as usize]).write(val, self.extensions()).read(),
+            _ if CSR_PMPCFGS.contains(&addr) => self.write_pmpcfg(addr, val),
+            _ if CSR_PMPADDRS.contains(&addr) => self.write_pmpaddr(addr, val),
+            CSR_MEDELEG => val & MEDELEG_MASK,
+            CSR_MIDELEG => val & MIDELEG_MASK,
+            CSR_MIE => val & ALL_INTERRUPTS,
+            CSR_MIP => (self.data[addr as usize] & !MIP_WRITABLE) | (val & MIP_WRITABLE),
+            // NOTE: MODE >= 2 は予約済みなので、Direct (0) か Vectored (1) のみ保持する
+            CSR_MTVEC | CSR_STVEC => val & !0b10,
+            // NOTE: IALIGN = 16 なので、最下位ビットは常に 0
+            CSR_MEPC | CSR_SEPC => val & !1,
+            CSR_SSTATUS => {
+                let val = self.mstatus().write_supervisor(val, self.extensions()).read();
+                self.data[CSR_MSTATUS as usize] = val;
+                return Ok(());
+            },
+            // NOTE: sie は mideleg で委譲された S モードの割り込みのみ書き込み可能
+            CSR_SIE => {
+                let mask = self.data[CSR_MIDELEG as usize] & SUPERVISOR_INTERRUPTS;
+                let mie = self.data[CSR_MIE as usize];
+                self.data[CSR_MIE as usize] = (mie & !mask) | (val & mask);
+                return Ok(());
+            },
+            // NOTE: sip は委譲された SSIP のみ書き込み可能 (STIP, SEIP は読み取り専用)
+            CSR_SIP => {
+                let mask = self.data[CSR_MIDELEG as usize] & SSIP;
+                let mip = self.data[CSR_MIP as usize];
+                self.data[CSR_MIP as usize] = (mip & !mask) | (val & mask);
+                return Ok(());
+            },
+            // NOTE: サポートしていない MODE が書き込まれた場合、書き込み自体を無視する (WARL)
+            CSR_SATP => {
+                if !self.is_supported_satp_mode((val & SATP_MODE) >> 60) {
+                    return Ok(());
+                }
+                val & (SATP_MODE | SATP_ASID | SATP_PPN)
+            },
+            // NOTE: 浮動小数点 CSR への書き込みは、浮動小数点の状態を Dirty にする
+            CSR_FFLAGS | CSR_FRM | CSR_FCSR => {
+                let fcsr = self.data[CSR_FCSR as usize];
+                self.data[CSR_FCSR as usize] = match addr {
+                    CSR_FFLAGS => (fcsr & !FCSR_FFLAGS) | (val & FCSR_FFLAGS),
+                    CSR_FRM => (fcsr & !FCSR_FRM) | ((val << 5) & FCSR_FRM),
+                    _ => val & (FCSR_FRM | FCSR_FFLAGS),
+                };
+                self.mark_fpu_dirty();
+                return Ok(());
+            },
+            // NOTE: menvcfg は ADUE のみ実装している
+            CSR_MENVCFG => val & MENVCFG_ADUE,
+            // NOTE: CY, IR 以外のカウンタは実装していない
+            CSR_MCOUNTINHIBIT => val & (INHIBIT_CY | INHIBIT_IR),
+            _ => val,
+        };
+        Ok(())
+    }
+
+    /// CSR アドレスのエンコーディングと現在の特権モードから、アクセスが許可されているかを確認します。
+    fn check_access(&self, addr: u16, mode: PrivilegeMode, is_write: bool) -> Result<(), Exception> {
+        // NOTE: addr[11:10] = 0b11 なら読み取り専用、addr[9:8] はアクセスに必要な最低の特権モード
+        let is_read_only = (addr >> 10) & 0b11 == 0b11;
+        let required_mode = PrivilegeMode::from_bits((addr >> 8) as u64);
+
+        if !Self::is_implemented(addr)
+            || mode < required_mode
+            || (is_write && is_read_only)
+            || !self.is_counter_enabled(addr, mode)
+            || (Self::is_fpu_csr(addr) && self.mstatus().get(FS) == 0)
+            // NOTE: mstatus.TVM = 1 の場合、S モードからは satp にアクセスできない
+            || (addr == CSR_SATP && mode == PrivilegeMode::Supervisor && self.mstatus().get(TVM) != 0) {
+            return Err(Exception::InvalidCsrAccess(addr));
+        }
+        Ok(())
+    }
+
+    /// CSR が実装されているかを取得します。
+    fn is_implemented(addr: u16) -> bool {
+        matches!(addr,
+            CSR_FFLAGS | CSR_FRM | CSR_FCSR
+            | CSR_MVENDORID | CSR_MARCHID | CSR_MIMPID | CSR_MHARTID | CSR_MCONFIGPTR
+            | CSR_MSTATUS | CSR_MISA | CSR_MEDELEG | CSR_MIDELEG | CSR_MIE | CSR_MTVEC
+            | CSR_MCOUNTEREN | CSR_MENVCFG | CSR_MCOUNTINHIBIT
+            | CSR_MSCRATCH | CSR_MEPC | CSR_MCAUSE | CSR_MTVAL | CSR_MIP
+            | CSR_MCYCLE | CSR_MINSTRET
+            | CSR_SSTATUS | CSR_SIE | CSR_STVEC | CSR_SCOUNTEREN
+            | CSR_SSCRATCH | CSR_SEPC | CSR_SCAUSE | CSR_STVAL | CSR_SIP | CSR_SATP
+            | CSR_CYCLE | CSR_TIME | CSR_INSTRET
+        ) || (CSR_PMPCFGS.contains(&addr) && addr.is_multiple_of(2))
+            || CSR_PMPADDRS.contains(&addr)
+            || CSR_MHPMCOUNTERS.contains(&addr)
+            || CSR_MHPMEVENTS.contains(&addr)
+            || CSR_HPMCOUNTERS.contains(&addr)
+    }
+
+    /// 浮動小数点 CSR かを取得します。(mstatus.FS = Off の場合はアクセスできない)
+    fn is_fpu_csr(addr: u16) -> bool {
+        matches!(addr, CSR_FFLAGS | CSR_FRM | CSR_FCSR)
+    }
+
+    /// satp.MODE がサポートされているかを取得します。
+    fn is_supported_satp_mode(&self, mode: u64) -> bool {
+        mode == SATP_MODE_BARE
+            || TranslationMode::from_bits(mode).is_some_and(|mode| self.translation_modes.contains(&mode))
+    }
+
+    /// satp.MODE に書き込める仮想アドレス変換方式を設定します。(Bare は常に書き込める)
+    pub fn set_translation_modes(&mut self, modes: &[TranslationMode]) {
+        self.translation_modes = modes.to_vec();
+    }
+
+    /// ページテーブルの A / D ビットをハードウェアで更新するかを取得します。(menvcfg.ADUE)
+    pub fn hardware_updates_ad(&self) -> bool {
+        self.data[CSR_MENVCFG as usize] & MENVCFG_ADUE != 0
+    }
+
+    /// ユーザーモードカウンタ (cycle, time, instret, hpmcounterN) へのアクセスが、mcounteren / scounteren によって許可されているかを取得します。
+    fn is_counter_enabled(&self, addr: u16, mode: PrivilegeMode) -> bool {
+        if !(CSR_CYCLE..=0xC1F).contains(&addr) {
+            return true;
         }
-        if addr == CSR_MHARTID || addr == CSR_MISA {
-            return;
+
+        let bit = 1 << (addr - CSR_CYCLE);
+        match mode {
+            PrivilegeMode::Machine => true,
+            PrivilegeMode::Supervisor => self.data[CSR_MCOUNTEREN as usize] & bit != 0,
+            PrivilegeMode::User => self.data[CSR_MCOUNTEREN as usize] & self.data[CSR_SCOUNTEREN as usize] & bit != 0,
         }
-        self.data[addr as usize] = if addr == CSR_MSTATUS {
-            Mstatus::new(self.data[addr as usize]).write(val, mstatus::Extensions {
-                has_fpu: false,
-                has_vector: false,
-                is_rv64: true,
-            }).read()
-        } else {
-            val
-        };
+    }
+
+    /// mcycle と minstret を進めます。
+    pub fn count(&mut self, retired: bool) {
+        let inhibit = self.data[CSR_MCOUNTINHIBIT as usize];
+        if inhibit & INHIBIT_CY == 0 {
+            self.data[CSR_MCYCLE as usize] = self.data[CSR_MCYCLE as usize].wrapping_add(1);
+        }
+        if retired && inhibit & INHIBIT_IR == 0 {
+            self.data[CSR_MINSTRET as usize] = self.data[CSR_MINSTRET as usize].wrapping_add(1);
+        }
+    }
+
+    /// 割り込みコントローラから駆動される mip のビット (MSIP, MTIP, MEIP, SEIP) を設定します。
+    pub fn set_interrupt_lines(&mut self, lines: u64) {
+        let mip = self.data[CSR_MIP as usize];
+        self.data[CSR_MIP as usize] = (mip & MIP_WRITABLE) | (lines & ALL_INTERRUPTS & !MIP_WRITABLE);
+        self.interrupt_lines = lines & ALL_INTERRUPTS;
+    }
+
+    /// mip の値を取得します。
+    ///
+    /// SEIP はソフトウェアから書き込んだビットと、割り込みコントローラからの信号の論理和になります。
+    pub fn mip(&self) -> u64 {
+        self.data[CSR_MIP as usize] | (self.interrupt_lines & SEIP)
+    }
+
+    /// 現在の丸めモード (frm) を取得します。
+    pub fn frm(&self) -> u8 {
+        ((self.data[CSR_FCSR as usize] & FCSR_FRM) >> 5) as u8
+    }
+    /// 浮動小数点演算で発生した例外フラグを fflags に累積します。
+    pub fn accrue_fflags(&mut self, flags: u8) {
+        self.data[CSR_FCSR as usize] |= flags as u64 & FCSR_FFLAGS;
+        self.mark_fpu_dirty();
+    }
+    /// mstatus.FS を Dirty にします。
+    pub fn mark_fpu_dirty(&mut self) {
+        let mut mstatus = self.mstatus();
+        mstatus.set(FS, 0b11);
+        self.set_mstatus(&mstatus);
+    }
+
+    /// CSR レジスタの値を、チェックや WARL 処理を介さずに直接取得します。(トラップ処理用)
+    pub fn get(&self, addr: u16) -> u64 {
+        self.data[addr as usize]
+    }
+    /// CSR レジスタに値を、チェックや WARL 処理を介さずに直接設定します。(トラップ処理用)
+    pub fn set(&mut self, addr: u16, val: u64) {
+        self.data[addr as usize] = val;
+    }
+
+    /// 実装されている拡張機能を取得します。(mstatus の WARL 処理用)
+    fn extensions(&self) -> mstatus::Extensions {
+        mstatus::Extensions {
+            has_fpu: true,
+            has_vector: false,
+            is_rv64: true,
+        }
+    }
+
+    /// mstatus のラッパーを取得します。
+    pub fn mstatus(&self) -> Mstatus {
+        Mstatus::new(self.data[CSR_MSTATUS as usize])
+    }
+    /// mstatus のラッパーの値を書き戻します。
+    pub fn set_mstatus(&mut self, mstatus: &Mstatus) {
+        self.data[CSR_MSTATUS as usize] = mstatus.read();
     }
 }
@@ -0,0 +1,28 @@
+// NOTE: mip / mie のビット配置は共通 (xIP / xIE)
+
+/// Supervisor Software Interrupt
+pub const SSIP: u64 = 1 << 1;
+
+/// Machine Software Interrupt
+pub const MSIP: u64 = 1 << 3;
+
+/// Supervisor Timer Interrupt
+pub const STIP: u64 = 1 << 5;
+
+/// Machine Timer Interrupt
+pub const MTIP: u64 = 1 << 7;
+
+/// Supervisor External Interrupt
+pub const SEIP: u64 = 1 << 9;
+
+/// Machine External Interrupt
+pub const MEIP: u64 = 1 << 11;
+
+/// S モードの割り込み
+pub const SUPERVISOR_INTERRUPTS: u64 = SSIP | STIP | SEIP;
+
+/// 実装されているすべての割り込み
+pub const ALL_INTERRUPTS: u64 = SSIP | MSIP | STIP | MTIP | SEIP | MEIP;
+
+/// mip のうち、M モードのソフトウェアから書き込み可能なビット (それ以外は割り込みコントローラによって駆動される)
+pub const MIP_WRITABLE: u64 = SSIP | STIP | SEIP;
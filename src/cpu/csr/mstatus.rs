@@ -63,6 +63,11 @@ pub const SXL:  u64 = 0b11 << 34;
 /// State Dirty: FS, VS, XS のいずれかが Dirty(11) であることを示す (読み取り専用)
 pub const SD:   u64 = 1 << 63;
 
+// --- Supervisor View (sstatus) ---
+
+/// sstatus から見える mstatus のビット
+pub const SSTATUS_MASK: u64 = SIE | SPIE | SPP | VS | FS | XS | SUM | MXR | UXL | SD;
+
 #[derive(Clone, Copy)]
 pub struct Extensions {
     /// F/D 拡張を持っているか
@@ -86,10 +91,30 @@ impl Mstatus {
     /// mstatus に値を書き込みます。
     pub const fn write(&mut self, val: u64, ext: Extensions) -> &mut Self {
         let mask = self.make_write_mask(ext);
+        self.write_with_mask(val, mask, ext)
+    }
+
+    /// sstatus として値を書き込みます。sstatus から見えないビットは元の値を維持します。
+    pub const fn write_supervisor(&mut self, val: u64, ext: Extensions) -> &mut Self {
+        let mask = self.make_write_mask(ext) & SSTATUS_MASK;
+        self.write_with_mask(val, mask, ext)
+    }
+
+    /// sstatus として値を読み取ります。
+    pub const fn read_supervisor(&self) -> u64 {
+        self.read() & SSTATUS_MASK
+    }
 
+    /// マスクで指定されたビットだけを書き込みます。
+    const fn write_with_mask(&mut self, val: u64, mask: u64, ext: Extensions) -> &mut Self {
         // NOTE: 書き込み可能な部分だけ更新し、残りは元の値を維持
         let mut next_val = (self.raw & !mask) | (val & mask);
 
+        // NOTE: MPP = 0b10 は予約済みなので、その場合は元の値を維持する (WARL)
+        if next_val & MPP == 0b10 << 11 {
+            next_val = (next_val & !MPP) | (self.raw & MPP);
+        }
+
         if ext.is_rv64 {
             // NOTE: UXL = 2 (0b10), SXL = 2 (0b10) -> bits: 10
             next_val = (next_val & !UXL) | (0b10 << 32);
@@ -117,6 +142,17 @@ impl Mstatus {
         val
     }
 
+    /// 指定したフィールド (ビットマスク) の値を、右詰めにして取得します。
+    pub const fn get(&self, field: u64) -> u64 {
+        (self.raw & field) >> field.trailing_zeros()
+    }
+
+    /// 指定したフィールド (ビットマスク) に値を設定します。
+    pub const fn set(&mut self, field: u64, val: u64) -> &mut Self {
+        self.raw = (self.raw & !field) | ((val << field.trailing_zeros()) & field);
+        self
+    }
+
     /// 機能フラグに基づいて「書き込み可能なビット」のマスクを作成します。
     const fn make_write_mask(&self, ext: Extensions) -> u64 {
         // NOTE: 常に書き込み可能な基本ビット
@@ -0,0 +1,144 @@
+use crate::{PrivilegeMode, cpu::{csr::{CSR_PMPADDRS, CSR_PMPCFGS, Csr}, mmu::AccessType}};
+
+// NOTE: pmpcfg は 1 エントリあたり 8bit で、RV64 では偶数番目の pmpcfg がそれぞれ 8 エントリ分を持つ
+
+/// Read: 読み込みを許可する
+pub const R: u64 = 1 << 0;
+
+/// Write: 書き込みを許可する
+pub const W: u64 = 1 << 1;
+
+/// eXecute: 命令フェッチを許可する
+pub const X: u64 = 1 << 2;
+
+/// Address-matching mode: アドレスの一致方式 (00=OFF, 01=TOR, 10=NA4, 11=NAPOT)
+pub const A: u64 = 0b11 << 3;
+
+/// Lock: エントリを変更不可にし、M モードのアクセスにも適用する
+pub const L: u64 = 1 << 7;
+
+/// A = TOR: 直前のエントリの pmpaddr から、このエントリの pmpaddr までの範囲
+const A_TOR: u64 = 0b01 << 3;
+
+/// A = NA4: 4 バイトの範囲
+const A_NA4: u64 = 0b10 << 3;
+
+/// A = NAPOT: 2 の冪乗のサイズの、自然にアラインされた範囲 (下位の連続する 1 のビット数でサイズを表す)
+const A_NAPOT: u64 = 0b11 << 3;
+
+/// pmpaddr の書き込み可能なビット (物理アドレスの 55:2 bit)
+const PMPADDR_MASK: u64 = (1 << 54) - 1;
+
+/// PMP エントリの最大数
+pub const MAX_PMP_ENTRIES: usize = 64;
+
+impl Csr {
+    /// PMP エントリ index の pmpcfg を取得します。
+    fn pmpcfg(&self, index: usize) -> u64 {
+        let csr = *CSR_PMPCFGS.start() as usize + (index / 8) * 2;
+        (self.data[csr] >> ((index % 8) * 8)) & 0xFF
+    }
+
+    /// PMP エントリ index の pmpaddr を取得します。
+    fn pmpaddr(&self, index: usize) -> u64 {
+        self.data[*CSR_PMPADDRS.start() as usize + index]
+    }
+
+    /// PMP エントリ index がロックされているかを取得します。
+    ///
+    /// 次のエントリがロックされた TOR の場合、その範囲の下限となるこのエントリの pmpaddr もロックされます。
+    fn is_pmpaddr_locked(&self, index: usize) -> bool {
+        let next_locked_tor = index + 1 < self.pmp_entries
+            && self.pmpcfg(index + 1) & (L | A) == L | A_TOR;
+        self.pmpcfg(index) & L != 0 || next_locked_tor
+    }
+
+    /// pmpcfg への書き込み値に WARL 処理を適用します。
+    pub(super) fn write_pmpcfg(&self, addr: u16, val: u64) -> u64 {
+        let first = (addr - CSR_PMPCFGS.start()) as usize * 4;
+        (0..8).fold(0, |acc, i| {
+            let index = first + i;
+            let old = self.pmpcfg(index);
+            let new = if index >= self.pmp_entries {
+                // NOTE: 実装されていないエントリは常に 0
+                0
+            } else if old & L != 0 {
+                // NOTE: ロックされたエントリへの書き込みは無視する
+                old
+            } else {
+                let cfg = (val >> (i * 8)) & (R | W | X | A | L);
+                // NOTE: R = 0, W = 1 の組み合わせは予約済みなので、W も 0 にする
+                if cfg & (R | W) == W { cfg & !W } else { cfg }
+            };
+            acc | (new << (i * 8))
+        })
+    }
+
+    /// pmpaddr への書き込み値に WARL 処理を適用します。
+    pub(super) fn write_pmpaddr(&self, addr: u16, val: u64) -> u64 {
+        let index = (addr - CSR_PMPADDRS.start()) as usize;
+        if index >= self.pmp_entries {
+            0
+        } else if self.is_pmpaddr_locked(index) {
+            self.pmpaddr(index)
+        } else {
+            val & PMPADDR_MASK
+        }
+    }
+
+    /// 実装する PMP エントリの数を設定します。(それ以降のエントリの CSR は 0 に固定される)
+    pub fn set_pmp_entries(&mut self, count: usize) {
+        assert!(count <= MAX_PMP_ENTRIES, "PMP エントリは最大 {MAX_PMP_ENTRIES} 個です");
+        self.pmp_entries = count;
+        for index in count..MAX_PMP_ENTRIES {
+            let csr = *CSR_PMPCFGS.start() as usize + (index / 8) * 2;
+            self.data[csr] &= !(0xFF << ((index % 8) * 8));
+            self.data[*CSR_PMPADDRS.start() as usize + index] = 0;
+        }
+    }
+
+    /// 物理アドレス [addr, addr + size) への access が、mode において PMP で許可されているかを取得します。
+    pub fn pmp_permits(&self, addr: u64, size: u64, access: AccessType, mode: PrivilegeMode) -> bool {
+        let end = addr.saturating_add(size);
+        let mut prev = 0;
+        for index in 0..self.pmp_entries {
+            let cfg = self.pmpcfg(index);
+            let pmpaddr = self.pmpaddr(index);
+            let (start, limit) = match cfg & A {
+                A_TOR => (prev << 2, pmpaddr << 2),
+                A_NA4 => (pmpaddr << 2, (pmpaddr << 2) + 4),
+                A_NAPOT => {
+                    let size = 1 << (pmpaddr.trailing_ones() + 3);
+                    let base = (pmpaddr << 2) & !(size - 1);
+                    (base, base + size)
+                },
+                _ => (0, 0),
+            };
+            prev = pmpaddr;
+
+            // NOTE: TOR で pmpaddr[i-1] >= pmpaddr[i] の場合は、どのアドレスにも一致しない
+            if start >= limit {
+                continue;
+            }
+            if !(addr < limit && start < end) {
+                continue;
+            }
+            // NOTE: 最も番号の小さい一致したエントリで決まり、アクセスの一部だけが一致する場合は失敗する
+            if !(start <= addr && end <= limit) {
+                return false;
+            }
+            // NOTE: M モードのアクセスは、ロックされたエントリのみが適用される
+            if mode == PrivilegeMode::Machine && cfg & L == 0 {
+                return true;
+            }
+            return match access {
+                AccessType::Instruction => cfg & X != 0,
+                AccessType::Load => cfg & R != 0,
+                AccessType::Store => cfg & W != 0,
+            };
+        }
+
+        // NOTE: 一致するエントリがない場合、M モードは成功し、S / U モードは PMP が実装されていれば失敗する
+        mode == PrivilegeMode::Machine || self.pmp_entries == 0
+    }
+}
@@ -1,3 +1,6 @@
+// NOTE: オペコードなどは命令フォーマットのフィールド境界に合わせて区切っている
+#![allow(clippy::unusual_byte_groupings)]
+
 use crate::{Exception, Imm, Instruction, RawInstruction, RawShortInstruction, RegIdx, Shamt};
 
 /// 命令をデコードします。
@@ -9,7 +12,7 @@ pub fn decode(instruction: RawInstruction) -> Result<Instruction, Exception> {
     let rs2 = ((instruction >> 20) & 0b1_1111) as RegIdx; // ソースレジスタ2
     let funct7 = (instruction >> 25) & 0b111_1111; // 細分類その2
 
-    Ok(match opcode {
+    match opcode {
         0b01100_11 => match (funct7, funct3) {
             // NOTE: RV32I R-Type
             (0b00000_00, 0b000) => Ok(Instruction::ADD { rd, rs1, rs2 }),
@@ -132,6 +135,185 @@ pub fn decode(instruction: RawInstruction) -> Result<Instruction, Exception> {
             }
         },
 
+        // NOTE: RV32A / RV64A
+        0b01011_11 => {
+            let funct5 = funct7 >> 2;
+            match (funct5, funct3) {
+                // NOTE: RV32A
+                (0b00010, 0b010) if rs2 == 0 => Ok(Instruction::LRW { rd, rs1 }),
+                (0b00011, 0b010) => Ok(Instruction::SCW { rd, rs1, rs2 }),
+                (0b00001, 0b010) => Ok(Instruction::AMOSWAPW { rd, rs1, rs2 }),
+                (0b00000, 0b010) => Ok(Instruction::AMOADDW { rd, rs1, rs2 }),
+                (0b00100, 0b010) => Ok(Instruction::AMOXORW { rd, rs1, rs2 }),
+                (0b01100, 0b010) => Ok(Instruction::AMOANDW { rd, rs1, rs2 }),
+                (0b01000, 0b010) => Ok(Instruction::AMOORW { rd, rs1, rs2 }),
+                (0b10000, 0b010) => Ok(Instruction::AMOMINW { rd, rs1, rs2 }),
+                (0b10100, 0b010) => Ok(Instruction::AMOMAXW { rd, rs1, rs2 }),
+                (0b11000, 0b010) => Ok(Instruction::AMOMINUW { rd, rs1, rs2 }),
+                (0b11100, 0b010) => Ok(Instruction::AMOMAXUW { rd, rs1, rs2 }),
+
+                // NOTE: RV64A
+                (0b00010, 0b011) if rs2 == 0 => Ok(Instruction::LRD { rd, rs1 }),
+                (0b00011, 0b011) => Ok(Instruction::SCD { rd, rs1, rs2 }),
+                (0b00001, 0b011) => Ok(Instruction::AMOSWAPD { rd, rs1, rs2 }),
+                (0b00000, 0b011) => Ok(Instruction::AMOADDD { rd, rs1, rs2 }),
+                (0b00100, 0b011) => Ok(Instruction::AMOXORD { rd, rs1, rs2 }),
+                (0b01100, 0b011) => Ok(Instruction::AMOANDD { rd, rs1, rs2 }),
+                (0b01000, 0b011) => Ok(Instruction::AMOORD { rd, rs1, rs2 }),
+                (0b10000, 0b011) => Ok(Instruction::AMOMIND { rd, rs1, rs2 }),
+                (0b10100, 0b011) => Ok(Instruction::AMOMAXD { rd, rs1, rs2 }),
+                (0b11000, 0b011) => Ok(Instruction::AMOMINUD { rd, rs1, rs2 }),
+                (0b11100, 0b011) => Ok(Instruction::AMOMAXUD { rd, rs1, rs2 }),
+
+                _ => Err(Exception::UnknownInstruction(instruction)),
+            }
+        },
+
+        // NOTE: RV32/64F, D I-Type (メモリ操作)
+        0b00001_11 => {
+            let offset = ((instruction as i32) >> 20) as Imm;
+            match funct3 {
+                0b010 => Ok(Instruction::FLW { rd, rs1, offset }),
+                0b011 => Ok(Instruction::FLD { rd, rs1, offset }),
+
+                _ => Err(Exception::UnknownInstruction(instruction)),
+            }
+        },
+        // NOTE: RV32/64F, D S-Type
+        0b01001_11 => {
+            let imm12 = (((instruction >> 25) & 0x7f) << 5) | ((instruction >> 7) & 0x1f);
+            let offset = (((imm12 as i32) << 20) >> 20) as Imm;
+            match funct3 {
+                0b010 => Ok(Instruction::FSW { rs1, rs2, offset }),
+                0b011 => Ok(Instruction::FSD { rs1, rs2, offset }),
+
+                _ => Err(Exception::UnknownInstruction(instruction)),
+            }
+        },
+        // NOTE: RV32/64F, D R4-Type (funct7 の上位 5bit が rs3、下位 2bit が fmt)
+        0b10000_11 => {
+            let rs3 = (funct7 >> 2) as RegIdx;
+            let rm = funct3 as u8;
+            match funct7 & 0b11 {
+                0b00 => Ok(Instruction::FMADDS { rd, rs1, rs2, rs3, rm }),
+                0b01 => Ok(Instruction::FMADDD { rd, rs1, rs2, rs3, rm }),
+
+                _ => Err(Exception::UnknownInstruction(instruction)),
+            }
+        },
+        0b10001_11 => {
+            let rs3 = (funct7 >> 2) as RegIdx;
+            let rm = funct3 as u8;
+            match funct7 & 0b11 {
+                0b00 => Ok(Instruction::FMSUBS { rd, rs1, rs2, rs3, rm }),
+                0b01 => Ok(Instruction::FMSUBD { rd, rs1, rs2, rs3, rm }),
+
+                _ => Err(Exception::UnknownInstruction(instruction)),
+            }
+        },
+        0b10010_11 => {
+            let rs3 = (funct7 >> 2) as RegIdx;
+            let rm = funct3 as u8;
+            match funct7 & 0b11 {
+                0b00 => Ok(Instruction::FNMSUBS { rd, rs1, rs2, rs3, rm }),
+                0b01 => Ok(Instruction::FNMSUBD { rd, rs1, rs2, rs3, rm }),
+
+                _ => Err(Exception::UnknownInstruction(instruction)),
+            }
+        },
+        0b10011_11 => {
+            let rs3 = (funct7 >> 2) as RegIdx;
+            let rm = funct3 as u8;
+            match funct7 & 0b11 {
+                0b00 => Ok(Instruction::FNMADDS { rd, rs1, rs2, rs3, rm }),
+                0b01 => Ok(Instruction::FNMADDD { rd, rs1, rs2, rs3, rm }),
+
+                _ => Err(Exception::UnknownInstruction(instruction)),
+            }
+        },
+        // NOTE: RV32/64F, D R-Type (funct3 は丸めモードか、細分類として使われる)
+        0b10100_11 => {
+            let rm = funct3 as u8;
+            match (funct7, rs2, funct3) {
+                // NOTE: RV32F
+                (0b00000_00, _, _) => Ok(Instruction::FADDS { rd, rs1, rs2, rm }),
+                (0b00001_00, _, _) => Ok(Instruction::FSUBS { rd, rs1, rs2, rm }),
+                (0b00010_00, _, _) => Ok(Instruction::FMULS { rd, rs1, rs2, rm }),
+                (0b00011_00, _, _) => Ok(Instruction::FDIVS { rd, rs1, rs2, rm }),
+                (0b01011_00, 0, _) => Ok(Instruction::FSQRTS { rd, rs1, rm }),
+                (0b00100_00, _, 0b000) => Ok(Instruction::FSGNJS { rd, rs1, rs2 }),
+                (0b00100_00, _, 0b001) => Ok(Instruction::FSGNJNS { rd, rs1, rs2 }),
+                (0b00100_00, _, 0b010) => Ok(Instruction::FSGNJXS { rd, rs1, rs2 }),
+                (0b00101_00, _, 0b000) => Ok(Instruction::FMINS { rd, rs1, rs2 }),
+                (0b00101_00, _, 0b001) => Ok(Instruction::FMAXS { rd, rs1, rs2 }),
+                (0b11000_00, 0, _) => Ok(Instruction::FCVTWS { rd, rs1, rm }),
+                (0b11000_00, 1, _) => Ok(Instruction::FCVTWUS { rd, rs1, rm }),
+                (0b11100_00, 0, 0b000) => Ok(Instruction::FMVXW { rd, rs1 }),
+                (0b10100_00, _, 0b010) => Ok(Instruction::FEQS { rd, rs1, rs2 }),
+                (0b10100_00, _, 0b001) => Ok(Instruction::FLTS { rd, rs1, rs2 }),
+                (0b10100_00, _, 0b000) => Ok(Instruction::FLES { rd, rs1, rs2 }),
+                (0b11100_00, 0, 0b001) => Ok(Instruction::FCLASSS { rd, rs1 }),
+                (0b11010_00, 0, _) => Ok(Instruction::FCVTSW { rd, rs1, rm }),
+                (0b11010_00, 1, _) => Ok(Instruction::FCVTSWU { rd, rs1, rm }),
+                (0b11110_00, 0, 0b000) => Ok(Instruction::FMVWX { rd, rs1 }),
+
+                // NOTE: RV64F
+                (0b11000_00, 2, _) => Ok(Instruction::FCVTLS { rd, rs1, rm }),
+                (0b11000_00, 3, _) => Ok(Instruction::FCVTLUS { rd, rs1, rm }),
+                (0b11010_00, 2, _) => Ok(Instruction::FCVTSL { rd, rs1, rm }),
+                (0b11010_00, 3, _) => Ok(Instruction::FCVTSLU { rd, rs1, rm }),
+
+                // NOTE: RV32D
+                (0b00000_01, _, _) => Ok(Instruction::FADDD { rd, rs1, rs2, rm }),
+                (0b00001_01, _, _) => Ok(Instruction::FSUBD { rd, rs1, rs2, rm }),
+                (0b00010_01, _, _) => Ok(Instruction::FMULD { rd, rs1, rs2, rm }),
+                (0b00011_01, _, _) => Ok(Instruction::FDIVD { rd, rs1, rs2, rm }),
+                (0b01011_01, 0, _) => Ok(Instruction::FSQRTD { rd, rs1, rm }),
+                (0b00100_01, _, 0b000) => Ok(Instruction::FSGNJD { rd, rs1, rs2 }),
+                (0b00100_01, _, 0b001) => Ok(Instruction::FSGNJND { rd, rs1, rs2 }),
+                (0b00100_01, _, 0b010) => Ok(Instruction::FSGNJXD { rd, rs1, rs2 }),
+                (0b00101_01, _, 0b000) => Ok(Instruction::FMIND { rd, rs1, rs2 }),
+                (0b00101_01, _, 0b001) => Ok(Instruction::FMAXD { rd, rs1, rs2 }),
+                (0b01000_00, 1, _) => Ok(Instruction::FCVTSD { rd, rs1, rm }),
+                (0b01000_01, 0, _) => Ok(Instruction::FCVTDS { rd, rs1, rm }),
+                (0b10100_01, _, 0b010) => Ok(Instruction::FEQD { rd, rs1, rs2 }),
+                (0b10100_01, _, 0b001) => Ok(Instruction::FLTD { rd, rs1, rs2 }),
+                (0b10100_01, _, 0b000) => Ok(Instruction::FLED { rd, rs1, rs2 }),
+                (0b11100_01, 0, 0b001) => Ok(Instruction::FCLASSD { rd, rs1 }),
+                (0b11000_01, 0, _) => Ok(Instruction::FCVTWD { rd, rs1, rm }),
+                (0b11000_01, 1, _) => Ok(Instruction::FCVTWUD { rd, rs1, rm }),
+                (0b11010_01, 0, _) => Ok(Instruction::FCVTDW { rd, rs1, rm }),
+                (0b11010_01, 1, _) => Ok(Instruction::FCVTDWU { rd, rs1, rm }),
+
+                // NOTE: RV64D
+                (0b11000_01, 2, _) => Ok(Instruction::FCVTLD { rd, rs1, rm }),
+                (0b11000_01, 3, _) => Ok(Instruction::FCVTLUD { rd, rs1, rm }),
+                (0b11100_01, 0, 0b000) => Ok(Instruction::FMVXD { rd, rs1 }),
+                (0b11010_01, 2, _) => Ok(Instruction::FCVTDL { rd, rs1, rm }),
+                (0b11010_01, 3, _) => Ok(Instruction::FCVTDLU { rd, rs1, rm }),
+                (0b11110_01, 0, 0b000) => Ok(Instruction::FMVDX { rd, rs1 }),
+
+                _ => Err(Exception::UnknownInstruction(instruction)),
+            }
+        },
+
+        // NOTE: RV32I MISC-MEM / Zifencei (rd, rs1 は将来のために予約されており、無視する)
+        0b00011_11 => match funct3 {
+            0b000 => {
+                let fm = ((instruction >> 28) & 0b1111) as u8;
+                let pred = ((instruction >> 24) & 0b1111) as u8;
+                let succ = ((instruction >> 20) & 0b1111) as u8;
+                Ok(match (fm, pred, succ) {
+                    (0b1000, 0b0011, 0b0011) => Instruction::FENCETSO,
+                    (0b0000, 0b0001, 0b0000) if rd == 0 && rs1 == 0 => Instruction::PAUSE,
+                    _ => Instruction::FENCE { pred, succ, fm },
+                })
+            },
+            0b001 => Ok(Instruction::FENCEI),
+
+            _ => Err(Exception::UnknownInstruction(instruction)),
+        },
+
         // NOTE: RV32I B-Type
         0b11000_11 => {
             let imm12 = (instruction >> 31) & 1;
@@ -186,9 +368,16 @@ pub fn decode(instruction: RawInstruction) -> Result<Instruction, Exception> {
             let csr = ((instruction >> 20) & 0b1111_1111_1111) as u16;
 
             match funct3 {
-                0b000 => match csr {
-                    0b00000_00_00000 => Ok(Instruction::ECALL),
-                    0b00000_00_00001 => Ok(Instruction::EBREAK),
+                0b000 => match (csr, rs1, rd) {
+                    (0b00000_00_00000, 0, 0) => Ok(Instruction::ECALL),
+                    (0b00000_00_00001, 0, 0) => Ok(Instruction::EBREAK),
+
+                    // NOTE: 特権命令
+                    (0b00110_00_00010, 0, 0) => Ok(Instruction::MRET),
+                    (0b00010_00_00010, 0, 0) => Ok(Instruction::SRET),
+                    (0b00010_00_00101, 0, 0) => Ok(Instruction::WFI),
+                    // NOTE: SFENCE.VMA は csr フィールドの下位 5bit が rs2
+                    (csr, rs1, 0) if csr >> 5 == 0b0001001 => Ok(Instruction::SFENCEVMA { rs1, rs2: (csr & 0b11111) as RegIdx }),
 
                     _ => Err(Exception::UnknownInstruction(instruction)),
                 },
@@ -205,7 +394,7 @@ pub fn decode(instruction: RawInstruction) -> Result<Instruction, Exception> {
         },
 
         _ => Err(Exception::UnknownInstruction(instruction)),
-    }?)
+    }
 }
 
 /// 圧縮命令をデコードします。
@@ -218,22 +407,29 @@ pub fn decode_compressed(instruction: RawShortInstruction) -> Result<Instruction
     // NOTE: 5bit レジスタをそのまま使うクロージャ
     let as_register = |x: u16| (x & 0b1_1111) as RegIdx;
 
-    Ok(match opcode {
+    match opcode {
         0b00 => match funct3 {
             // NOTE: C.ADDI4SPN (addi rd', x2, nzuimm)
             0b000 => {
                 let rd = to_register(instruction >> 2);
-                if rd == 0 { return Err(Exception::UnknownInstruction(instruction as RawInstruction)); }
                 // NOTE: nzuimm[5:4|9:6|2|3] (12-5 bit)
                 let nzuimm = ((instruction >> 7) & 0b11_0000)
                     | ((instruction >> 1) & 0b11_1100_0000)
                     | ((instruction >> 4) & 0b100)
                     | ((instruction >> 2) & 0b1000);
+                // NOTE: nzuimm = 0 は予約済み (0x0000 は不正命令として定義されている)
+                if nzuimm == 0 { return Err(Exception::UnknownInstruction(instruction as RawInstruction)); }
                 Ok(Instruction::ADDI { rd, rs1: 2, imm: nzuimm as Imm })
             },
-            // NOTE: C.FLD
-            // TODO: Phase 7 (RV64F/D) で実装
-            0b001 => Err(Exception::UnknownInstruction(instruction as RawInstruction)),
+            // NOTE: C.FLD (fld rd', offset(rs1'))
+            0b001 => {
+                let rd = to_register(instruction >> 2);
+                let rs1 = to_register(instruction >> 7);
+                // NOTE: uimm[5:3|7:6] * 8
+                let uimm = ((instruction >> 7) & 0b11_1000)
+                    | ((instruction << 1) & 0b1100_0000);
+                Ok(Instruction::FLD { rd, rs1, offset: uimm as Imm })
+            },
             // NOTE: C.LW (lw rd', offset(rs1'))
             0b010 => {
                 let rd = to_register(instruction >> 2);
@@ -254,9 +450,15 @@ pub fn decode_compressed(instruction: RawShortInstruction) -> Result<Instruction
                     | ((instruction << 1) & 0b1100_0000);
                 Ok(Instruction::LD { rd, rs1, offset: uimm as Imm })
             },
-            // NOTE: C.FSD
-            // TODO: Phase 7 (RV64F/D) で実装
-            0b101 => Err(Exception::UnknownInstruction(instruction as RawInstruction)),
+            // NOTE: C.FSD (fsd rs2', offset(rs1'))
+            0b101 => {
+                let rs2 = to_register(instruction >> 2);
+                let rs1 = to_register(instruction >> 7);
+                // NOTE: uimm[5:3|7:6] * 8
+                let uimm = ((instruction >> 7) & 0b11_1000)
+                    | ((instruction << 1) & 0b1100_0000);
+                Ok(Instruction::FSD { rs1, rs2, offset: uimm as Imm })
+            },
             // NOTE: C.SW (sw rs2', offset(rs1'))
             0b110 => {
                 let rs2 = to_register(instruction >> 2);
@@ -432,9 +634,15 @@ pub fn decode_compressed(instruction: RawShortInstruction) -> Result<Instruction
                     | ((instruction >> 2) & 0b1_1111);
                 Ok(Instruction::SLLI { rd, rs1: rd, shamt: shamt as Shamt })
             },
-            // NOTE: C.FLDSP
-            // TODO: Phase 7
-            0b001 => Err(Exception::UnknownInstruction(instruction as RawInstruction)),
+            // NOTE: C.FLDSP (fld rd, offset(x2)) (C.LDSP と異なり、rd = 0 も有効)
+            0b001 => {
+                let rd = as_register(instruction >> 7);
+                // NOTE: uimm[5|4:3|8:6] * 8
+                let uimm = ((instruction >> 7) & 0b10_0000)
+                    | ((instruction >> 2) & 0b01_1000)
+                    | ((instruction << 4) & 0b1_1100_0000);
+                Ok(Instruction::FLD { rd, rs1: 2, offset: uimm as Imm })
+            },
             // NOTE: C.LWSP (lw rd, offset(x2))
             0b010 => {
                 let rd = as_register(instruction >> 7);
@@ -486,9 +694,14 @@ pub fn decode_compressed(instruction: RawShortInstruction) -> Result<Instruction
                     }
                 }
             },
-            // NOTE: C.FSDSP
-            // TODO: Phase 7
-            0b101 => Err(Exception::UnknownInstruction(instruction as RawInstruction)),
+            // NOTE: C.FSDSP (fsd rs2, offset(x2))
+            0b101 => {
+                let rs2 = as_register(instruction >> 2);
+                // NOTE: uimm[5:3|8:6] * 8
+                let uimm = ((instruction >> 7) & 0b11_1000)
+                    | ((instruction >> 1) & 0b1_1100_0000);
+                Ok(Instruction::FSD { rs1: 2, rs2, offset: uimm as Imm })
+            },
             // NOTE: C.SWSP (sw rs2, offset(x2))
             0b110 => {
                 let rs2 = as_register(instruction >> 2);
@@ -510,5 +723,5 @@ pub fn decode_compressed(instruction: RawShortInstruction) -> Result<Instruction
         },
 
         _ => Err(Exception::UnknownInstruction(instruction as RawInstruction)), // NOTE: opcode = 11 は 32 bit 命令
-    }?)
+    }
 }
@@ -0,0 +1,706 @@
+use crate::{Exception, Imm, RawInstruction, RegIdx, cpu::{Cpu, csr::mstatus::FS, mmu::AccessType, sign_extend}};
+
+// --- Exception Flags (fflags) ---
+
+/// Inexact: 丸めによって結果が不正確になった
+pub const NX: u8 = 1 << 0;
+
+/// Underflow: 結果が非正規化数の範囲まで小さくなり、かつ不正確になった
+pub const UF: u8 = 1 << 1;
+
+/// Overflow: 結果が表現可能な最大値を超えた
+pub const OF: u8 = 1 << 2;
+
+/// Divide by Zero: 有限の値を 0 で割った
+pub const DZ: u8 = 1 << 3;
+
+/// Invalid Operation: 無効な演算 (signaling NaN の入力、∞ - ∞、0 × ∞ など)
+pub const NV: u8 = 1 << 4;
+
+/// 丸めモード
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// RNE: 最近接偶数丸め
+    NearestEven,
+    /// RTZ: 0 方向への丸め
+    TowardZero,
+    /// RDN: 負の無限大方向への丸め
+    Down,
+    /// RUP: 正の無限大方向への丸め
+    Up,
+    /// RMM: 最近接丸め (同値の場合は絶対値が大きい方)
+    NearestMaxMagnitude,
+}
+impl RoundingMode {
+    /// 命令の rm フィールドや frm の値から丸めモードを取得します。(予約済みの値や DYN は None)
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0b000 => Some(RoundingMode::NearestEven),
+            0b001 => Some(RoundingMode::TowardZero),
+            0b010 => Some(RoundingMode::Down),
+            0b011 => Some(RoundingMode::Up),
+            0b100 => Some(RoundingMode::NearestMaxMagnitude),
+            _ => None,
+        }
+    }
+}
+
+/// rm フィールドの DYN (frm の丸めモードを使う)
+const RM_DYNAMIC: u8 = 0b111;
+
+// NOTE: ホストの浮動小数点演算では丸めモードを指定できず、例外フラグも取得できないので、
+//       四則演算などは仮数を整数として正確に計算してから、指定された丸めモードで丸める
+
+/// f32 / f64 を共通に扱うためのトレイト
+pub trait Float: Copy + PartialEq + PartialOrd {
+    /// ビット幅
+    const BITS: u32;
+    /// 仮数部のビット数 (暗黙の 1 を含まない)
+    const MANTISSA_BITS: u32;
+    /// RISC-V の正規化された NaN (Canonical NaN)
+    const CANONICAL_NAN: u64;
+
+    fn from_raw(raw: u64) -> Self;
+    fn to_raw(self) -> u64;
+    fn is_nan(self) -> bool;
+    fn is_infinite(self) -> bool;
+    fn is_sign_negative(self) -> bool;
+    fn is_subnormal(self) -> bool;
+
+    /// 浮動小数点レジスタの値から読み出します。(NaN Boxing されていない値は Canonical NaN として扱う)
+    fn unbox(raw: u64) -> Self {
+        if Self::BITS == 64 || raw >> Self::BITS == u64::MAX >> Self::BITS {
+            Self::from_raw(raw & Self::mask())
+        } else {
+            Self::from_raw(Self::CANONICAL_NAN)
+        }
+    }
+    /// 浮動小数点レジスタに格納する値に変換します。(64bit 未満の値は上位ビットを 1 で埋める)
+    fn boxed(self) -> u64 {
+        self.to_raw() | !Self::mask()
+    }
+    /// ビット幅分のマスク
+    fn mask() -> u64 {
+        u64::MAX >> (64 - Self::BITS)
+    }
+    /// 符号ビット
+    fn sign_bit() -> u64 {
+        1 << (Self::BITS - 1)
+    }
+    /// 指数部のバイアス
+    fn bias() -> i32 {
+        (1 << (Self::BITS - Self::MANTISSA_BITS - 2)) - 1
+    }
+    /// signaling NaN かどうか (仮数部の最上位ビット = quiet ビットが 0 の NaN)
+    fn is_signaling(self) -> bool {
+        let quiet_bit = 1 << Self::CANONICAL_NAN.trailing_zeros();
+        self.is_nan() && self.to_raw() & quiet_bit == 0
+    }
+    /// ±0 かどうか
+    fn is_zero(self) -> bool {
+        self.to_raw() & !Self::sign_bit() == 0
+    }
+    /// 符号を反転した値
+    fn negate(self) -> Self {
+        Self::from_raw(self.to_raw() ^ Self::sign_bit())
+    }
+
+    /// Canonical NaN
+    fn nan() -> Self {
+        Self::from_raw(Self::CANONICAL_NAN)
+    }
+    /// 指定された符号の 0
+    fn zero(negative: bool) -> Self {
+        Self::from_raw(if negative { Self::sign_bit() } else { 0 })
+    }
+    /// 指定された符号の無限大
+    fn infinity(negative: bool) -> Self {
+        let exponent = (Self::mask() >> 1) & !((1 << Self::MANTISSA_BITS) - 1);
+        Self::from_raw(Self::zero(negative).to_raw() | exponent)
+    }
+    /// 指定された符号の最大の有限値
+    fn max_finite(negative: bool) -> Self {
+        Self::from_raw(Self::infinity(negative).to_raw() - 1)
+    }
+
+    /// 有限の値を、符号・指数・仮数 (値 = (-1)^sign × sig × 2^exp) に分解します。
+    fn unpack(self) -> (bool, i32, u128) {
+        let raw = self.to_raw();
+        let biased = ((raw & (Self::mask() >> 1)) >> Self::MANTISSA_BITS) as i32;
+        let mantissa = (raw & ((1 << Self::MANTISSA_BITS) - 1)) as u128;
+        let (exp, sig) = if biased == 0 {
+            // NOTE: 非正規化数は暗黙の 1 を持たず、指数は最小の正規化数と同じ
+            (1, mantissa)
+        } else {
+            (biased, mantissa | (1 << Self::MANTISSA_BITS))
+        };
+        (self.is_sign_negative(), exp - Self::bias() - Self::MANTISSA_BITS as i32, sig)
+    }
+}
+
+macro_rules! impl_float {
+    ($t:ty, $bits:ty, $mantissa:expr, $nan:expr) => {
+        impl Float for $t {
+            const BITS: u32 = <$bits>::BITS;
+            const MANTISSA_BITS: u32 = $mantissa;
+            const CANONICAL_NAN: u64 = $nan;
+
+            fn from_raw(raw: u64) -> Self { <$t>::from_bits(raw as $bits) }
+            fn to_raw(self) -> u64 { self.to_bits() as u64 }
+            fn is_nan(self) -> bool { <$t>::is_nan(self) }
+            fn is_infinite(self) -> bool { <$t>::is_infinite(self) }
+            fn is_sign_negative(self) -> bool { <$t>::is_sign_negative(self) }
+            fn is_subnormal(self) -> bool { <$t>::is_subnormal(self) }
+        }
+    };
+}
+impl_float!(f32, u32, 23, 0x7fc0_0000);
+impl_float!(f64, u64, 52, 0x7ff8_0000_0000_0000);
+
+/// 演算結果と、その演算で発生した例外フラグ
+pub type FloatResult<F> = (F, u8);
+
+/// 結果が NaN になる場合の処理: 結果を Canonical NaN にし、signaling NaN が入力された場合は NV を立てます。
+fn nan_result<F: Float, G: Float>(inputs: &[G]) -> FloatResult<F> {
+    (F::nan(), if inputs.iter().any(|v| v.is_signaling()) { NV } else { 0 })
+}
+
+/// 正確な結果が 0 になった場合の符号: 同じ符号の 0 同士の和はその符号、それ以外は RDN で -0、その他の丸めモードで +0 になります。
+fn exact_zero<F: Float>(a_negative: bool, b_negative: bool, rm: RoundingMode) -> F {
+    F::zero(if a_negative == b_negative { a_negative } else { rm == RoundingMode::Down })
+}
+
+/// 値の最上位ビットの位置 + 1
+fn bit_length(val: u128) -> i32 {
+    (u128::BITS - val.leading_zeros()) as i32
+}
+
+/// 右シフトし、シフトで失われたビットがあれば最下位ビットに 1 を立てます。(sticky ビット)
+fn shift_right_jam(val: u128, shift: i32) -> u128 {
+    match shift {
+        ..=0 => val,
+        128.. => (val != 0) as u128,
+        _ => (val >> shift) | (val & ((1 << shift) - 1) != 0) as u128,
+    }
+}
+
+/// 仮数 sig の下位 shift ビットを丸めモードに従って丸め、丸めた仮数と、不正確だったかどうかを返します。
+fn round_bits(sig: u128, shift: i32, negative: bool, rm: RoundingMode) -> (u128, bool) {
+    if shift <= 0 {
+        return (sig << -shift, false);
+    }
+
+    // NOTE: 128 ビット以上のシフトでは、残りは常に 0.5 ulp 未満
+    let (kept, rest, half) = if shift >= 128 {
+        (0, sig, u128::MAX)
+    } else {
+        (sig >> shift, sig & ((1 << shift) - 1), 1 << (shift - 1))
+    };
+    if rest == 0 {
+        return (kept, false);
+    }
+
+    let round_up = match rm {
+        RoundingMode::NearestEven => rest > half || (rest == half && kept & 1 == 1),
+        RoundingMode::NearestMaxMagnitude => rest >= half,
+        RoundingMode::TowardZero => false,
+        RoundingMode::Down => negative,
+        RoundingMode::Up => !negative,
+    };
+    (kept + round_up as u128, true)
+}
+
+/// 正確な値 (-1)^negative × sig × 2^exp を丸めて F に変換し、発生した例外フラグとともに返します。
+///
+/// sig の最下位ビットは、それより下に 0 でないビットがあることを示す sticky ビットであってもよいですが、
+/// その場合は丸める位置より 2 ビット以上下になければなりません。
+fn round_pack<F: Float>(negative: bool, exp: i32, sig: u128, rm: RoundingMode) -> FloatResult<F> {
+    if sig == 0 {
+        return (F::zero(negative), 0);
+    }
+
+    let precision = F::MANTISSA_BITS as i32 + 1;
+    let emin = 1 - F::bias();
+    // NOTE: 最上位ビットの指数
+    let msb = exp + bit_length(sig) - 1;
+
+    // NOTE: RISC-V では、アンダーフローは指数範囲を無制限として丸めた後の値で判定する
+    let tiny = msb < emin - 1 || (msb == emin - 1 && {
+        let (kept, _) = round_bits(sig, msb - (precision - 1) - exp, negative, rm);
+        kept >> precision == 0
+    });
+
+    // NOTE: 非正規化数の範囲では、最下位ビットの指数は emin - (precision - 1) で固定される
+    let mut lsb = msb.max(emin) - (precision - 1);
+    let (mut kept, inexact) = round_bits(sig, lsb - exp, negative, rm);
+    if kept >> precision != 0 {
+        // NOTE: 丸めによる桁上がり
+        kept >>= 1;
+        lsb += 1;
+    }
+
+    let biased = if kept >> (precision - 1) == 0 {
+        0
+    } else {
+        let exponent = lsb + precision - 1;
+        if exponent > F::bias() {
+            return overflow(negative, rm);
+        }
+        (exponent + F::bias()) as u64
+    };
+    let raw = F::zero(negative).to_raw()
+        | (biased << F::MANTISSA_BITS)
+        | (kept as u64 & ((1 << F::MANTISSA_BITS) - 1));
+
+    let flags = match (inexact, tiny) {
+        (false, _) => 0,
+        (true, false) => NX,
+        (true, true) => NX | UF,
+    };
+    (F::from_raw(raw), flags)
+}
+
+/// オーバーフローした結果を、丸めモードに従って求めます。
+fn overflow<F: Float>(negative: bool, rm: RoundingMode) -> FloatResult<F> {
+    // NOTE: 0 方向や、符号と逆方向への丸めでは、無限大ではなく最大の有限値になる
+    let saturate = match rm {
+        RoundingMode::NearestEven | RoundingMode::NearestMaxMagnitude => false,
+        RoundingMode::TowardZero => true,
+        RoundingMode::Down => !negative,
+        RoundingMode::Up => negative,
+    };
+    let val = if saturate { F::max_finite(negative) } else { F::infinity(negative) };
+    (val, OF | NX)
+}
+
+/// 2 つの値 (-1)^sign × sig × 2^exp の正確な和を求め、丸めます。
+fn add_exact<F: Float>(a: (bool, i32, u128), b: (bool, i32, u128), rm: RoundingMode) -> FloatResult<F> {
+    // NOTE: 指数の大きい方を左シフトし、足りない分は小さい方を sticky ビット付きで右シフトして揃える
+    let (x, y) = if a.1 >= b.1 { (a, b) } else { (b, a) };
+    let diff = x.1 - y.1;
+    let headroom = 125 - bit_length(x.2);
+    let shift = diff.min(headroom);
+    let exp = x.1 - shift;
+    let x_sig = (x.2 << shift) as i128;
+    let y_sig = shift_right_jam(y.2, diff - shift) as i128;
+
+    let sum = if x.0 { -x_sig } else { x_sig } + if y.0 { -y_sig } else { y_sig };
+    if sum == 0 {
+        return (exact_zero::<F>(a.0, b.0, rm), 0);
+    }
+    round_pack(sum < 0, exp, sum.unsigned_abs(), rm)
+}
+
+/// 加算
+pub fn add<F: Float>(a: F, b: F, rm: RoundingMode) -> FloatResult<F> {
+    if a.is_nan() || b.is_nan() {
+        return nan_result(&[a, b]);
+    }
+    match (a.is_infinite(), b.is_infinite()) {
+        // NOTE: ∞ - ∞ は無効な演算
+        (true, true) if a.is_sign_negative() != b.is_sign_negative() => return (F::nan(), NV),
+        (true, _) => return (a, 0),
+        (_, true) => return (b, 0),
+        _ => {},
+    }
+    if a.is_zero() && b.is_zero() {
+        return (exact_zero(a.is_sign_negative(), b.is_sign_negative(), rm), 0);
+    }
+    add_exact(a.unpack(), b.unpack(), rm)
+}
+
+/// 減算
+pub fn sub<F: Float>(a: F, b: F, rm: RoundingMode) -> FloatResult<F> {
+    if b.is_nan() {
+        return nan_result(&[a, b]);
+    }
+    add(a, b.negate(), rm)
+}
+
+/// 乗算
+pub fn mul<F: Float>(a: F, b: F, rm: RoundingMode) -> FloatResult<F> {
+    if a.is_nan() || b.is_nan() {
+        return nan_result(&[a, b]);
+    }
+    let negative = a.is_sign_negative() != b.is_sign_negative();
+    if a.is_infinite() || b.is_infinite() {
+        // NOTE: ∞ × 0 は無効な演算
+        return if a.is_zero() || b.is_zero() { (F::nan(), NV) } else { (F::infinity(negative), 0) };
+    }
+
+    let (_, a_exp, a_sig) = a.unpack();
+    let (_, b_exp, b_sig) = b.unpack();
+    round_pack(negative, a_exp + b_exp, a_sig * b_sig, rm)
+}
+
+/// 除算
+pub fn div<F: Float>(a: F, b: F, rm: RoundingMode) -> FloatResult<F> {
+    if a.is_nan() || b.is_nan() {
+        return nan_result(&[a, b]);
+    }
+    let negative = a.is_sign_negative() != b.is_sign_negative();
+    match (a.is_infinite(), b.is_infinite()) {
+        // NOTE: ∞ / ∞ は無効な演算
+        (true, true) => return (F::nan(), NV),
+        (true, false) => return (F::infinity(negative), 0),
+        (false, true) => return (F::zero(negative), 0),
+        _ => {},
+    }
+    match (a.is_zero(), b.is_zero()) {
+        // NOTE: 0 / 0 は無効な演算、0 以外の有限の値を 0 で割ると DZ
+        (true, true) => return (F::nan(), NV),
+        (false, true) => return (F::infinity(negative), DZ),
+        (true, false) => return (F::zero(negative), 0),
+        _ => {},
+    }
+
+    // NOTE: 被除数を左に寄せてから割ることで、商の精度を十分に確保する (余りは sticky ビットにする)
+    let (_, a_exp, a_sig) = a.unpack();
+    let (_, b_exp, b_sig) = b.unpack();
+    let shift = 126 - bit_length(a_sig);
+    let dividend = a_sig << shift;
+    let quotient = (dividend / b_sig) | !dividend.is_multiple_of(b_sig) as u128;
+    round_pack(negative, a_exp - shift - b_exp, quotient, rm)
+}
+
+/// 平方根
+pub fn sqrt<F: Float>(a: F, rm: RoundingMode) -> FloatResult<F> {
+    if a.is_nan() {
+        return nan_result(&[a]);
+    }
+    if a.is_zero() {
+        return (a, 0);
+    }
+    // NOTE: 負の数の平方根は無効な演算
+    if a.is_sign_negative() {
+        return (F::nan(), NV);
+    }
+    if a.is_infinite() {
+        return (a, 0);
+    }
+
+    // NOTE: 指数が偶数になるように、仮数を左に寄せてから整数の平方根を求める
+    let (_, exp, sig) = a.unpack();
+    let mut shift = 124 - bit_length(sig);
+    if (exp - shift) % 2 != 0 {
+        shift += 1;
+    }
+    let radicand = sig << shift;
+    let root = radicand.isqrt();
+    round_pack(false, (exp - shift) / 2, root | (root * root != radicand) as u128, rm)
+}
+
+/// 積和演算 (a × b + c) を 1 回の丸めで計算します。
+pub fn mul_add<F: Float>(a: F, b: F, c: F, rm: RoundingMode) -> FloatResult<F> {
+    // NOTE: ∞ × 0 は、加数が quiet NaN であっても無効な演算
+    if (a.is_infinite() && b.is_zero()) || (a.is_zero() && b.is_infinite()) {
+        return (F::nan(), NV);
+    }
+    if a.is_nan() || b.is_nan() || c.is_nan() {
+        return nan_result(&[a, b, c]);
+    }
+
+    let product_negative = a.is_sign_negative() != b.is_sign_negative();
+    if a.is_infinite() || b.is_infinite() {
+        // NOTE: ∞ + (-∞) は無効な演算
+        return if c.is_infinite() && c.is_sign_negative() != product_negative {
+            (F::nan(), NV)
+        } else {
+            (F::infinity(product_negative), 0)
+        };
+    }
+    if c.is_infinite() {
+        return (c, 0);
+    }
+    if a.is_zero() || b.is_zero() {
+        return if c.is_zero() { (exact_zero(product_negative, c.is_sign_negative(), rm), 0) } else { (c, 0) };
+    }
+
+    let (_, a_exp, a_sig) = a.unpack();
+    let (_, b_exp, b_sig) = b.unpack();
+    let product = (product_negative, a_exp + b_exp, a_sig * b_sig);
+    if c.is_zero() {
+        return round_pack(product.0, product.1, product.2, rm);
+    }
+    add_exact(product, c.unpack(), rm)
+}
+
+/// 最小値 (どちらかが NaN の場合はもう一方、-0 は +0 より小さいものとして扱う)
+pub fn min<F: Float>(a: F, b: F) -> FloatResult<F> {
+    min_max(a, b, true)
+}
+
+/// 最大値 (どちらかが NaN の場合はもう一方、+0 は -0 より大きいものとして扱う)
+pub fn max<F: Float>(a: F, b: F) -> FloatResult<F> {
+    min_max(a, b, false)
+}
+
+fn min_max<F: Float>(a: F, b: F, is_min: bool) -> FloatResult<F> {
+    let flags = if a.is_signaling() || b.is_signaling() { NV } else { 0 };
+    let r = match (a.is_nan(), b.is_nan()) {
+        (true, true) => F::nan(),
+        (true, false) => b,
+        (false, true) => a,
+        _ if a.is_zero() && b.is_zero() => if is_min == a.is_sign_negative() { a } else { b },
+        _ => if (a < b) == is_min { a } else { b },
+    };
+    (r, flags)
+}
+
+/// 等価比較 (quiet: signaling NaN の場合のみ NV を立てる)
+pub fn eq<F: Float>(a: F, b: F) -> (bool, u8) {
+    (a == b, if a.is_signaling() || b.is_signaling() { NV } else { 0 })
+}
+
+/// 小なり比較 (signaling: NaN の場合は NV を立てる)
+pub fn lt<F: Float>(a: F, b: F) -> (bool, u8) {
+    (a < b, if a.is_nan() || b.is_nan() { NV } else { 0 })
+}
+
+/// 小なりイコール比較 (signaling: NaN の場合は NV を立てる)
+pub fn le<F: Float>(a: F, b: F) -> (bool, u8) {
+    (a <= b, if a.is_nan() || b.is_nan() { NV } else { 0 })
+}
+
+/// FCLASS: 値の種類を表すビットを取得します。
+pub fn classify<F: Float>(a: F) -> u64 {
+    let negative = a.is_sign_negative();
+    let bit = if a.is_nan() {
+        if a.is_signaling() { 8 } else { 9 }
+    } else if a.is_infinite() {
+        if negative { 0 } else { 7 }
+    } else if a.is_zero() {
+        if negative { 3 } else { 4 }
+    } else if a.is_subnormal() {
+        if negative { 2 } else { 5 }
+    } else if negative {
+        1
+    } else {
+        6
+    };
+    1 << bit
+}
+
+/// 浮動小数点数を、指定された範囲 (min..=max) の整数に変換します。
+///
+/// NaN と範囲外の値は飽和させ、NV を立てます。(NaN は最大値になる)
+pub fn to_int<F: Float>(a: F, rm: RoundingMode, min: i128, max: i128) -> (i128, u8) {
+    let negative = a.is_sign_negative();
+    let saturated = if negative && !a.is_nan() { min } else { max };
+    if a.is_nan() || a.is_infinite() {
+        return (saturated, NV);
+    }
+
+    let (_, exp, sig) = a.unpack();
+    // NOTE: 2^64 以上の値は、どの変換でも範囲外
+    if exp + bit_length(sig) > 64 {
+        return (saturated, NV);
+    }
+    let (magnitude, inexact) = round_bits(sig, -exp, negative, rm);
+    let int = if negative { -(magnitude as i128) } else { magnitude as i128 };
+    if int < min || int > max {
+        return (saturated, NV);
+    }
+    (int, if inexact { NX } else { 0 })
+}
+
+/// 整数を浮動小数点数に変換します。
+pub fn from_int<F: Float>(val: i128, rm: RoundingMode) -> FloatResult<F> {
+    round_pack(val < 0, 0, val.unsigned_abs(), rm)
+}
+
+/// 浮動小数点数の精度を変換します。
+pub fn convert<F: Float, G: Float>(a: F, rm: RoundingMode) -> FloatResult<G> {
+    if a.is_nan() {
+        return nan_result(&[a]);
+    }
+    if a.is_infinite() {
+        return (G::infinity(a.is_sign_negative()), 0);
+    }
+    let (negative, exp, sig) = a.unpack();
+    round_pack(negative, exp, sig, rm)
+}
+
+impl Cpu {
+    /// 浮動小数点レジスタから値を読み出します。
+    fn read_float<F: Float>(&self, index: RegIdx) -> F {
+        F::unbox(self.fregisters[index as usize])
+    }
+    /// 浮動小数点レジスタに値を書き込みます。
+    fn write_float<F: Float>(&mut self, index: RegIdx, value: F) {
+        self.write_fregister(index, value.boxed());
+    }
+
+    /// 浮動小数点ユニットが有効か (mstatus.FS != Off) を確認します。
+    fn check_fpu(&self, raw: RawInstruction) -> Result<(), Exception> {
+        if self.csr.mstatus().get(FS) == 0 {
+            return Err(Exception::UnknownInstruction(raw));
+        }
+        Ok(())
+    }
+
+    /// 命令の rm フィールドから丸めモードを取得します。(DYN の場合は frm を使う)
+    fn rounding_mode(&self, rm: u8, raw: RawInstruction) -> Result<RoundingMode, Exception> {
+        let bits = if rm == RM_DYNAMIC { self.csr.frm() } else { rm };
+        RoundingMode::from_bits(bits).ok_or(Exception::UnknownInstruction(raw))
+    }
+
+    /// 演算で発生した例外フラグを fflags に累積します。
+    fn accrue(&mut self, flags: u8) {
+        if flags != 0 {
+            self.csr.accrue_fflags(flags);
+        }
+    }
+
+    /// 浮動小数点 Load 命令用ヘルパー: アドレスを読み込み、NaN Boxing して浮動小数点レジスタに書き込みます。
+    #[inline(always)]
+    pub(super) fn op_fp_load(&mut self, rd: RegIdx, rs1: RegIdx, offset: Imm, width: u64, raw: RawInstruction) -> Result<(), Exception> {
+        self.check_fpu(raw)?;
+        let addr = self.read_register(rs1).wrapping_add(offset as u64);
+        let val = self.read_memory(addr, width, AccessType::Load)?;
+        let boxed = if width == 4 { f32::from_raw(val).boxed() } else { val };
+        self.write_fregister(rd, boxed);
+        Ok(())
+    }
+
+    /// 浮動小数点 Store 命令用ヘルパー: 浮動小数点レジスタの下位 width バイトをそのまま書き込みます。
+    #[inline(always)]
+    pub(super) fn op_fp_store(&mut self, rs1: RegIdx, rs2: RegIdx, offset: Imm, width: u64, raw: RawInstruction) -> Result<(), Exception> {
+        self.check_fpu(raw)?;
+        let addr = self.read_register(rs1).wrapping_add(offset as u64);
+        self.write_memory(addr, self.fregisters[rs2 as usize], width)?;
+        self.invalidate_reservation(addr, width);
+        Ok(())
+    }
+
+    /// 浮動小数点 2 オペランド演算用ヘルパー: rs1 と rs2 に op を適用して rd に書き込み、例外フラグを累積します。
+    #[inline(always)]
+    pub(super) fn op_fp_rr<F, Op>(&mut self, rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rm: u8, raw: RawInstruction, op: Op) -> Result<(), Exception>
+    where
+        F: Float,
+        Op: FnOnce(F, F, RoundingMode) -> FloatResult<F>,
+    {
+        self.check_fpu(raw)?;
+        let rm = self.rounding_mode(rm, raw)?;
+        let (res, flags) = op(self.read_float(rs1), self.read_float(rs2), rm);
+        self.write_float(rd, res);
+        self.accrue(flags);
+        Ok(())
+    }
+
+    /// 浮動小数点 3 オペランド演算 (R4-Type) 用ヘルパー
+    #[inline(always)]
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn op_fp_r4<F, Op>(&mut self, rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rs3: RegIdx, rm: u8, raw: RawInstruction, op: Op) -> Result<(), Exception>
+    where
+        F: Float,
+        Op: FnOnce(F, F, F, RoundingMode) -> FloatResult<F>,
+    {
+        self.check_fpu(raw)?;
+        let rm = self.rounding_mode(rm, raw)?;
+        let (res, flags) = op(self.read_float(rs1), self.read_float(rs2), self.read_float(rs3), rm);
+        self.write_float(rd, res);
+        self.accrue(flags);
+        Ok(())
+    }
+
+    /// 浮動小数点 1 オペランド演算用ヘルパー: 平方根や精度の変換など、rs1 を F として読み出し、結果を G として rd に書き込みます。
+    #[inline(always)]
+    pub(super) fn op_fp_unary<F, G, Op>(&mut self, rd: RegIdx, rs1: RegIdx, rm: u8, raw: RawInstruction, op: Op) -> Result<(), Exception>
+    where
+        F: Float,
+        G: Float,
+        Op: FnOnce(F, RoundingMode) -> FloatResult<G>,
+    {
+        self.check_fpu(raw)?;
+        let rm = self.rounding_mode(rm, raw)?;
+        let (res, flags) = op(self.read_float(rs1), rm);
+        self.write_float(rd, res);
+        self.accrue(flags);
+        Ok(())
+    }
+
+    /// 符号注入命令用ヘルパー: rs1 の符号以外のビットと、op が rs1, rs2 の符号ビットから求めた符号を組み合わせて rd に書き込みます。
+    #[inline(always)]
+    pub(super) fn op_fp_sign<F, Op>(&mut self, rd: RegIdx, rs1: RegIdx, rs2: RegIdx, raw: RawInstruction, op: Op) -> Result<(), Exception>
+    where
+        F: Float,
+        Op: FnOnce(u64, u64) -> u64,
+    {
+        self.check_fpu(raw)?;
+        let sign_bit = F::sign_bit();
+        let val1 = self.read_float::<F>(rs1).to_raw();
+        let val2 = self.read_float::<F>(rs2).to_raw();
+        let sign = op(val1 & sign_bit, val2 & sign_bit) & sign_bit;
+        self.write_float(rd, F::from_raw((val1 & !sign_bit) | sign));
+        Ok(())
+    }
+
+    /// 浮動小数点比較命令用ヘルパー: 比較結果 (0 / 1) を整数レジスタ rd に書き込みます。
+    #[inline(always)]
+    pub(super) fn op_fp_compare<F, Op>(&mut self, rd: RegIdx, rs1: RegIdx, rs2: RegIdx, raw: RawInstruction, op: Op) -> Result<(), Exception>
+    where
+        F: Float,
+        Op: FnOnce(F, F) -> (bool, u8),
+    {
+        self.check_fpu(raw)?;
+        let (res, flags) = op(self.read_float(rs1), self.read_float(rs2));
+        self.write_register(rd, res as u64);
+        self.accrue(flags);
+        Ok(())
+    }
+
+    /// FCLASS 命令用ヘルパー
+    #[inline(always)]
+    pub(super) fn op_fp_classify<F: Float>(&mut self, rd: RegIdx, rs1: RegIdx, raw: RawInstruction) -> Result<(), Exception> {
+        self.check_fpu(raw)?;
+        self.write_register(rd, classify(self.read_float::<F>(rs1)));
+        Ok(())
+    }
+
+    /// 浮動小数点数から整数への変換命令用ヘルパー: 結果を width バイトの整数として符号拡張し、整数レジスタ rd に書き込みます。
+    #[inline(always)]
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn op_fp_to_int<F: Float>(&mut self, rd: RegIdx, rs1: RegIdx, rm: u8, raw: RawInstruction, min: i128, max: i128, width: u64) -> Result<(), Exception> {
+        self.check_fpu(raw)?;
+        let rm = self.rounding_mode(rm, raw)?;
+        let (res, flags) = to_int(self.read_float::<F>(rs1), rm, min, max);
+        // NOTE: W / WU の結果は、符号なしであっても 32bit 値を符号拡張する
+        self.write_register(rd, sign_extend(res as u64, width));
+        self.accrue(flags);
+        Ok(())
+    }
+
+    /// 整数から浮動小数点数への変換命令用ヘルパー: 整数レジスタ rs1 の値を extend で解釈して変換します。
+    #[inline(always)]
+    pub(super) fn op_fp_from_int<F, Ext>(&mut self, rd: RegIdx, rs1: RegIdx, rm: u8, raw: RawInstruction, extend: Ext) -> Result<(), Exception>
+    where
+        F: Float,
+        Ext: FnOnce(u64) -> i128,
+    {
+        self.check_fpu(raw)?;
+        let rm = self.rounding_mode(rm, raw)?;
+        let (res, flags) = from_int::<F>(extend(self.read_register(rs1)), rm);
+        self.write_float(rd, res);
+        self.accrue(flags);
+        Ok(())
+    }
+
+    /// FMV.X.W / FMV.X.D 命令用ヘルパー: 浮動小数点レジスタの下位 width バイトを、そのまま符号拡張して整数レジスタに書き込みます。
+    #[inline(always)]
+    pub(super) fn op_fp_move_to_int(&mut self, rd: RegIdx, rs1: RegIdx, width: u64, raw: RawInstruction) -> Result<(), Exception> {
+        self.check_fpu(raw)?;
+        self.write_register(rd, sign_extend(self.fregisters[rs1 as usize], width));
+        Ok(())
+    }
+
+    /// FMV.W.X / FMV.D.X 命令用ヘルパー: 整数レジスタの下位 width バイトを、そのまま (NaN Boxing して) 浮動小数点レジスタに書き込みます。
+    #[inline(always)]
+    pub(super) fn op_fp_move_from_int(&mut self, rd: RegIdx, rs1: RegIdx, width: u64, raw: RawInstruction) -> Result<(), Exception> {
+        self.check_fpu(raw)?;
+        let val = self.read_register(rs1);
+        let boxed = if width == 4 { f32::from_raw(val).boxed() } else { val };
+        self.write_fregister(rd, boxed);
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{Exception, PrivilegeMode, TranslationMode, cpu::{Cpu, csr::{CSR_SATP, SATP_ASID, SATP_MODE, SATP_PPN, mstatus::{MPP, MPRV, MXR, SUM}}, tlb::{Tlb, TlbEntry}}};
+
+/// ページサイズ
+const PAGE_SIZE: u64 = 4096;
+/// ページオフセットのビット数
+const PAGE_SHIFT: u64 = 12;
+/// 各レベルの VPN のビット数
+const VPN_BITS: u64 = 9;
+/// PTE のサイズ (バイト)
+const PTE_SIZE: u64 = 8;
+
+/// PTE の Valid ビット
+const PTE_V: u64 = 1 << 0;
+/// PTE の Read ビット
+const PTE_R: u64 = 1 << 1;
+/// PTE の Write ビット
+const PTE_W: u64 = 1 << 2;
+/// PTE の eXecute ビット
+const PTE_X: u64 = 1 << 3;
+/// PTE の User ビット
+const PTE_U: u64 = 1 << 4;
+/// PTE の Global ビット
+const PTE_G: u64 = 1 << 5;
+/// PTE の Accessed ビット
+const PTE_A: u64 = 1 << 6;
+/// PTE の Dirty ビット
+const PTE_D: u64 = 1 << 7;
+/// PTE の PPN フィールド (44bit)
+const PTE_PPN: u64 = ((1 << 44) - 1) << 10;
+/// PTE の予約済みビット (Svpbmt, Svnapot は実装していないので、PBMT / N も予約済みとして扱う)
+const PTE_RESERVED: u64 = !((1 << 54) - 1);
+
+/// メモリアクセスの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessType {
+    /// 命令フェッチ
+    Instruction,
+    /// ロード
+    Load,
+    /// ストア (AMO を含む)
+    Store,
+}
+impl AccessType {
+    /// アクセスの種類に対応するページフォールト例外を作成します。
+    fn page_fault(self, addr: u64) -> Exception {
+        match self {
+            AccessType::Instruction => Exception::InstructionPageFault(addr),
+            AccessType::Load => Exception::LoadPageFault(addr),
+            AccessType::Store => Exception::StorePageFault(addr),
+        }
+    }
+
+    /// アクセスの種類に対応するアクセスフォールト例外を作成します。
+    fn access_fault(self, addr: u64) -> Exception {
+        match self {
+            AccessType::Instruction => Exception::InstructionAccessFault(addr),
+            AccessType::Load => Exception::LoadAccessFault(addr),
+            AccessType::Store => Exception::StoreAccessFault(addr),
+        }
+    }
+}
+
+impl Cpu {
+    /// 仮想アドレスから width バイトを読み込みます。
+    pub(super) fn read_memory(&mut self, addr: u64, width: u64, access: AccessType) -> Result<u64, Exception> {
+        let (first, second) = self.translate_range(addr, width, access)?;
+        let Some((second, split)) = second else {
+            return self.bus.read(first, width).map_err(|_| access.access_fault(addr));
+        };
+
+        // NOTE: ページ境界をまたぐアクセスは、それぞれのページの物理アドレスから 1 バイトずつ読み込む
+        let mut val = 0;
+        for i in 0..width {
+            let (paddr, vaddr) = if i < split { (first + i, addr + i) } else { (second + i - split, addr + i) };
+            val |= self.bus.read(paddr, 1).map_err(|_| access.access_fault(vaddr))? << (i * 8);
+        }
+        Ok(val)
+    }
+
+    /// 仮想アドレスに width バイトを書き込みます。
+    pub(super) fn write_memory(&mut self, addr: u64, val: u64, width: u64) -> Result<(), Exception> {
+        let (first, second) = self.translate_range(addr, width, AccessType::Store)?;
+        let Some((second, split)) = second else {
+            return self.bus.write(first, val, width).map_err(|_| Exception::StoreAccessFault(addr));
+        };
+
+        for i in 0..width {
+            let (paddr, vaddr) = if i < split { (first + i, addr + i) } else { (second + i - split, addr + i) };
+            self.bus.write(paddr, val >> (i * 8), 1).map_err(|_| Exception::StoreAccessFault(vaddr))?;
+        }
+        Ok(())
+    }
+
+    /// [addr, addr + width) を物理アドレスに変換します。
+    ///
+    /// ページ境界をまたぐ場合は、後半のページの物理アドレスと、前半のページに含まれるバイト数も返します。
+    /// NOTE: 後半のページでフォールトした場合に前半だけが書き込まれないよう、アクセスの前に両方のページを変換しておく
+    fn translate_range(&mut self, addr: u64, width: u64, access: AccessType) -> Result<(u64, Option<(u64, u64)>), Exception> {
+        let split = PAGE_SIZE - (addr % PAGE_SIZE);
+        if width <= split {
+            return Ok((self.translate(addr, width, access)?, None));
+        }
+        let first = self.translate(addr, split, access)?;
+        let second = self.translate(addr.wrapping_add(split), width - split, access)?;
+        Ok((first, Some((second, split))))
+    }
+
+    /// 仮想アドレス [addr, addr + width) を物理アドレスに変換し、PMP によってアクセスが許可されているかを確認します。
+    ///
+    /// NOTE: [addr, addr + width) は 1 つのページに収まっていなければならない
+    pub(super) fn translate(&mut self, addr: u64, width: u64, access: AccessType) -> Result<u64, Exception> {
+        let paddr = self.translate_page(addr, access)?;
+        if !self.csr.pmp_permits(paddr, width, access, self.effective_mode(access)) {
+            return Err(access.access_fault(addr));
+        }
+        Ok(paddr)
+    }
+
+    /// 仮想アドレスを物理アドレスに変換します。
+    fn translate_page(&mut self, addr: u64, access: AccessType) -> Result<u64, Exception> {
+        let mode = self.effective_mode(access);
+        let satp = self.csr.get(CSR_SATP);
+        let Some(translation) = TranslationMode::from_bits((satp & SATP_MODE) >> 60) else {
+            return Ok(addr);
+        };
+        // NOTE: M モードではアドレス変換を行わない
+        if mode == PrivilegeMode::Machine {
+            return Ok(addr);
+        }
+
+        let vpn = addr >> PAGE_SHIFT;
+        let asid = (satp & SATP_ASID) >> 44;
+        if let Some(entry) = self.tlb(access).lookup(vpn, asid) {
+            // NOTE: 権限は特権モードや mstatus によって変わるので、ヒットしても毎回確認する
+            if !self.is_permitted(entry.pte, access, mode) {
+                self.tlb(access).record(true);
+                return Err(access.page_fault(addr));
+            }
+            // NOTE: A / D ビットを立てる必要がある場合は、ページテーブルをたどり直す (ミスとして数える)
+            if entry.pte & Self::required_ad(access) == Self::required_ad(access) {
+                self.tlb(access).record(true);
+                return Ok((entry.ppn << PAGE_SHIFT) | (addr & (PAGE_SIZE - 1)));
+            }
+        }
+
+        self.tlb(access).record(false);
+        let entry = self.walk(addr, access, mode, translation.levels(), (satp & SATP_PPN) * PAGE_SIZE, asid)?;
+        self.tlb(access).insert(entry);
+        Ok((entry.ppn << PAGE_SHIFT) | (addr & (PAGE_SIZE - 1)))
+    }
+
+    /// SFENCE.VMA: addr を含むページ、asid のアドレス空間の TLB のエントリを破棄します。(None ならすべてが対象)
+    pub(super) fn flush_tlb(&mut self, addr: Option<u64>, asid: Option<u64>) {
+        let vpn = addr.map(|addr| addr >> PAGE_SHIFT);
+        self.itlb.flush(vpn, asid);
+        self.dtlb.flush(vpn, asid);
+    }
+
+    /// アクセスの種類に対応する TLB を取得します。
+    fn tlb(&mut self, access: AccessType) -> &mut Tlb {
+        if access == AccessType::Instruction { &mut self.itlb } else { &mut self.dtlb }
+    }
+
+    /// アクセスの種類に応じて、リーフ PTE に立っている必要がある A / D ビットを取得します。
+    fn required_ad(access: AccessType) -> u64 {
+        if access == AccessType::Store { PTE_A | PTE_D } else { PTE_A }
+    }
+
+    /// メモリアクセスの権限チェックに用いる特権モードを取得します。
+    ///
+    /// M モードで mstatus.MPRV = 1 の場合、ロード・ストアは MPP のモードで行われます。(命令フェッチは影響を受けない)
+    fn effective_mode(&self, access: AccessType) -> PrivilegeMode {
+        let mstatus = self.csr.mstatus();
+        if access != AccessType::Instruction && self.mode == PrivilegeMode::Machine && mstatus.get(MPRV) != 0 {
+            PrivilegeMode::from_bits(mstatus.get(MPP))
+        } else {
+            self.mode
+        }
+    }
+
+    /// ページテーブルをたどり、仮想アドレスを変換した結果を TLB のエントリとして返します。
+    fn walk(&mut self, addr: u64, access: AccessType, mode: PrivilegeMode, levels: u64, root: u64, asid: u64) -> Result<TlbEntry, Exception> {
+        // NOTE: 仮想アドレスの上位ビットは、変換に使われる最上位ビットの符号拡張でなければならない
+        let unused_bits = 64 - (PAGE_SHIFT + VPN_BITS * levels);
+        if (((addr << unused_bits) as i64) >> unused_bits) as u64 != addr {
+            return Err(access.page_fault(addr));
+        }
+
+        let mut table = root;
+        // NOTE: 非リーフ PTE の G ビットが立っていれば、それ以下のマッピングはすべてグローバル
+        let mut global = false;
+        for level in (0..levels).rev() {
+            let vpn = (addr >> (PAGE_SHIFT + VPN_BITS * level)) & ((1 << VPN_BITS) - 1);
+            let pte_addr = table + vpn * PTE_SIZE;
+            // NOTE: PTE の読み込みに失敗した場合は、元のアクセスのアクセスフォールトになる (PMP は S モードのアクセスとして確認する)
+            if !self.csr.pmp_permits(pte_addr, PTE_SIZE, AccessType::Load, PrivilegeMode::Supervisor) {
+                return Err(access.access_fault(addr));
+            }
+            let pte = self.bus.read(pte_addr, PTE_SIZE).map_err(|_| access.access_fault(addr))?;
+
+            if pte & PTE_V == 0 || (pte & PTE_R == 0 && pte & PTE_W != 0) || pte & PTE_RESERVED != 0 {
+                return Err(access.page_fault(addr));
+            }
+            let ppn = (pte & PTE_PPN) >> 10;
+            global |= pte & PTE_G != 0;
+
+            // NOTE: R = X = 0 なら次のレベルのページテーブルへのポインタ
+            if pte & (PTE_R | PTE_X) == 0 {
+                if level == 0 {
+                    return Err(access.page_fault(addr));
+                }
+                table = ppn * PAGE_SIZE;
+                continue;
+            }
+
+            // NOTE: スーパーページの場合、PPN の下位ビットは 0 でなければならない (ミスアラインなスーパーページ)
+            let superpage_mask = (1 << (VPN_BITS * level)) - 1;
+            if ppn & superpage_mask != 0 || !self.is_permitted(pte, access, mode) {
+                return Err(access.page_fault(addr));
+            }
+
+            let updated = pte | Self::required_ad(access);
+            if updated != pte {
+                // NOTE: menvcfg.ADUE = 1 ならハードウェアで A / D ビットを更新し、そうでなければページフォールトでソフトウェアに任せる
+                if !self.csr.hardware_updates_ad() {
+                    return Err(access.page_fault(addr));
+                }
+                if !self.csr.pmp_permits(pte_addr, PTE_SIZE, AccessType::Store, PrivilegeMode::Supervisor) {
+                    return Err(access.access_fault(addr));
+                }
+                self.bus.write(pte_addr, updated, PTE_SIZE).map_err(|_| access.access_fault(addr))?;
+            }
+
+            let vpn = addr >> PAGE_SHIFT;
+            return Ok(TlbEntry { vpn, ppn: ppn | (vpn & superpage_mask), pte: updated, level, asid, global });
+        }
+        unreachable!()
+    }
+
+    /// リーフ PTE の権限で、指定されたアクセスが許可されているかを取得します。
+    fn is_permitted(&self, pte: u64, access: AccessType, mode: PrivilegeMode) -> bool {
+        let mstatus = self.csr.mstatus();
+        let is_user_page = pte & PTE_U != 0;
+        match mode {
+            // NOTE: U モードは U = 1 のページにのみアクセスできる
+            PrivilegeMode::User if !is_user_page => return false,
+            // NOTE: S モードは U = 1 のページの命令を実行できず、ロード・ストアも SUM = 1 の場合のみ許可される
+            PrivilegeMode::Supervisor if is_user_page
+                && (access == AccessType::Instruction || mstatus.get(SUM) == 0) => return false,
+            _ => {},
+        }
+
+        match access {
+            AccessType::Instruction => pte & PTE_X != 0,
+            // NOTE: MXR = 1 なら、実行可能なページも読み込める
+            AccessType::Load => pte & PTE_R != 0 || (mstatus.get(MXR) != 0 && pte & PTE_X != 0),
+            AccessType::Store => pte & PTE_W != 0,
+        }
+    }
+}
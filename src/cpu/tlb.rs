@@ -0,0 +1,100 @@
+/// TLB のエントリ数
+const TLB_ENTRIES: usize = 64;
+/// 各レベルの VPN のビット数
+const VPN_BITS: u64 = 9;
+
+/// TLB のエントリ (ページテーブルをたどった結果)
+///
+/// NOTE: スーパーページも 4KiB のページ単位で登録する
+#[derive(Debug, Clone, Copy)]
+pub struct TlbEntry {
+    /// 仮想ページ番号 (4KiB 単位)
+    pub vpn: u64,
+    /// 物理ページ番号 (4KiB 単位)
+    pub ppn: u64,
+    /// リーフ PTE (権限や A / D ビットの確認に使う)
+    pub pte: u64,
+    /// リーフ PTE のレベル (0 なら 4KiB ページ、1 以上はスーパーページ)
+    pub level: u64,
+    /// ASID
+    pub asid: u64,
+    /// すべてのアドレス空間で共有されるマッピング (G ビット) か
+    pub global: bool,
+}
+impl TlbEntry {
+    /// エントリのページが、仮想ページ番号 vpn を含むかを取得します。
+    fn contains(&self, vpn: u64) -> bool {
+        let shift = VPN_BITS * self.level;
+        self.vpn >> shift == vpn >> shift
+    }
+}
+
+/// TLB のヒット・ミス回数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TlbStats {
+    /// ヒットした回数
+    pub hits: u64,
+    /// ミスした (ページテーブルをたどった) 回数
+    pub misses: u64,
+}
+
+/// TLB (ダイレクトマップ方式)
+pub struct Tlb {
+    /// エントリ (仮想ページ番号の下位ビットで索引する)
+    entries: [Option<TlbEntry>; TLB_ENTRIES],
+    /// ヒット・ミス回数
+    stats: TlbStats,
+}
+impl Tlb {
+    /// 空の TLB を作成します。
+    pub fn new() -> Self {
+        Self {
+            entries: [None; TLB_ENTRIES],
+            stats: TlbStats::default(),
+        }
+    }
+
+    /// 仮想ページ番号 vpn のエントリを検索します。
+    ///
+    /// NOTE: 見つかったエントリを変換に使えるとは限らないので、ヒット・ミス回数は呼び出し元が record で記録する
+    pub fn lookup(&self, vpn: u64, asid: u64) -> Option<TlbEntry> {
+        self.entries[Self::index(vpn)].filter(|entry| entry.vpn == vpn && (entry.global || entry.asid == asid))
+    }
+
+    /// 変換にエントリを使えたか (hit) を、ヒット・ミス回数に記録します。
+    pub fn record(&mut self, hit: bool) {
+        if hit {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+    }
+
+    /// エントリを登録します。(同じ位置にあるエントリは追い出される)
+    pub fn insert(&mut self, entry: TlbEntry) {
+        self.entries[Self::index(entry.vpn)] = Some(entry);
+    }
+
+    /// SFENCE.VMA: vpn を含むページ、asid のアドレス空間のエントリを破棄します。(None ならすべてが対象)
+    ///
+    /// ASID を指定した場合、グローバルなマッピングは破棄されません。
+    pub fn flush(&mut self, vpn: Option<u64>, asid: Option<u64>) {
+        for slot in self.entries.iter_mut() {
+            if let Some(entry) = slot
+                && vpn.is_none_or(|vpn| entry.contains(vpn))
+                && asid.is_none_or(|asid| !entry.global && entry.asid == asid) {
+                *slot = None;
+            }
+        }
+    }
+
+    /// ヒット・ミス回数を取得します。
+    pub fn stats(&self) -> TlbStats {
+        self.stats
+    }
+
+    /// 仮想ページ番号に対応するエントリの位置を取得します。
+    fn index(vpn: u64) -> usize {
+        vpn as usize % TLB_ENTRIES
+    }
+}
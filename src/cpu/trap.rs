@@ -0,0 +1,204 @@
+use crate::{Exception, PrivilegeMode, cpu::{Cpu, csr::{CSR_MCAUSE, CSR_MEDELEG, CSR_MEPC, CSR_MIDELEG, CSR_MIE, CSR_MTVAL, CSR_MTVEC, CSR_SCAUSE, CSR_SEPC, CSR_STVAL, CSR_STVEC, mstatus::{MIE, MPIE, MPP, MPRV, SIE, SPIE, SPP}}}};
+
+/// mcause の Interrupt ビット
+const INTERRUPT_BIT: u64 = 1 << 63;
+
+/// 割り込み要因 (値は mcause の Exception Code であり、mip / mie のビット位置でもある)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    SupervisorSoftware = 1,
+    MachineSoftware = 3,
+    SupervisorTimer = 5,
+    MachineTimer = 7,
+    SupervisorExternal = 9,
+    MachineExternal = 11,
+}
+impl Interrupt {
+    /// 優先度の高い順に並べた割り込み要因
+    const PRIORITY: [Interrupt; 6] = [
+        Interrupt::MachineExternal,
+        Interrupt::MachineSoftware,
+        Interrupt::MachineTimer,
+        Interrupt::SupervisorExternal,
+        Interrupt::SupervisorSoftware,
+        Interrupt::SupervisorTimer,
+    ];
+
+    /// mcause に書き込む例外コード (Exception Code) を取得します。
+    pub const fn code(self) -> u64 {
+        self as u64
+    }
+
+    /// 保留中の割り込みビットの中から、最も優先度の高い割り込みを選びます。
+    fn highest_priority(pending: u64) -> Option<Interrupt> {
+        Self::PRIORITY.into_iter().find(|i| pending & (1 << i.code()) != 0)
+    }
+}
+
+impl Exception {
+    /// mcause に書き込む例外コード (Exception Code) を取得します。
+    pub fn code(&self) -> u64 {
+        match self {
+            Exception::InstructionAddressMisaligned(_) => 0,
+            Exception::InstructionAccessFault(_) => 1,
+            Exception::UnknownInstruction(_) | Exception::InvalidCsrAccess(_) => 2,
+            Exception::Breakpoint(_) => 3,
+            Exception::LoadAddressMisaligned(_) => 4,
+            Exception::LoadAccessFault(_) => 5,
+            Exception::StoreAddressMisaligned(_) => 6,
+            Exception::StoreAccessFault(_) => 7,
+            Exception::EnvironmentCallFromUMode => 8,
+            Exception::EnvironmentCallFromSMode => 9,
+            Exception::EnvironmentCallFromMMode => 11,
+            Exception::InstructionPageFault(_) => 12,
+            Exception::LoadPageFault(_) => 13,
+            Exception::StorePageFault(_) => 15,
+        }
+    }
+
+    /// mtval に書き込む値 (フォールトしたアドレスや命令) を取得します。
+    pub fn value(&self) -> u64 {
+        match self {
+            Exception::UnknownInstruction(instruction) => *instruction as u64,
+            Exception::InstructionAddressMisaligned(addr)
+            | Exception::InstructionAccessFault(addr)
+            | Exception::Breakpoint(addr)
+            | Exception::LoadAddressMisaligned(addr)
+            | Exception::LoadAccessFault(addr)
+            | Exception::StoreAddressMisaligned(addr)
+            | Exception::StoreAccessFault(addr)
+            | Exception::InstructionPageFault(addr)
+            | Exception::LoadPageFault(addr)
+            | Exception::StorePageFault(addr) => *addr,
+            Exception::InvalidCsrAccess(_)
+            | Exception::EnvironmentCallFromUMode
+            | Exception::EnvironmentCallFromSMode
+            | Exception::EnvironmentCallFromMMode => 0,
+        }
+    }
+}
+
+impl Cpu {
+    /// 例外をトラップとして処理し、トラップハンドラへジャンプします。
+    pub(super) fn handle_exception(&mut self, exception: Exception) {
+        self.take_trap(exception.code(), exception.value(), false);
+    }
+
+    /// 割り込みをトラップとして処理し、トラップハンドラへジャンプします。
+    pub(super) fn handle_interrupt(&mut self, interrupt: Interrupt) {
+        self.take_trap(interrupt.code(), 0, true);
+    }
+
+    /// 現在受け付け可能な割り込みのうち、最も優先度の高いものを取得します。
+    pub(super) fn pending_interrupt(&self) -> Option<Interrupt> {
+        let pending = self.csr.mip() & self.csr.get(CSR_MIE);
+        if pending == 0 {
+            return None;
+        }
+
+        let mideleg = self.csr.get(CSR_MIDELEG);
+        let mstatus = self.csr.mstatus();
+
+        // NOTE: M モードへの割り込みは、M モード未満にいるか、M モードで MIE = 1 の場合に受け付ける
+        let machine_enabled = self.mode < PrivilegeMode::Machine || mstatus.get(MIE) != 0;
+        // NOTE: S モードへ委譲された割り込みは、S モード未満にいるか、S モードで SIE = 1 の場合に受け付ける (M モードでは受け付けない)
+        let supervisor_enabled = self.mode < PrivilegeMode::Supervisor
+            || (self.mode == PrivilegeMode::Supervisor && mstatus.get(SIE) != 0);
+
+        // NOTE: M モードへの割り込みは、S モードへの割り込みより優先される
+        let machine_pending = if machine_enabled { pending & !mideleg } else { 0 };
+        let supervisor_pending = if supervisor_enabled { pending & mideleg } else { 0 };
+        Interrupt::highest_priority(machine_pending)
+            .or_else(|| Interrupt::highest_priority(supervisor_pending))
+    }
+
+    /// WFI から復帰すべき割り込みが保留されているかを取得します。(mstatus の割り込み許可ビットには依存しない)
+    pub(super) fn has_wakeup_interrupt(&self) -> bool {
+        self.csr.mip() & self.csr.get(CSR_MIE) != 0
+    }
+
+    /// トラップを処理する特権モードを決定します。
+    ///
+    /// M モード以外で発生したトラップは、medeleg / mideleg の対応するビットが立っていれば S モードに委譲されます。
+    fn trap_target(&self, code: u64, is_interrupt: bool) -> PrivilegeMode {
+        let deleg = if is_interrupt {
+            self.csr.get(CSR_MIDELEG)
+        } else {
+            self.csr.get(CSR_MEDELEG)
+        };
+
+        if self.mode < PrivilegeMode::Machine && (deleg >> code) & 1 == 1 {
+            PrivilegeMode::Supervisor
+        } else {
+            PrivilegeMode::Machine
+        }
+    }
+
+    /// トラップを発生させます: 現在の PC やトラップ要因を CSR に保存し、xtvec の指すハンドラへジャンプします。
+    fn take_trap(&mut self, code: u64, tval: u64, is_interrupt: bool) {
+        let cause = if is_interrupt { code | INTERRUPT_BIT } else { code };
+        let target = self.trap_target(code, is_interrupt);
+
+        let mut mstatus = self.csr.mstatus();
+        let tvec = if target == PrivilegeMode::Supervisor {
+            self.csr.set(CSR_SEPC, self.pc);
+            self.csr.set(CSR_SCAUSE, cause);
+            self.csr.set(CSR_STVAL, tval);
+
+            // NOTE: SPIE <- SIE, SIE <- 0, SPP <- 現在の特権モード (U=0, S=1)
+            let sie = mstatus.get(SIE);
+            mstatus.set(SPIE, sie).set(SIE, 0).set(SPP, self.mode.bits());
+            self.csr.get(CSR_STVEC)
+        } else {
+            self.csr.set(CSR_MEPC, self.pc);
+            self.csr.set(CSR_MCAUSE, cause);
+            self.csr.set(CSR_MTVAL, tval);
+
+            // NOTE: MPIE <- MIE, MIE <- 0, MPP <- 現在の特権モード
+            let mie = mstatus.get(MIE);
+            mstatus.set(MPIE, mie).set(MIE, 0).set(MPP, self.mode.bits());
+            self.csr.get(CSR_MTVEC)
+        };
+        self.csr.set_mstatus(&mstatus);
+        self.mode = target;
+        // NOTE: トラップが発生すると、LR による予約は破棄される
+        self.reservation = None;
+
+        // NOTE: MODE = 1 (Vectored) の場合、割り込みは BASE + 4 * cause へジャンプする
+        let base = tvec & !0b11;
+        self.pc = if tvec & 0b11 == 1 && is_interrupt {
+            base.wrapping_add(code * 4)
+        } else {
+            base
+        };
+    }
+
+    /// MRET: M モードのトラップから復帰し、復帰先のアドレスを返します。
+    pub(super) fn return_from_machine_trap(&mut self) -> u64 {
+        // NOTE: 特権モード <- MPP, MIE <- MPIE, MPIE <- 1, MPP <- U
+        let mut mstatus = self.csr.mstatus();
+        let mode = PrivilegeMode::from_bits(mstatus.get(MPP));
+        let mpie = mstatus.get(MPIE);
+        mstatus.set(MIE, mpie).set(MPIE, 1).set(MPP, PrivilegeMode::User.bits());
+        if mode != PrivilegeMode::Machine {
+            mstatus.set(MPRV, 0);
+        }
+        self.csr.set_mstatus(&mstatus);
+        self.mode = mode;
+
+        self.csr.get(CSR_MEPC)
+    }
+
+    /// SRET: S モードのトラップから復帰し、復帰先のアドレスを返します。
+    pub(super) fn return_from_supervisor_trap(&mut self) -> u64 {
+        // NOTE: 特権モード <- SPP, SIE <- SPIE, SPIE <- 1, SPP <- U
+        let mut mstatus = self.csr.mstatus();
+        let mode = PrivilegeMode::from_bits(mstatus.get(SPP));
+        let spie = mstatus.get(SPIE);
+        mstatus.set(SIE, spie).set(SPIE, 1).set(SPP, PrivilegeMode::User.bits()).set(MPRV, 0);
+        self.csr.set_mstatus(&mstatus);
+        self.mode = mode;
+
+        self.csr.get(CSR_SEPC)
+    }
+}
@@ -0,0 +1,65 @@
+mod clint;
+mod htif;
+mod plic;
+mod test_finisher;
+mod uart;
+mod virtio;
+
+pub use clint::{Clint, ClockSource};
+pub use htif::Htif;
+pub use plic::Plic;
+pub use test_finisher::TestFinisher;
+#[cfg(unix)]
+pub use uart::UnixSocketBackend;
+#[cfg(unix)]
+pub use virtio::{UnixDatagramBackend, Virtio9p};
+pub use uart::{MemoryBackend, StdioBackend, Uart, UartBackend};
+pub use virtio::{
+    DescriptorChain, DiskMode, EntropySource, LoopbackBackend, NetBackend, PcapBackend, VirtioBlk, VirtioConsole, VirtioDevice, VirtioMmio, VirtioNet, VirtioRng, Virtqueue,
+};
+
+use crate::{ExitReason, bus::Dma};
+
+/// デバイスへのアクセスの失敗 (Bus によってアクセスフォールトに変換される)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceError;
+
+/// バスに接続するメモリマップド I/O デバイス
+///
+/// オフセットはデバイスを配置したアドレスからの相対アドレスで、アクセスは必ずデバイスの領域内に収まります。
+pub trait Device {
+    /// オフセット offset から size バイトを読み込みます。
+    fn read(&mut self, offset: u64, size: u64) -> Result<u64, DeviceError>;
+
+    /// オフセット offset に size バイトを書き込みます。
+    fn write(&mut self, offset: u64, value: u64, size: u64) -> Result<(), DeviceError>;
+
+    /// デバイスの時間を 1 サイクル進めます。(CPU が 1 サイクル進むたびに呼ばれる)
+    fn tick(&mut self) {}
+
+    /// RAM にアクセスして処理を進めます。(毎サイクル、tick の後に呼ばれる)
+    fn dma(&mut self, _dma: &mut Dma) {}
+
+    /// ゲストの実行の終了を要求している場合、その理由を取得します。
+    fn exit_reason(&self) -> Option<ExitReason> {
+        None
+    }
+
+    /// デバイスの割り込み線がアサートされているかを取得します。(Bus で割り込み番号に接続された場合のみ参照される)
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// アサートされている割り込み線の割り込み番号を受け取ります。(PLIC などの割り込みコントローラのみが使い、毎サイクル呼ばれる)
+    fn set_irq_lines(&mut self, _irqs: &[u32]) {}
+
+    /// デバイスが CPU に直接伝えている割り込みを、mip のビットで取得します。(CLINT などの割り込みコントローラのみが使う)
+    fn interrupts(&self) -> u64 {
+        0
+    }
+
+    /// time CSR の元となる mtime の値を取得します。(CLINT のみが提供する)
+    fn mtime(&self) -> Option<u64> {
+        None
+    }
+}
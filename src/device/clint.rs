@@ -0,0 +1,140 @@
+use std::time::Instant;
+
+use crate::device::{Device, DeviceError};
+
+// NOTE: SiFive CLINT 互換のレイアウト (ハート i のレジスタは msip: 4 * i, mtimecmp: 0x4000 + 8 * i に並ぶ)
+
+/// msip: M モードのソフトウェア割り込み (bit 0 のみ実装)
+const REG_MSIP: u64 = 0x0000;
+/// mtimecmp: タイマ割り込みを発生させる時刻
+const REG_MTIMECMP: u64 = 0x4000;
+/// mtime: 実時間カウンタ
+const REG_MTIME: u64 = 0xBFF8;
+
+/// mip.MSIP
+const MIP_MSIP: u64 = 1 << 3;
+/// mip.MTIP
+const MIP_MTIP: u64 = 1 << 7;
+
+/// mtime を進める基準
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// CPU の 1 サイクル (1 命令、または WFI 中の 1 サイクル) ごとに 1 進む (決定的)
+    InstructionCount,
+    /// ホストの実時間に従って、1 秒あたり frequency 進む
+    WallClock { frequency: u64 },
+}
+
+/// CLINT (Core-Local Interruptor)
+///
+/// ハート 0 の msip, mtimecmp と、共通の mtime を実装し、mip の MSIP / MTIP を駆動します。
+pub struct Clint {
+    /// mtime を進める基準
+    source: ClockSource,
+    /// ハート 0 の msip
+    msip: u32,
+    /// ハート 0 の mtimecmp
+    mtimecmp: u64,
+    /// mtime (ClockSource::WallClock の場合は、epoch 時点の値)
+    mtime: u64,
+    /// ClockSource::WallClock で、mtime を数え始めた時刻
+    epoch: Instant,
+}
+impl Clint {
+    /// 慣例的な配置アドレス
+    pub const BASE: u64 = 0x0200_0000;
+    /// アドレスマップ上の領域のサイズ
+    pub const SIZE: u64 = 0x1_0000;
+
+    /// source に従って mtime を進める、新しい Clint を作成します。
+    pub fn new(source: ClockSource) -> Self {
+        Self {
+            source,
+            msip: 0,
+            // NOTE: リセット直後にタイマ割り込みが保留されないよう、最大値にしておく
+            mtimecmp: u64::MAX,
+            mtime: 0,
+            epoch: Instant::now(),
+        }
+    }
+
+    /// 現在の mtime を取得します。
+    fn read_mtime(&self) -> u64 {
+        match self.source {
+            ClockSource::InstructionCount => self.mtime,
+            ClockSource::WallClock { frequency } => {
+                let ticks = self.epoch.elapsed().as_nanos() * frequency as u128 / 1_000_000_000;
+                self.mtime.wrapping_add(ticks as u64)
+            },
+        }
+    }
+
+    /// mtime に値を書き込みます。
+    fn write_mtime(&mut self, value: u64) {
+        self.mtime = value;
+        self.epoch = Instant::now();
+    }
+}
+impl Device for Clint {
+    fn read(&mut self, offset: u64, size: u64) -> Result<u64, DeviceError> {
+        if offset == REG_MSIP && size == 4 {
+            return Ok(self.msip as u64);
+        }
+        let (value, shift) = if let Some(shift) = field(offset, size, REG_MTIMECMP) {
+            (self.mtimecmp, shift)
+        } else if let Some(shift) = field(offset, size, REG_MTIME) {
+            (self.read_mtime(), shift)
+        } else {
+            return Err(DeviceError);
+        };
+        Ok((value >> shift) & mask(size))
+    }
+
+    fn write(&mut self, offset: u64, value: u64, size: u64) -> Result<(), DeviceError> {
+        if offset == REG_MSIP && size == 4 {
+            self.msip = value as u32 & 1;
+            return Ok(());
+        }
+        // NOTE: 32bit ずつの書き込みでは、もう半分の値を保ったまま書き換える
+        let merge = |old: u64, shift: u64| (old & !(mask(size) << shift)) | ((value & mask(size)) << shift);
+        if let Some(shift) = field(offset, size, REG_MTIMECMP) {
+            self.mtimecmp = merge(self.mtimecmp, shift);
+        } else if let Some(shift) = field(offset, size, REG_MTIME) {
+            let mtime = merge(self.read_mtime(), shift);
+            self.write_mtime(mtime);
+        } else {
+            return Err(DeviceError);
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        if self.source == ClockSource::InstructionCount {
+            self.mtime = self.mtime.wrapping_add(1);
+        }
+    }
+
+    fn interrupts(&self) -> u64 {
+        let msip = if self.msip & 1 != 0 { MIP_MSIP } else { 0 };
+        let mtip = if self.read_mtime() >= self.mtimecmp { MIP_MTIP } else { 0 };
+        msip | mtip
+    }
+
+    fn mtime(&self) -> Option<u64> {
+        Some(self.read_mtime())
+    }
+}
+
+/// [offset, offset + size) が 64bit のレジスタ base の全体か、上位・下位の 32bit である場合、そのビット位置を取得します。
+fn field(offset: u64, size: u64, base: u64) -> Option<u64> {
+    match (offset.checked_sub(base)?, size) {
+        (0, 4 | 8) => Some(0),
+        (4, 4) => Some(32),
+        _ => None,
+    }
+}
+
+/// size バイトの値のマスクを取得します。
+fn mask(size: u64) -> u64 {
+    if size == 8 { u64::MAX } else { (1 << (size * 8)) - 1 }
+}
@@ -0,0 +1,137 @@
+use elf::{ElfBytes, endian::LittleEndian};
+
+use crate::{ExitReason, bus::Dma, device::{Device, DeviceError, UartBackend}};
+
+// NOTE: tohost / fromhost の値は device (63:56), command (55:48), payload (47:0) からなる
+
+/// device = 0: システムコールのプロキシ (payload の bit 0 が 1 なら終了)
+const DEVICE_SYSCALL: u64 = 0;
+/// device = 1: コンソール
+const DEVICE_CONSOLE: u64 = 1;
+
+/// コンソールの command = 1: payload の下位 8bit を出力する
+const CONSOLE_PUTCHAR: u64 = 1;
+
+/// システムコール write の番号
+const SYS_WRITE: u64 = 64;
+/// 標準出力のファイルディスクリプタ
+const STDOUT: u64 = 1;
+/// 標準エラー出力のファイルディスクリプタ
+const STDERR: u64 = 2;
+/// 実装していないシステムコールの戻り値 (-ENOSYS)
+const ENOSYS: u64 = -38i64 as u64;
+/// 引数のアドレスが不正な場合の戻り値 (-EFAULT)
+const EFAULT: u64 = -14i64 as u64;
+
+/// HTIF (Host-Target Interface)
+///
+/// riscv-tests などが使う tohost / fromhost 変数を毎サイクル監視し、終了要求やシステムコールを処理します。
+/// tohost / fromhost は RAM 上の変数なので、アドレスマップには配置せずに `Bus::attach_device` で接続します。
+pub struct Htif {
+    /// tohost のアドレス
+    tohost: u64,
+    /// fromhost のアドレス (存在しない場合は応答しない)
+    fromhost: Option<u64>,
+    /// コンソールの出力先
+    console: Box<dyn UartBackend>,
+    /// ゲストが要求した終了
+    exit: Option<ExitReason>,
+}
+impl Htif {
+    /// tohost, fromhost のアドレスを指定して、新しい Htif を作成します。
+    pub fn new(tohost: u64, fromhost: Option<u64>, console: Box<dyn UartBackend>) -> Self {
+        Self { tohost, fromhost, console, exit: None }
+    }
+
+    /// ELF のシンボルテーブルから tohost, fromhost のアドレスを解決して、新しい Htif を作成します。
+    ///
+    /// ELF を解析できないか、tohost シンボルが存在しない場合は None を返します。
+    pub fn from_elf(data: &[u8], console: Box<dyn UartBackend>) -> Option<Self> {
+        let file = ElfBytes::<LittleEndian>::minimal_parse(data).ok()?;
+        let (symbols, strings) = file.symbol_table().ok()??;
+        let find = |name: &str| {
+            symbols.iter().find(|symbol| strings.get(symbol.st_name as usize).is_ok_and(|s| s == name)).map(|symbol| symbol.st_value)
+        };
+        Some(Self::new(find("tohost")?, find("fromhost"), console))
+    }
+
+    /// tohost に書き込まれた要求を処理します。
+    fn handle(&mut self, dma: &mut Dma, request: u64) {
+        let device = request >> 56;
+        let command = (request >> 48) & 0xFF;
+        let payload = request & ((1 << 48) - 1);
+        match (device, command) {
+            (DEVICE_SYSCALL, _) if payload & 1 != 0 => {
+                self.exit = Some(ExitReason::Poweroff((payload >> 1) as u32));
+            },
+            (DEVICE_SYSCALL, _) => {
+                // NOTE: payload は magic_mem (システムコール番号と引数の 64bit 配列) を指し、戻り値はその先頭に書き戻す
+                let result = self.syscall(dma, payload).unwrap_or(ENOSYS);
+                let _ = dma.write(payload, result, 8);
+                self.respond(dma, DEVICE_SYSCALL, 0, 1);
+            },
+            (DEVICE_CONSOLE, CONSOLE_PUTCHAR) => {
+                self.console.write(payload as u8);
+                self.respond(dma, DEVICE_CONSOLE, CONSOLE_PUTCHAR, 0);
+            },
+            // NOTE: コンソールからの入力 (command = 0) などには応答しない
+            _ => {},
+        }
+    }
+
+    /// magic_mem が指すシステムコールを実行し、その戻り値を取得します。
+    fn syscall(&mut self, dma: &mut Dma, magic_mem: u64) -> Option<u64> {
+        let mut args = [0; 4];
+        for (i, arg) in args.iter_mut().enumerate() {
+            *arg = dma.read(magic_mem + i as u64 * 8, 8).ok()?;
+        }
+        match args {
+            [SYS_WRITE, STDOUT | STDERR, buf, len] => {
+                // NOTE: len はゲストが指定するので、RAM に収まることを確認してから確保する
+                if !dma.contains(buf, len) {
+                    return Some(EFAULT);
+                }
+                let mut bytes = vec![0; len as usize];
+                dma.read_bytes(buf, &mut bytes).ok()?;
+                for byte in bytes {
+                    self.console.write(byte);
+                }
+                Some(len)
+            },
+            _ => None,
+        }
+    }
+
+    /// fromhost に応答を書き込みます。
+    fn respond(&self, dma: &mut Dma, device: u64, command: u64, payload: u64) {
+        if let Some(fromhost) = self.fromhost {
+            let _ = dma.write(fromhost, (device << 56) | (command << 48) | payload, 8);
+        }
+    }
+}
+impl Device for Htif {
+    fn read(&mut self, _offset: u64, _size: u64) -> Result<u64, DeviceError> {
+        Err(DeviceError)
+    }
+
+    fn write(&mut self, _offset: u64, _value: u64, _size: u64) -> Result<(), DeviceError> {
+        Err(DeviceError)
+    }
+
+    fn dma(&mut self, dma: &mut Dma) {
+        if self.exit.is_some() {
+            return;
+        }
+        let Ok(request) = dma.read(self.tohost, 8) else { return };
+        if request == 0 {
+            return;
+        }
+        // NOTE: 要求を受け取ったことを、tohost を 0 に戻して知らせる
+        let _ = dma.write(self.tohost, 0, 8);
+        self.handle(dma, request);
+    }
+
+    fn exit_reason(&self) -> Option<ExitReason> {
+        self.exit
+    }
+}
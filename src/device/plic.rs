@@ -0,0 +1,194 @@
+use crate::device::{Device, DeviceError};
+
+// NOTE: SiFive PLIC 互換のレイアウトで、コンテキストごとのレジスタはコンテキスト番号の順に並ぶ
+
+/// 割り込み源ごとの優先度 (4 バイトずつ)
+const REG_PRIORITY: u64 = 0x00_0000;
+/// 保留中のビット列 (32 割り込み源ずつ)
+const REG_PENDING: u64 = 0x00_1000;
+/// コンテキストごとの許可ビット列
+const REG_ENABLE: u64 = 0x00_2000;
+/// 許可ビット列のコンテキストごとの間隔
+const ENABLE_STRIDE: u64 = 0x80;
+/// コンテキストごとの優先度の閾値 (その 4 バイト後がクレーム / 完了レジスタ)
+const REG_CONTEXT: u64 = 0x20_0000;
+/// 閾値・クレーム / 完了レジスタのコンテキストごとの間隔
+const CONTEXT_STRIDE: u64 = 0x1000;
+
+/// 優先度・閾値の実装されているビット (0 は「割り込まない」を表す)
+const PRIORITY_MASK: u32 = 0b111;
+
+/// 割り込み源の最大数 (割り込み源 0 は存在しない)
+pub const MAX_PLIC_SOURCES: usize = 1023;
+
+/// コンテキストの数 (コンテキスト 0 はハート 0 の M モード、コンテキスト 1 はハート 0 の S モード)
+const CONTEXTS: usize = 2;
+
+/// 各コンテキストが駆動する mip のビット (MEIP, SEIP)
+const CONTEXT_INTERRUPTS: [u64; CONTEXTS] = [1 << 11, 1 << 9];
+
+/// PLIC (Platform-Level Interrupt Controller)
+///
+/// 割り込み線はレベルトリガとして扱い、アサートされている割り込み源は、クレームから完了までの間を除いて保留され続けます。
+pub struct Plic {
+    /// 割り込み源の数 (割り込み源 1..=sources が存在する)
+    sources: usize,
+    /// 割り込み源ごとの優先度
+    priorities: Vec<u32>,
+    /// 保留中の割り込み源 (32 割り込み源ずつのビット列)
+    pending: Vec<u32>,
+    /// クレームされ、まだ完了していない割り込み源
+    claimed: Vec<u32>,
+    /// コンテキストごとの許可ビット列
+    enables: [Vec<u32>; CONTEXTS],
+    /// コンテキストごとの優先度の閾値
+    thresholds: [u32; CONTEXTS],
+}
+impl Plic {
+    /// 慣例的な配置アドレス
+    pub const BASE: u64 = 0x0c00_0000;
+    /// アドレスマップ上の領域のサイズ
+    pub const SIZE: u64 = 0x400_0000;
+
+    /// 割り込み源 1..=sources を持つ、新しい Plic を作成します。
+    pub fn new(sources: usize) -> Self {
+        assert!(sources <= MAX_PLIC_SOURCES, "PLIC の割り込み源は最大 {MAX_PLIC_SOURCES} 個です");
+        let words = (sources + 1).div_ceil(32);
+        Self {
+            sources,
+            priorities: vec![0; sources + 1],
+            pending: vec![0; words],
+            claimed: vec![0; words],
+            enables: std::array::from_fn(|_| vec![0; words]),
+            thresholds: [0; CONTEXTS],
+        }
+    }
+
+    /// 割り込み源 id のビット列の要素番号とビットを取得します。
+    fn bit(id: usize) -> (usize, u32) {
+        (id / 32, 1 << (id % 32))
+    }
+
+    /// ビット列の要素 word のうち、実装されている割り込み源のビットのマスクを取得します。
+    fn word_mask(&self, word: usize) -> u32 {
+        let first = word * 32;
+        (0..32).filter(|&i| (1..=self.sources).contains(&(first + i))).fold(0, |acc, i| acc | (1 << i))
+    }
+
+    /// context に通知すべき割り込み源のうち、最も優先度の高いものを取得します。(同じ優先度なら番号の小さいものを優先する)
+    fn best_pending(&self, context: usize) -> Option<usize> {
+        let mut best: Option<(usize, u32)> = None;
+        for (word, (&pending, &enable)) in self.pending.iter().zip(&self.enables[context]).enumerate() {
+            let mut bits = pending & enable;
+            while bits != 0 {
+                let id = word * 32 + bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                let priority = self.priorities[id];
+                if priority > self.thresholds[context] && best.is_none_or(|(_, p)| priority > p) {
+                    best = Some((id, priority));
+                }
+            }
+        }
+        best.map(|(id, _)| id)
+    }
+
+    /// context で、最も優先度の高い割り込みをクレームします。(なければ 0 を返す)
+    fn claim(&mut self, context: usize) -> u32 {
+        let Some(id) = self.best_pending(context) else { return 0 };
+        let (word, bit) = Self::bit(id);
+        self.pending[word] &= !bit;
+        self.claimed[word] |= bit;
+        id as u32
+    }
+
+    /// context で、割り込み源 id の処理の完了を通知します。
+    fn complete(&mut self, context: usize, id: usize) {
+        if !(1..=self.sources).contains(&id) {
+            return;
+        }
+        // NOTE: context で許可されていない割り込み源の完了は無視する
+        let (word, bit) = Self::bit(id);
+        if self.enables[context][word] & bit != 0 {
+            self.claimed[word] &= !bit;
+        }
+    }
+
+    /// offset がコンテキストごとのレジスタであれば、(コンテキスト番号, コンテキスト内のオフセット) を取得します。
+    fn context_register(offset: u64, base: u64, stride: u64) -> Option<(usize, u64)> {
+        let relative = offset.checked_sub(base)?;
+        let context = (relative / stride) as usize;
+        (context < CONTEXTS).then_some((context, relative % stride))
+    }
+}
+impl Device for Plic {
+    fn read(&mut self, offset: u64, size: u64) -> Result<u64, DeviceError> {
+        if size != 4 || !offset.is_multiple_of(4) {
+            return Err(DeviceError);
+        }
+        let value = if offset < REG_PENDING {
+            self.priorities.get(((offset - REG_PRIORITY) / 4) as usize).copied().unwrap_or(0)
+        } else if offset < REG_ENABLE {
+            self.pending.get(((offset - REG_PENDING) / 4) as usize).copied().unwrap_or(0)
+        } else if let Some((context, offset)) = Self::context_register(offset, REG_ENABLE, ENABLE_STRIDE) {
+            self.enables[context].get((offset / 4) as usize).copied().unwrap_or(0)
+        } else if let Some((context, offset)) = Self::context_register(offset, REG_CONTEXT, CONTEXT_STRIDE) {
+            match offset {
+                0 => self.thresholds[context],
+                4 => self.claim(context),
+                _ => 0,
+            }
+        } else {
+            // NOTE: 存在しないコンテキストなどの予約済みのレジスタは 0 に固定される
+            0
+        };
+        Ok(value as u64)
+    }
+
+    fn write(&mut self, offset: u64, value: u64, size: u64) -> Result<(), DeviceError> {
+        if size != 4 || !offset.is_multiple_of(4) {
+            return Err(DeviceError);
+        }
+        let value = value as u32;
+        if offset < REG_PENDING {
+            let id = ((offset - REG_PRIORITY) / 4) as usize;
+            // NOTE: 割り込み源 0 は存在しないので、優先度も 0 に固定される
+            if (1..=self.sources).contains(&id) {
+                self.priorities[id] = value & PRIORITY_MASK;
+            }
+        } else if offset < REG_ENABLE {
+            // NOTE: 保留中のビット列は読み取り専用
+        } else if let Some((context, offset)) = Self::context_register(offset, REG_ENABLE, ENABLE_STRIDE) {
+            let word = (offset / 4) as usize;
+            if word < self.enables[context].len() {
+                self.enables[context][word] = value & self.word_mask(word);
+            }
+        } else if let Some((context, offset)) = Self::context_register(offset, REG_CONTEXT, CONTEXT_STRIDE) {
+            match offset {
+                0 => self.thresholds[context] = value & PRIORITY_MASK,
+                4 => self.complete(context, value as usize),
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    fn set_irq_lines(&mut self, irqs: &[u32]) {
+        for &id in irqs {
+            let id = id as usize;
+            if !(1..=self.sources).contains(&id) {
+                continue;
+            }
+            // NOTE: クレームされてから完了するまでの間は、同じ割り込み源を再び保留しない
+            let (word, bit) = Self::bit(id);
+            if self.claimed[word] & bit == 0 {
+                self.pending[word] |= bit;
+            }
+        }
+    }
+
+    fn interrupts(&self) -> u64 {
+        (0..CONTEXTS)
+            .filter(|&context| self.best_pending(context).is_some())
+            .fold(0, |acc, context| acc | CONTEXT_INTERRUPTS[context])
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{ExitReason, device::{Device, DeviceError}};
+
+/// 下位 16bit = 0x5555: 終了コード 0 で電源を切る
+const FINISHER_PASS: u64 = 0x5555;
+/// 下位 16bit = 0x3333: 上位 16bit を終了コードとして電源を切る
+const FINISHER_FAIL: u64 = 0x3333;
+/// 下位 16bit = 0x7777: 再起動する
+const FINISHER_RESET: u64 = 0x7777;
+
+/// SiFive のテストデバイス互換の、電源断・再起動デバイス (syscon-poweroff / syscon-reboot)
+pub struct TestFinisher {
+    /// ゲストが要求した終了
+    exit: Option<ExitReason>,
+}
+impl TestFinisher {
+    /// 慣例的な配置アドレス
+    pub const BASE: u64 = 0x10_0000;
+    /// アドレスマップ上の領域のサイズ
+    pub const SIZE: u64 = 0x1000;
+
+    /// 新しい TestFinisher を作成します。
+    pub fn new() -> Self {
+        Self { exit: None }
+    }
+}
+impl Default for TestFinisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Device for TestFinisher {
+    fn read(&mut self, offset: u64, size: u64) -> Result<u64, DeviceError> {
+        match (offset, size) {
+            (0, 4) => Ok(0),
+            _ => Err(DeviceError),
+        }
+    }
+
+    fn write(&mut self, offset: u64, value: u64, size: u64) -> Result<(), DeviceError> {
+        if (offset, size) != (0, 4) {
+            return Err(DeviceError);
+        }
+        let reason = match value & 0xFFFF {
+            FINISHER_PASS => ExitReason::Poweroff(0),
+            FINISHER_FAIL => ExitReason::Poweroff(((value >> 16) & 0xFFFF) as u32),
+            FINISHER_RESET => ExitReason::Reset,
+            // NOTE: それ以外の値の書き込みは無視する
+            _ => return Ok(()),
+        };
+        // NOTE: 最初に要求された終了を優先する
+        self.exit.get_or_insert(reason);
+        Ok(())
+    }
+
+    fn exit_reason(&self) -> Option<ExitReason> {
+        self.exit
+    }
+}
@@ -0,0 +1,315 @@
+mod backend;
+
+use std::collections::VecDeque;
+
+use crate::device::{Device, DeviceError};
+
+#[cfg(unix)]
+pub use backend::UnixSocketBackend;
+pub use backend::{MemoryBackend, StdioBackend, UartBackend};
+
+// NOTE: レジスタは 1 バイト間隔で並び (reg-shift = 0)、LCR.DLAB によってオフセット 0, 1 の意味が切り替わる
+
+/// RBR (読み込み) / THR (書き込み) / DLL (DLAB = 1)
+const REG_DATA: u64 = 0;
+/// IER / DLM (DLAB = 1)
+const REG_IER: u64 = 1;
+/// IIR (読み込み) / FCR (書き込み)
+const REG_IIR: u64 = 2;
+/// LCR
+const REG_LCR: u64 = 3;
+/// MCR
+const REG_MCR: u64 = 4;
+/// LSR
+const REG_LSR: u64 = 5;
+/// MSR
+const REG_MSR: u64 = 6;
+/// SCR
+const REG_SCR: u64 = 7;
+
+/// IER.ERBFI: 受信データの割り込みを有効にする
+const IER_RDI: u8 = 1 << 0;
+/// IER.ETBEI: 送信保持レジスタが空になった割り込みを有効にする
+const IER_THRI: u8 = 1 << 1;
+/// IER.ELSI: ラインステータスの割り込みを有効にする
+const IER_RLSI: u8 = 1 << 2;
+/// IER.EDSSI: モデムステータスの割り込みを有効にする
+const IER_MSI: u8 = 1 << 3;
+
+/// IIR: 割り込みが保留されていない
+const IIR_NO_INT: u8 = 0x01;
+/// IIR: ラインステータス (最優先)
+const IIR_RLSI: u8 = 0x06;
+/// IIR: 受信データがトリガレベルに達した
+const IIR_RDI: u8 = 0x04;
+/// IIR: キャラクタタイムアウト (トリガレベル未満のデータが FIFO に残っている)
+const IIR_TIMEOUT: u8 = 0x0C;
+/// IIR: 送信保持レジスタが空になった
+const IIR_THRI: u8 = 0x02;
+/// IIR: FIFO が有効になっている
+const IIR_FIFO_ENABLED: u8 = 0xC0;
+
+/// FCR: FIFO を有効にする
+const FCR_ENABLE_FIFO: u8 = 1 << 0;
+/// FCR: 受信 FIFO をクリアする
+const FCR_CLEAR_RCVR: u8 = 1 << 1;
+
+/// LCR.DLAB: オフセット 0, 1 を分周器ラッチ (DLL / DLM) に切り替える
+const LCR_DLAB: u8 = 1 << 7;
+
+/// MCR: ループバックモード (送信したデータを自身で受信する)
+const MCR_LOOP: u8 = 1 << 4;
+
+/// LSR.DR: 受信データがある
+const LSR_DR: u8 = 1 << 0;
+/// LSR.OE: 受信 FIFO が溢れてデータが失われた
+const LSR_OE: u8 = 1 << 1;
+/// LSR.THRE: 送信保持レジスタが空
+const LSR_THRE: u8 = 1 << 5;
+/// LSR.TEMT: 送信器が空
+const LSR_TEMT: u8 = 1 << 6;
+
+/// MSR: CTS, DSR, DCD (ループバックモードでない場合は常に接続されているとみなす)
+const MSR_CONNECTED: u8 = 0xB0;
+
+/// FIFO の段数
+const FIFO_SIZE: usize = 16;
+
+/// バックエンドから受信データを取り込む間隔 (サイクル数)
+const POLL_INTERVAL: u32 = 256;
+
+/// キャラクタタイムアウトとみなすまでのサイクル数
+const CHARACTER_TIMEOUT: u32 = 4 * POLL_INTERVAL;
+
+/// NS16550A 互換の UART
+///
+/// 送信は即座にバックエンドへ書き出されるので、送信保持レジスタは常に空です。
+/// 分周器ラッチは読み書きできますが、ボーレートは送受信に影響しません。
+pub struct Uart {
+    /// 送受信するバイト列の接続先
+    backend: Box<dyn UartBackend>,
+    /// 受信 FIFO (FIFO が無効な場合は 1 段として扱う)
+    rx_fifo: VecDeque<u8>,
+    /// IER: 割り込み許可レジスタ
+    ier: u8,
+    /// FCR: FIFO 制御レジスタ (書き込み専用なので、FIFO の有効化とトリガレベルのみを保持する)
+    fcr: u8,
+    /// LCR: ライン制御レジスタ
+    lcr: u8,
+    /// MCR: モデム制御レジスタ
+    mcr: u8,
+    /// LSR.OE: オーバーランエラー (LSR の読み込みでクリアされる)
+    overrun: bool,
+    /// SCR: スクラッチレジスタ
+    scr: u8,
+    /// DLL: 分周器ラッチ (下位)
+    dll: u8,
+    /// DLM: 分周器ラッチ (上位)
+    dlm: u8,
+    /// 送信保持レジスタが空になった割り込みが保留されているか
+    thr_pending: bool,
+    /// 受信 FIFO が最後に読み書きされてからのサイクル数
+    idle_cycles: u32,
+    /// 次にバックエンドから受信データを取り込むまでのサイクル数
+    poll_countdown: u32,
+}
+impl Uart {
+    /// 慣例的な配置アドレス
+    pub const BASE: u64 = 0x1000_0000;
+    /// アドレスマップ上の領域のサイズ
+    pub const SIZE: u64 = 0x100;
+    /// 慣例的な割り込み番号
+    pub const IRQ: u32 = 10;
+
+    /// backend に接続された、新しい Uart を作成します。
+    pub fn new(backend: Box<dyn UartBackend>) -> Self {
+        Self {
+            backend,
+            rx_fifo: VecDeque::with_capacity(FIFO_SIZE),
+            ier: 0,
+            fcr: 0,
+            lcr: 0,
+            mcr: 0,
+            overrun: false,
+            scr: 0,
+            dll: 0,
+            dlm: 0,
+            thr_pending: false,
+            idle_cycles: 0,
+            poll_countdown: 0,
+        }
+    }
+
+    /// FIFO が有効になっているかを取得します。
+    fn fifo_enabled(&self) -> bool {
+        self.fcr & FCR_ENABLE_FIFO != 0
+    }
+
+    /// 受信 FIFO の段数を取得します。
+    fn fifo_capacity(&self) -> usize {
+        if self.fifo_enabled() { FIFO_SIZE } else { 1 }
+    }
+
+    /// 受信データの割り込みが発生する、受信 FIFO のデータ数を取得します。
+    fn trigger_level(&self) -> usize {
+        if !self.fifo_enabled() {
+            return 1;
+        }
+        match self.fcr >> 6 {
+            0 => 1,
+            1 => 4,
+            2 => 8,
+            _ => 14,
+        }
+    }
+
+    /// 受信したバイトを受信 FIFO に格納します。(溢れた場合はオーバーランエラーとなる)
+    fn receive(&mut self, byte: u8) {
+        if self.rx_fifo.len() < self.fifo_capacity() {
+            self.rx_fifo.push_back(byte);
+        } else {
+            self.overrun = true;
+        }
+        self.idle_cycles = 0;
+    }
+
+    /// 1 バイトを送信します。
+    fn transmit(&mut self, byte: u8) {
+        if self.mcr & MCR_LOOP != 0 {
+            self.receive(byte);
+        } else {
+            self.backend.write(byte);
+        }
+        // NOTE: 送信は即座に完了するので、送信保持レジスタはすぐに空になる
+        self.thr_pending = true;
+    }
+
+    /// 保留されている割り込みのうち、最も優先度の高いものの IIR の値を取得します。
+    fn interrupt_id(&self) -> Option<u8> {
+        if self.ier & IER_RLSI != 0 && self.overrun {
+            Some(IIR_RLSI)
+        } else if self.ier & IER_RDI != 0 && self.rx_fifo.len() >= self.trigger_level() {
+            Some(IIR_RDI)
+        } else if self.ier & IER_RDI != 0 && self.fifo_enabled() && !self.rx_fifo.is_empty() && self.idle_cycles >= CHARACTER_TIMEOUT {
+            Some(IIR_TIMEOUT)
+        } else if self.ier & IER_THRI != 0 && self.thr_pending {
+            Some(IIR_THRI)
+        } else {
+            // NOTE: モデムステータスは変化しないので、その割り込みは発生しない
+            None
+        }
+    }
+
+    /// LSR の値を取得します。
+    fn lsr(&self) -> u8 {
+        let mut lsr = LSR_THRE | LSR_TEMT;
+        if !self.rx_fifo.is_empty() {
+            lsr |= LSR_DR;
+        }
+        if self.overrun {
+            lsr |= LSR_OE;
+        }
+        lsr
+    }
+
+    /// MSR の値を取得します。
+    fn msr(&self) -> u8 {
+        if self.mcr & MCR_LOOP != 0 {
+            // NOTE: ループバックモードでは、MCR の DTR, RTS, OUT1, OUT2 がそれぞれ DSR, CTS, RI, DCD に接続される
+            let mcr = self.mcr;
+            ((mcr & 0b0001) << 5) | ((mcr & 0b0010) << 3) | ((mcr & 0b0100) << 4) | ((mcr & 0b1000) << 4)
+        } else {
+            MSR_CONNECTED
+        }
+    }
+}
+impl Device for Uart {
+    fn read(&mut self, offset: u64, size: u64) -> Result<u64, DeviceError> {
+        if size != 1 {
+            return Err(DeviceError);
+        }
+        let dlab = self.lcr & LCR_DLAB != 0;
+        let value = match offset {
+            REG_DATA if dlab => self.dll,
+            REG_DATA => {
+                self.idle_cycles = 0;
+                self.rx_fifo.pop_front().unwrap_or(0)
+            },
+            REG_IER if dlab => self.dlm,
+            REG_IER => self.ier,
+            REG_IIR => {
+                let id = self.interrupt_id();
+                // NOTE: 送信保持レジスタが空になった割り込みは、それを報告した IIR の読み込みでクリアされる
+                if id == Some(IIR_THRI) {
+                    self.thr_pending = false;
+                }
+                let fifo = if self.fifo_enabled() { IIR_FIFO_ENABLED } else { 0 };
+                id.unwrap_or(IIR_NO_INT) | fifo
+            },
+            REG_LCR => self.lcr,
+            REG_MCR => self.mcr,
+            REG_LSR => {
+                let lsr = self.lsr();
+                self.overrun = false;
+                lsr
+            },
+            REG_MSR => self.msr(),
+            REG_SCR => self.scr,
+            _ => 0,
+        };
+        Ok(value as u64)
+    }
+
+    fn write(&mut self, offset: u64, value: u64, size: u64) -> Result<(), DeviceError> {
+        if size != 1 {
+            return Err(DeviceError);
+        }
+        let value = value as u8;
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match offset {
+            REG_DATA if dlab => self.dll = value,
+            REG_DATA => self.transmit(value),
+            REG_IER if dlab => self.dlm = value,
+            REG_IER => {
+                // NOTE: ETBEI を有効にした時点で送信保持レジスタは空なので、割り込みが保留される
+                if value & IER_THRI != 0 && self.ier & IER_THRI == 0 {
+                    self.thr_pending = true;
+                }
+                self.ier = value & (IER_RDI | IER_THRI | IER_RLSI | IER_MSI);
+            },
+            REG_IIR => {
+                // NOTE: FIFO の有効・無効を切り替えると、FIFO の内容は破棄される
+                if (value ^ self.fcr) & FCR_ENABLE_FIFO != 0 || value & FCR_CLEAR_RCVR != 0 {
+                    self.rx_fifo.clear();
+                }
+                self.fcr = value & (FCR_ENABLE_FIFO | 0xC0);
+            },
+            REG_LCR => self.lcr = value,
+            REG_MCR => self.mcr = value & 0x1F,
+            REG_SCR => self.scr = value,
+            // NOTE: LSR, MSR, 予約済みのオフセットへの書き込みは無視する
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.idle_cycles = self.idle_cycles.saturating_add(1);
+
+        // NOTE: バックエンドの読み込みはシステムコールを伴うことがあるので、毎サイクルは行わない
+        if self.poll_countdown > 0 {
+            self.poll_countdown -= 1;
+            return;
+        }
+        self.poll_countdown = POLL_INTERVAL;
+        // NOTE: ループバックモードでは、バックエンドから切り離される
+        while self.mcr & MCR_LOOP == 0 && self.rx_fifo.len() < self.fifo_capacity() {
+            let Some(byte) = self.backend.read() else { break };
+            self.receive(byte);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.interrupt_id().is_some()
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, Read, Write},
+    rc::Rc,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+/// UART が送受信するバイト列の接続先
+pub trait UartBackend {
+    /// 1 バイトを送信します。
+    fn write(&mut self, byte: u8);
+
+    /// 受信したバイトがあれば、1 バイト取り出します。(ブロックしない)
+    fn read(&mut self) -> Option<u8>;
+
+    /// バイト列をまとめて送信します。
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write(byte);
+        }
+    }
+
+    /// 受信したバイト列を buf に取り出し、そのバイト数を返します。(ブロックしない)
+    fn read_bytes(&mut self, buf: &mut [u8]) -> usize {
+        buf.iter_mut().map_while(|slot| self.read().map(|byte| *slot = byte)).count()
+    }
+}
+
+/// ホストの標準入出力に接続するバックエンド
+///
+/// NOTE: 端末の raw モードへの切り替えは行わないので、入力はホストの端末で行単位にバッファリングされる
+pub struct StdioBackend {
+    /// 標準入力を読み込むスレッドから、受信したバイトを受け取る
+    input: Receiver<u8>,
+}
+impl StdioBackend {
+    /// 標準入力を読み込むスレッドを起動して、新しい StdioBackend を作成します。
+    pub fn new() -> Self {
+        let (sender, input) = mpsc::channel();
+        // NOTE: 標準入力の読み込みはブロックするので、別スレッドで読み込んでチャネルで受け渡す
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                let Ok(byte) = byte else { break };
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { input }
+    }
+}
+impl Default for StdioBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl UartBackend for StdioBackend {
+    fn write(&mut self, byte: u8) {
+        let mut stdout = io::stdout().lock();
+        // NOTE: ゲストの出力を即座に表示するため、1 バイトごとにフラッシュする
+        let _ = stdout.write_all(&[byte]);
+        let _ = stdout.flush();
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        self.input.try_recv().ok()
+    }
+}
+
+/// メモリ上のバッファに接続するバックエンド (テスト用)
+///
+/// clone したものは同じバッファを共有するので、UART に渡した後も入出力を操作できます。
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    /// ゲストが受信するバイト列
+    input: Rc<RefCell<VecDeque<u8>>>,
+    /// ゲストが送信したバイト列
+    output: Rc<RefCell<Vec<u8>>>,
+}
+impl MemoryBackend {
+    /// 空のバッファを持つ、新しい MemoryBackend を作成します。
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ゲストが受信するバイト列を追加します。
+    pub fn push_input(&self, bytes: &[u8]) {
+        self.input.borrow_mut().extend(bytes);
+    }
+
+    /// ゲストがこれまでに送信したバイト列を取り出します。
+    pub fn take_output(&self) -> Vec<u8> {
+        self.output.take()
+    }
+}
+impl UartBackend for MemoryBackend {
+    fn write(&mut self, byte: u8) {
+        self.output.borrow_mut().push(byte);
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        self.input.borrow_mut().pop_front()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.output.borrow_mut().extend_from_slice(bytes);
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> usize {
+        let mut input = self.input.borrow_mut();
+        let len = buf.len().min(input.len());
+        for (slot, byte) in buf.iter_mut().zip(input.drain(..len)) {
+            *slot = byte;
+        }
+        len
+    }
+}
+
+/// UnixSocketBackend の送信待ちのバイト列の上限
+#[cfg(unix)]
+const PENDING_MAX: usize = 64 * 1024;
+
+/// Unix ドメインソケットに接続するバックエンド
+///
+/// ソケットで待ち受け、接続してきたクライアントと送受信します。(`socat - UNIX-CONNECT:path` などで接続できる)
+/// クライアントが接続していない間の送信は破棄され、クライアントが受信しきれない送信は次の送受信の際に再送されます。
+#[cfg(unix)]
+pub struct UnixSocketBackend {
+    /// 待ち受けているソケット
+    listener: std::os::unix::net::UnixListener,
+    /// 接続中のクライアント
+    stream: Option<std::os::unix::net::UnixStream>,
+    /// クライアントがまだ受け取っていない、送信待ちのバイト列
+    pending: Vec<u8>,
+}
+#[cfg(unix)]
+impl UnixSocketBackend {
+    /// path に Unix ドメインソケットを作成して待ち受けます。
+    pub fn bind(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, stream: None, pending: Vec::new() })
+    }
+
+    /// 接続中のクライアントを取得します。(未接続であれば、新しい接続を受け付ける)
+    fn stream(&mut self) -> Option<&mut std::os::unix::net::UnixStream> {
+        if self.stream.is_none()
+            && let Ok((stream, _)) = self.listener.accept()
+            && stream.set_nonblocking(true).is_ok()
+        {
+            self.stream = Some(stream);
+        }
+        self.stream.as_mut()
+    }
+
+    /// クライアントとの接続を切り、送信待ちのバイト列を破棄します。
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.pending.clear();
+    }
+
+    /// 送信待ちのバイト列を、クライアントが受け取れるだけ送信します。
+    fn flush_pending(&mut self) {
+        while !self.pending.is_empty() {
+            let Some(stream) = self.stream.as_mut() else { return };
+            match stream.write(&self.pending) {
+                Ok(len) if len > 0 => {
+                    self.pending.drain(..len);
+                },
+                // NOTE: クライアントの受信が追いついていないだけなので、残りは次の送信や受信の際に再試行する
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                // NOTE: クライアントが切断したので、次の接続を待つ
+                _ => return self.disconnect(),
+            }
+        }
+    }
+}
+#[cfg(unix)]
+impl UartBackend for UnixSocketBackend {
+    fn write(&mut self, byte: u8) {
+        self.write_bytes(&[byte]);
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        let mut byte = [0];
+        (self.read_bytes(&mut byte) == 1).then_some(byte[0])
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.stream().is_none() {
+            return;
+        }
+        // NOTE: クライアントが受信しないままでもメモリを使い切らないよう、上限を超えた分は破棄する
+        let len = bytes.len().min(PENDING_MAX.saturating_sub(self.pending.len()));
+        self.pending.extend_from_slice(&bytes[..len]);
+        self.flush_pending();
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> usize {
+        self.flush_pending();
+        let Some(stream) = self.stream() else { return 0 };
+        match stream.read(buf) {
+            Ok(len) if len > 0 || buf.is_empty() => len,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => 0,
+            // NOTE: クライアントが切断したので、次の接続を待つ
+            _ => {
+                self.disconnect();
+                0
+            },
+        }
+    }
+}
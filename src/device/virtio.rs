@@ -0,0 +1,317 @@
+mod blk;
+mod console;
+mod net;
+#[cfg(unix)]
+mod p9;
+mod queue;
+mod rng;
+
+use crate::{bus::Dma, device::{Device, DeviceError}};
+
+pub use blk::{DiskMode, VirtioBlk};
+pub use console::VirtioConsole;
+#[cfg(unix)]
+pub use net::UnixDatagramBackend;
+pub use net::{LoopbackBackend, NetBackend, PcapBackend, VirtioNet};
+#[cfg(unix)]
+pub use p9::Virtio9p;
+pub use queue::{DescriptorChain, Virtqueue};
+pub use rng::{EntropySource, VirtioRng};
+
+// NOTE: virtio-mmio (バージョン 2) のレジスタ配置
+
+/// MagicValue: "virt"
+const REG_MAGIC_VALUE: u64 = 0x000;
+/// Version
+const REG_VERSION: u64 = 0x004;
+/// DeviceID
+const REG_DEVICE_ID: u64 = 0x008;
+/// VendorID
+const REG_VENDOR_ID: u64 = 0x00c;
+/// DeviceFeatures
+const REG_DEVICE_FEATURES: u64 = 0x010;
+/// DeviceFeaturesSel
+const REG_DEVICE_FEATURES_SEL: u64 = 0x014;
+/// DriverFeatures
+const REG_DRIVER_FEATURES: u64 = 0x020;
+/// DriverFeaturesSel
+const REG_DRIVER_FEATURES_SEL: u64 = 0x024;
+/// QueueSel
+const REG_QUEUE_SEL: u64 = 0x030;
+/// QueueNumMax
+const REG_QUEUE_NUM_MAX: u64 = 0x034;
+/// QueueNum
+const REG_QUEUE_NUM: u64 = 0x038;
+/// QueueReady
+const REG_QUEUE_READY: u64 = 0x044;
+/// QueueNotify
+const REG_QUEUE_NOTIFY: u64 = 0x050;
+/// InterruptStatus
+const REG_INTERRUPT_STATUS: u64 = 0x060;
+/// InterruptACK
+const REG_INTERRUPT_ACK: u64 = 0x064;
+/// Status
+const REG_STATUS: u64 = 0x070;
+/// QueueDescLow / QueueDescHigh
+const REG_QUEUE_DESC: u64 = 0x080;
+/// QueueDriverLow / QueueDriverHigh
+const REG_QUEUE_DRIVER: u64 = 0x090;
+/// QueueDeviceLow / QueueDeviceHigh
+const REG_QUEUE_DEVICE: u64 = 0x0a0;
+/// ConfigGeneration
+const REG_CONFIG_GENERATION: u64 = 0x0fc;
+/// デバイス固有の設定領域
+const REG_CONFIG: u64 = 0x100;
+
+/// "virt" (リトルエンディアン)
+const MAGIC_VALUE: u64 = 0x7472_6976;
+/// "QEMU" (リトルエンディアン)
+const VENDOR_ID: u64 = 0x554d_4551;
+
+/// InterruptStatus: used リングが更新された
+const INTERRUPT_USED_BUFFER: u32 = 1 << 0;
+
+/// Status: ドライバの準備が完了した
+const STATUS_DRIVER_OK: u32 = 4;
+/// Status: 機能の交渉が完了した
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// VIRTIO_F_VERSION_1: レガシーでないインターフェイス (トランスポートが常に提供する)
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+/// キューの最大サイズ
+const QUEUE_SIZE_MAX: u16 = 256;
+
+/// デバイスがホスト側から受信データを取り込む間隔 (poll の回数)
+const POLL_INTERVAL: u32 = 256;
+
+/// virtio-mmio トランスポートに接続する VirtIO デバイス
+pub trait VirtioDevice {
+    /// デバイスの種類 (Device ID) を取得します。
+    fn device_id(&self) -> u32;
+
+    /// デバイスが提供する機能ビットを取得します。(VIRTIO_F_VERSION_1 はトランスポートが追加する)
+    fn features(&self) -> u64;
+
+    /// ドライバと交渉した機能ビットを受け取ります。
+    fn set_driver_features(&mut self, _features: u64) {}
+
+    /// キューの数を取得します。
+    fn queue_count(&self) -> usize;
+
+    /// デバイス固有の設定領域のオフセット offset から size バイトを読み込みます。
+    fn read_config(&self, offset: u64, size: u64) -> u64;
+
+    /// デバイス固有の設定領域に書き込みます。(既定では無視する)
+    fn write_config(&mut self, _offset: u64, _value: u64, _size: u64) {}
+
+    /// ドライバがキュー queue にリクエストを追加したことを通知します。
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], dma: &mut Dma);
+
+    /// ホスト側からの入力などを処理します。(ドライバの準備が完了している間、毎サイクル呼ばれる)
+    fn poll(&mut self, _queues: &mut [Virtqueue], _dma: &mut Dma) {}
+
+    /// デバイスをリセットします。
+    fn reset(&mut self) {}
+}
+
+/// 設定領域の先頭から並んだバイト列 config のうち、オフセット offset から size バイトを読み込みます。
+pub(super) fn read_config_bytes(config: &[u8], offset: u64, size: u64) -> u64 {
+    (0..size).fold(0, |acc, i| {
+        let byte = config.get((offset + i) as usize).copied().unwrap_or(0);
+        acc | ((byte as u64) << (i * 8))
+    })
+}
+
+/// virtio-mmio (バージョン 2) トランスポート
+pub struct VirtioMmio {
+    /// 接続された VirtIO デバイス
+    device: Box<dyn VirtioDevice>,
+    /// キュー
+    queues: Vec<Virtqueue>,
+    /// DeviceFeaturesSel
+    device_features_sel: u32,
+    /// DriverFeaturesSel
+    driver_features_sel: u32,
+    /// ドライバが書き込んだ機能ビット
+    driver_features: u64,
+    /// QueueSel
+    queue_sel: u32,
+    /// InterruptStatus
+    interrupt_status: u32,
+    /// Status
+    status: u32,
+    /// 通知されたが、まだデバイスに伝えていないキュー
+    notified: Vec<usize>,
+}
+impl VirtioMmio {
+    /// 慣例的な配置アドレス (i 番目のデバイスは BASE + i * SIZE に配置する)
+    pub const BASE: u64 = 0x1000_1000;
+    /// アドレスマップ上の領域のサイズ
+    pub const SIZE: u64 = 0x1000;
+    /// 慣例的な割り込み番号 (i 番目のデバイスは IRQ + i を使う)
+    pub const IRQ: u32 = 1;
+
+    /// device を接続した、新しい VirtioMmio を作成します。
+    pub fn new(device: Box<dyn VirtioDevice>) -> Self {
+        let queues = (0..device.queue_count()).map(|_| Virtqueue::default()).collect();
+        Self {
+            device,
+            queues,
+            device_features_sel: 0,
+            driver_features_sel: 0,
+            driver_features: 0,
+            queue_sel: 0,
+            interrupt_status: 0,
+            status: 0,
+            notified: Vec::new(),
+        }
+    }
+
+    /// デバイスが提供する機能ビットを取得します。
+    fn device_features(&self) -> u64 {
+        self.device.features() | VIRTIO_F_VERSION_1
+    }
+
+    /// QueueSel で選択されているキューを取得します。
+    fn selected_queue(&mut self) -> Option<&mut Virtqueue> {
+        self.queues.get_mut(self.queue_sel as usize)
+    }
+
+    /// Status に書き込みます。
+    fn write_status(&mut self, value: u32) {
+        if value == 0 {
+            // NOTE: 0 の書き込みはデバイスのリセット
+            self.queues.iter_mut().for_each(|queue| *queue = Virtqueue::default());
+            self.driver_features = 0;
+            self.interrupt_status = 0;
+            self.notified.clear();
+            self.device.reset();
+            self.status = 0;
+            return;
+        }
+        let mut value = value;
+        if value & STATUS_FEATURES_OK != 0 && self.status & STATUS_FEATURES_OK == 0 {
+            // NOTE: 提供していない機能や、レガシーインターフェイスが要求された場合は FEATURES_OK を受け付けない
+            let features = self.driver_features;
+            if features & !self.device_features() != 0 || features & VIRTIO_F_VERSION_1 == 0 {
+                value &= !STATUS_FEATURES_OK;
+            } else {
+                self.device.set_driver_features(features);
+            }
+        }
+        self.status = value;
+    }
+}
+impl Device for VirtioMmio {
+    fn read(&mut self, offset: u64, size: u64) -> Result<u64, DeviceError> {
+        if offset >= REG_CONFIG {
+            return Ok(self.device.read_config(offset - REG_CONFIG, size));
+        }
+        if size != 4 {
+            return Err(DeviceError);
+        }
+        let value = match offset {
+            REG_MAGIC_VALUE => MAGIC_VALUE,
+            REG_VERSION => 2,
+            REG_DEVICE_ID => self.device.device_id() as u64,
+            REG_VENDOR_ID => VENDOR_ID,
+            REG_DEVICE_FEATURES => match self.device_features_sel {
+                0 => self.device_features() & 0xFFFF_FFFF,
+                1 => self.device_features() >> 32,
+                _ => 0,
+            },
+            REG_QUEUE_NUM_MAX => self.selected_queue().map_or(0, |_| QUEUE_SIZE_MAX as u64),
+            REG_QUEUE_READY => self.selected_queue().map_or(0, |queue| queue.ready as u64),
+            REG_INTERRUPT_STATUS => self.interrupt_status as u64,
+            REG_STATUS => self.status as u64,
+            REG_CONFIG_GENERATION => 0,
+            _ => 0,
+        };
+        Ok(value)
+    }
+
+    fn write(&mut self, offset: u64, value: u64, size: u64) -> Result<(), DeviceError> {
+        if offset >= REG_CONFIG {
+            self.device.write_config(offset - REG_CONFIG, value, size);
+            return Ok(());
+        }
+        if size != 4 {
+            return Err(DeviceError);
+        }
+        let value = value as u32;
+        // NOTE: 64bit のアドレスは Low / High の 2 つのレジスタに分けて書き込まれる
+        let set_half = |addr: &mut u64, offset: u64| {
+            let shift = if offset % 8 == 4 { 32 } else { 0 };
+            *addr = (*addr & !(0xFFFF_FFFF << shift)) | ((value as u64) << shift);
+        };
+        match offset {
+            REG_DEVICE_FEATURES_SEL => self.device_features_sel = value,
+            REG_DRIVER_FEATURES => match self.driver_features_sel {
+                0 => self.driver_features = (self.driver_features & !0xFFFF_FFFF) | value as u64,
+                1 => self.driver_features = (self.driver_features & 0xFFFF_FFFF) | ((value as u64) << 32),
+                _ => {},
+            },
+            REG_DRIVER_FEATURES_SEL => self.driver_features_sel = value,
+            REG_QUEUE_SEL => self.queue_sel = value,
+            REG_QUEUE_NUM => {
+                if let Some(queue) = self.selected_queue() {
+                    // NOTE: キューのサイズは 2 の冪乗で、最大サイズ以下でなければならない
+                    if value.is_power_of_two() && value <= QUEUE_SIZE_MAX as u32 {
+                        queue.size = value as u16;
+                    }
+                }
+            },
+            REG_QUEUE_READY => {
+                if let Some(queue) = self.selected_queue() {
+                    queue.ready = value & 1 != 0;
+                }
+            },
+            REG_QUEUE_NOTIFY => {
+                let queue = value as usize;
+                if queue < self.queues.len() && !self.notified.contains(&queue) {
+                    self.notified.push(queue);
+                }
+            },
+            REG_INTERRUPT_ACK => self.interrupt_status &= !value,
+            REG_STATUS => self.write_status(value),
+            REG_QUEUE_DESC | 0x084 => {
+                if let Some(queue) = self.selected_queue() {
+                    set_half(&mut queue.desc, offset);
+                }
+            },
+            REG_QUEUE_DRIVER | 0x094 => {
+                if let Some(queue) = self.selected_queue() {
+                    set_half(&mut queue.avail, offset);
+                }
+            },
+            REG_QUEUE_DEVICE | 0x0a4 => {
+                if let Some(queue) = self.selected_queue() {
+                    set_half(&mut queue.used, offset);
+                }
+            },
+            // NOTE: 読み取り専用のレジスタや、予約済みのオフセットへの書き込みは無視する
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn dma(&mut self, dma: &mut Dma) {
+        if self.status & STATUS_DRIVER_OK == 0 {
+            return;
+        }
+        // NOTE: QueueNotify の書き込みではメモリにアクセスできないので、ここでまとめて処理する
+        for queue in std::mem::take(&mut self.notified) {
+            self.device.notify(queue, &mut self.queues, dma);
+        }
+        self.device.poll(&mut self.queues, dma);
+
+        if self.queues.iter_mut().fold(false, |acc, queue| queue.take_interrupt() | acc) {
+            self.interrupt_status |= INTERRUPT_USED_BUFFER;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.interrupt_status != 0
+    }
+}
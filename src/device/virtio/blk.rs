@@ -0,0 +1,186 @@
+use std::{collections::HashMap, fs::{File, OpenOptions}, io::{self, Read, Seek, SeekFrom, Write}, path::Path};
+
+use crate::bus::Dma;
+
+use super::{DescriptorChain, VirtioDevice, Virtqueue, read_config_bytes};
+
+/// virtio-blk の Device ID
+const DEVICE_ID: u32 = 2;
+
+/// VIRTIO_BLK_F_RO: 読み取り専用のデバイス
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+/// VIRTIO_BLK_F_FLUSH: キャッシュのフラッシュをサポートする
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+
+/// リクエストの種類: 読み込み
+const VIRTIO_BLK_T_IN: u32 = 0;
+/// リクエストの種類: 書き込み
+const VIRTIO_BLK_T_OUT: u32 = 1;
+/// リクエストの種類: フラッシュ
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+/// リクエストの種類: デバイスの ID の取得
+const VIRTIO_BLK_T_GET_ID: u32 = 8;
+
+/// リクエストの結果: 成功
+const VIRTIO_BLK_S_OK: u8 = 0;
+/// リクエストの結果: I/O エラー
+const VIRTIO_BLK_S_IOERR: u8 = 1;
+/// リクエストの結果: サポートしていないリクエスト
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+/// セクタのサイズ
+const SECTOR_SIZE: usize = 512;
+/// リクエストのヘッダ (type, reserved, sector) のサイズ
+const HEADER_SIZE: usize = 16;
+/// GET_ID で返す ID のサイズ
+const ID_SIZE: usize = 20;
+
+/// ディスクイメージへの書き込みの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskMode {
+    /// ディスクイメージに直接書き込む
+    ReadWrite,
+    /// 書き込みを許可しない (ゲストには読み取り専用のデバイスとして見せる)
+    ReadOnly,
+    /// 書き込みをメモリ上のオーバーレイに保持し、ディスクイメージは変更しない
+    CopyOnWrite,
+}
+
+/// ホストの raw ディスクイメージを使う virtio-blk デバイス
+pub struct VirtioBlk {
+    /// ディスクイメージ
+    file: File,
+    /// 書き込みの扱い
+    mode: DiskMode,
+    /// 容量 (セクタ数)
+    capacity: u64,
+    /// CopyOnWrite で書き込まれたセクタ
+    overlay: HashMap<u64, [u8; SECTOR_SIZE]>,
+}
+impl VirtioBlk {
+    /// path のディスクイメージを開いて、新しい VirtioBlk を作成します。
+    ///
+    /// ディスクイメージのサイズの端数 (512 バイト未満) は使用しません。
+    pub fn open(path: impl AsRef<Path>, mode: DiskMode) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(mode == DiskMode::ReadWrite).open(path)?;
+        let capacity = file.metadata()?.len() / SECTOR_SIZE as u64;
+        Ok(Self { file, mode, capacity, overlay: HashMap::new() })
+    }
+
+    /// 容量 (セクタ数) を取得します。
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// セクタ sector から buf の長さ分を読み込みます。
+    fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<()> {
+        for (i, chunk) in buf.chunks_mut(SECTOR_SIZE).enumerate() {
+            let sector = sector + i as u64;
+            if let Some(data) = self.overlay.get(&sector) {
+                chunk.copy_from_slice(data);
+            } else {
+                self.file.seek(SeekFrom::Start(sector * SECTOR_SIZE as u64))?;
+                self.file.read_exact(chunk)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// セクタ sector から data を書き込みます。
+    fn write_sectors(&mut self, sector: u64, data: &[u8]) -> io::Result<()> {
+        match self.mode {
+            DiskMode::ReadWrite => {
+                self.file.seek(SeekFrom::Start(sector * SECTOR_SIZE as u64))?;
+                self.file.write_all(data)
+            },
+            DiskMode::ReadOnly => Err(io::ErrorKind::PermissionDenied.into()),
+            DiskMode::CopyOnWrite => {
+                for (i, chunk) in data.chunks(SECTOR_SIZE).enumerate() {
+                    self.overlay.insert(sector + i as u64, chunk.try_into().unwrap());
+                }
+                Ok(())
+            },
+        }
+    }
+
+    /// リクエストを処理し、デバイスが書き込むバッファに返すデータと、結果のステータスを取得します。
+    fn handle(&mut self, dma: &mut Dma, chain: &DescriptorChain) -> (Vec<u8>, u8) {
+        // NOTE: 最後の 1 バイトがステータスで、その前が読み込んだデータなどを返す領域
+        let len = chain.writable_len().saturating_sub(1);
+        match chain.read(dma) {
+            Some(request) if request.len() >= HEADER_SIZE => {
+                let kind = u32::from_le_bytes(request[0..4].try_into().unwrap());
+                let sector = u64::from_le_bytes(request[8..16].try_into().unwrap());
+                self.execute(kind, sector, &request[HEADER_SIZE..], len)
+            },
+            _ => (Vec::new(), VIRTIO_BLK_S_IOERR),
+        }
+    }
+
+    /// 種類 kind のリクエストを実行し、最大 len バイトの返すデータと、結果のステータスを取得します。
+    fn execute(&mut self, kind: u32, sector: u64, data: &[u8], len: usize) -> (Vec<u8>, u8) {
+        // NOTE: 転送するデータはセクタ単位で、ディスクの範囲内に収まっていなければならない
+        let in_range = |len: usize| {
+            len.is_multiple_of(SECTOR_SIZE) && sector.checked_add((len / SECTOR_SIZE) as u64).is_some_and(|end| end <= self.capacity)
+        };
+        let mut response = Vec::new();
+        let result = match kind {
+            VIRTIO_BLK_T_IN if in_range(len) => {
+                // NOTE: 範囲を確認してから確保するので、ディスクより大きな領域は確保しない
+                response = vec![0; len];
+                self.read_sectors(sector, &mut response)
+            },
+            VIRTIO_BLK_T_OUT if in_range(data.len()) => self.write_sectors(sector, data),
+            VIRTIO_BLK_T_IN | VIRTIO_BLK_T_OUT => return (response, VIRTIO_BLK_S_IOERR),
+            VIRTIO_BLK_T_FLUSH => match self.mode {
+                DiskMode::ReadWrite => self.file.sync_data(),
+                _ => Ok(()),
+            },
+            VIRTIO_BLK_T_GET_ID => {
+                let id = b"riscv-emu";
+                response = vec![0; len.min(ID_SIZE)];
+                let len = response.len().min(id.len());
+                response[..len].copy_from_slice(&id[..len]);
+                Ok(())
+            },
+            _ => return (response, VIRTIO_BLK_S_UNSUPP),
+        };
+        match result {
+            Ok(()) => (response, VIRTIO_BLK_S_OK),
+            Err(_) => (response, VIRTIO_BLK_S_IOERR),
+        }
+    }
+}
+impl VirtioDevice for VirtioBlk {
+    fn device_id(&self) -> u32 {
+        DEVICE_ID
+    }
+
+    fn features(&self) -> u64 {
+        match self.mode {
+            DiskMode::ReadOnly => VIRTIO_BLK_F_RO | VIRTIO_BLK_F_FLUSH,
+            _ => VIRTIO_BLK_F_FLUSH,
+        }
+    }
+
+    fn queue_count(&self) -> usize {
+        1
+    }
+
+    fn read_config(&self, offset: u64, size: u64) -> u64 {
+        // NOTE: 設定領域の先頭は容量 (セクタ数) で、それ以降のフィールドは提供しない
+        read_config_bytes(&self.capacity.to_le_bytes(), offset, size)
+    }
+
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], dma: &mut Dma) {
+        let queue = &mut queues[queue];
+        while let Some(chain) = queue.pop(dma) {
+            let (response, status) = self.handle(dma, &chain);
+            let len = match chain.writable_len().checked_sub(1) {
+                Some(offset) => chain.write(dma, &response).unwrap_or(0) + chain.write_at(dma, offset, &[status]).unwrap_or(0),
+                None => 0,
+            };
+            queue.push(dma, chain, len as u32);
+        }
+    }
+}
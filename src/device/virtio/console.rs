@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+
+use crate::{bus::Dma, device::UartBackend};
+
+use super::{POLL_INTERVAL, VirtioDevice, Virtqueue, read_config_bytes};
+
+/// virtio-console の Device ID
+const DEVICE_ID: u32 = 3;
+
+/// VIRTIO_CONSOLE_F_MULTIPORT: 複数のポートと制御キューをサポートする
+const VIRTIO_CONSOLE_F_MULTIPORT: u64 = 1 << 1;
+/// VIRTIO_CONSOLE_F_EMERG_WRITE: 設定領域の emerg_wr への書き込みで出力できる
+const VIRTIO_CONSOLE_F_EMERG_WRITE: u64 = 1 << 2;
+
+/// 制御メッセージ: ドライバの準備が完了した (ドライバ -> デバイス)
+const VIRTIO_CONSOLE_DEVICE_READY: u16 = 0;
+/// 制御メッセージ: ポートを追加する (デバイス -> ドライバ)
+const VIRTIO_CONSOLE_DEVICE_ADD: u16 = 1;
+/// 制御メッセージ: ポートの準備が完了した (ドライバ -> デバイス)
+const VIRTIO_CONSOLE_PORT_READY: u16 = 3;
+/// 制御メッセージ: ポートをコンソールとして使う (デバイス -> ドライバ)
+const VIRTIO_CONSOLE_CONSOLE_PORT: u16 = 4;
+/// 制御メッセージ: ポートが開かれた / 閉じられた (双方向)
+const VIRTIO_CONSOLE_PORT_OPEN: u16 = 6;
+/// 制御メッセージ: ポートの名前 (デバイス -> ドライバ)
+const VIRTIO_CONSOLE_PORT_NAME: u16 = 7;
+
+/// 制御メッセージ (id, event, value) のサイズ
+const CONTROL_SIZE: usize = 8;
+/// 設定領域の emerg_wr のオフセット
+const CONFIG_EMERG_WR: u64 = 8;
+/// 1 回のポーリングでホストから読み込む最大のバイト数
+const READ_CHUNK: usize = 4096;
+
+/// 受信キュー (ポート 0)
+const QUEUE_PORT0_RX: usize = 0;
+/// 送信キュー (ポート 0)
+const QUEUE_PORT0_TX: usize = 1;
+/// 制御メッセージの受信キュー
+const QUEUE_CONTROL_RX: usize = 2;
+/// 制御メッセージの送信キュー
+const QUEUE_CONTROL_TX: usize = 3;
+
+/// ポート
+struct Port {
+    /// ゲストに伝えるポートの名前
+    name: Option<String>,
+    /// ホスト側の接続先
+    backend: Box<dyn UartBackend>,
+    /// ホストから受信し、まだゲストに渡していないバイト列
+    input: VecDeque<u8>,
+}
+
+/// virtio-console デバイス
+///
+/// ポート 0 はコンソールで、`add_port` で名前付きのポートを追加できます。(ドライバが MULTIPORT を受け入れた場合のみ使える)
+/// ポートの送受信は、ホスト側では UART と同じ `UartBackend` に接続します。
+pub struct VirtioConsole {
+    /// ポート
+    ports: Vec<Port>,
+    /// ドライバが MULTIPORT を受け入れたか
+    multiport: bool,
+    /// ドライバに送る制御メッセージ
+    control: VecDeque<Vec<u8>>,
+    /// 次にホストから受信データを取り込むまでの poll の回数
+    poll_countdown: u32,
+}
+impl VirtioConsole {
+    /// console をポート 0 (コンソール) に接続した、新しい VirtioConsole を作成します。
+    pub fn new(console: Box<dyn UartBackend>) -> Self {
+        Self {
+            ports: vec![Port { name: None, backend: console, input: VecDeque::new() }],
+            multiport: false,
+            control: VecDeque::new(),
+            poll_countdown: 0,
+        }
+    }
+
+    /// backend に接続した、名前 name のポートを追加し、その番号を返します。
+    ///
+    /// NOTE: キューの数が変わるので、VirtioMmio に接続する前に追加しなければならない
+    pub fn add_port(&mut self, name: impl Into<String>, backend: Box<dyn UartBackend>) -> u32 {
+        self.ports.push(Port { name: Some(name.into()), backend, input: VecDeque::new() });
+        self.ports.len() as u32 - 1
+    }
+
+    /// ポート port の受信キューの番号を取得します。(送信キューはその次の番号)
+    fn receive_queue(port: usize) -> usize {
+        match port {
+            0 => QUEUE_PORT0_RX,
+            _ => 2 + port * 2,
+        }
+    }
+
+    /// ドライバに制御メッセージを送ります。
+    fn send_control(&mut self, id: u32, event: u16, value: u16, data: &[u8]) {
+        let mut message = Vec::with_capacity(CONTROL_SIZE + data.len());
+        message.extend_from_slice(&id.to_le_bytes());
+        message.extend_from_slice(&event.to_le_bytes());
+        message.extend_from_slice(&value.to_le_bytes());
+        message.extend_from_slice(data);
+        self.control.push_back(message);
+    }
+
+    /// ドライバから受け取った制御メッセージを処理します。
+    fn handle_control(&mut self, message: &[u8]) {
+        let Some(message) = message.get(..CONTROL_SIZE) else { return };
+        let id = u32::from_le_bytes(message[0..4].try_into().unwrap());
+        let event = u16::from_le_bytes(message[4..6].try_into().unwrap());
+        let value = u16::from_le_bytes(message[6..8].try_into().unwrap());
+        match event {
+            VIRTIO_CONSOLE_DEVICE_READY if value == 1 => {
+                for port in 0..self.ports.len() {
+                    self.send_control(port as u32, VIRTIO_CONSOLE_DEVICE_ADD, 1, &[]);
+                }
+            },
+            VIRTIO_CONSOLE_PORT_READY if value == 1 && (id as usize) < self.ports.len() => {
+                if id == 0 {
+                    self.send_control(id, VIRTIO_CONSOLE_CONSOLE_PORT, 1, &[]);
+                }
+                if let Some(name) = self.ports[id as usize].name.clone() {
+                    self.send_control(id, VIRTIO_CONSOLE_PORT_NAME, 1, name.as_bytes());
+                }
+                // NOTE: ホスト側は常に接続しているものとして扱う
+                self.send_control(id, VIRTIO_CONSOLE_PORT_OPEN, 1, &[]);
+            },
+            // NOTE: ゲストがポートを開いた / 閉じたことの通知などは使わない
+            _ => {},
+        }
+    }
+}
+impl VirtioDevice for VirtioConsole {
+    fn device_id(&self) -> u32 {
+        DEVICE_ID
+    }
+
+    fn features(&self) -> u64 {
+        VIRTIO_CONSOLE_F_MULTIPORT | VIRTIO_CONSOLE_F_EMERG_WRITE
+    }
+
+    fn set_driver_features(&mut self, features: u64) {
+        self.multiport = features & VIRTIO_CONSOLE_F_MULTIPORT != 0;
+    }
+
+    fn queue_count(&self) -> usize {
+        // NOTE: ポート 0 の 2 つ, 制御キューの 2 つ, 追加したポートごとに 2 つ
+        2 + self.ports.len() * 2
+    }
+
+    fn read_config(&self, offset: u64, size: u64) -> u64 {
+        // NOTE: cols (u16), rows (u16), max_nr_ports (u32), emerg_wr (u32)
+        let mut config = [0; 12];
+        config[4..8].copy_from_slice(&(self.ports.len() as u32).to_le_bytes());
+        read_config_bytes(&config, offset, size)
+    }
+
+    fn write_config(&mut self, offset: u64, value: u64, _size: u64) {
+        if offset == CONFIG_EMERG_WR {
+            self.ports[0].backend.write(value as u8);
+        }
+    }
+
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], dma: &mut Dma) {
+        if queue == QUEUE_CONTROL_TX {
+            while let Some(chain) = queues[queue].pop(dma) {
+                if let Some(message) = chain.read(dma) {
+                    self.handle_control(&message);
+                }
+                queues[queue].push(dma, chain, 0);
+            }
+            return;
+        }
+        // NOTE: 送信キューの番号は奇数で、受信キューへの通知はバッファの追加なので poll で処理する
+        let port = match queue {
+            QUEUE_PORT0_TX => 0,
+            _ if queue > QUEUE_CONTROL_TX && queue % 2 == 1 => (queue - 2) / 2,
+            _ => return,
+        };
+        if port > 0 && !self.multiport {
+            return;
+        }
+        while let Some(chain) = queues[queue].pop(dma) {
+            if let Some(data) = chain.read(dma) {
+                self.ports[port].backend.write_bytes(&data);
+            }
+            queues[queue].push(dma, chain, 0);
+        }
+    }
+
+    fn poll(&mut self, queues: &mut [Virtqueue], dma: &mut Dma) {
+        if self.multiport {
+            while !self.control.is_empty() {
+                let Some(chain) = queues[QUEUE_CONTROL_RX].pop(dma) else { break };
+                let message = self.control.pop_front().unwrap();
+                let len = chain.write(dma, &message).unwrap_or(0);
+                queues[QUEUE_CONTROL_RX].push(dma, chain, len as u32);
+            }
+        }
+
+        // NOTE: バックエンドの読み込みはシステムコールを伴うことがあるので、毎サイクルは行わない
+        let read = self.poll_countdown == 0;
+        self.poll_countdown = if read { POLL_INTERVAL } else { self.poll_countdown - 1 };
+
+        let ports = if self.multiport { self.ports.len() } else { 1 };
+        for (i, port) in self.ports.iter_mut().take(ports).enumerate() {
+            if read && port.input.is_empty() {
+                let mut buf = [0; READ_CHUNK];
+                let len = port.backend.read_bytes(&mut buf);
+                port.input.extend(&buf[..len]);
+            }
+            let rx = Self::receive_queue(i);
+            while !port.input.is_empty() {
+                let Some(chain) = queues[rx].pop(dma) else { break };
+                let data: Vec<u8> = port.input.iter().take(chain.writable_len()).copied().collect();
+                let len = chain.write(dma, &data).unwrap_or(0);
+                port.input.drain(..len);
+                queues[rx].push(dma, chain, len as u32);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.multiport = false;
+        self.control.clear();
+        self.poll_countdown = 0;
+    }
+}
@@ -0,0 +1,112 @@
+mod backend;
+
+#[cfg(unix)]
+pub use backend::UnixDatagramBackend;
+pub use backend::{LoopbackBackend, NetBackend, PcapBackend};
+
+use crate::bus::Dma;
+
+use super::{POLL_INTERVAL, VirtioDevice, Virtqueue, read_config_bytes};
+
+/// virtio-net の Device ID
+const DEVICE_ID: u32 = 1;
+
+/// VIRTIO_NET_F_MAC: 設定領域に MAC アドレスがある
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+/// VIRTIO_NET_F_STATUS: 設定領域にリンクの状態がある
+const VIRTIO_NET_F_STATUS: u64 = 1 << 16;
+
+/// 設定領域の status: リンクが接続されている
+const VIRTIO_NET_S_LINK_UP: u16 = 1;
+
+/// フレームの前に付くヘッダ (virtio_net_hdr) のサイズ
+const HEADER_SIZE: usize = 12;
+/// ヘッダの num_buffers のオフセット
+const HEADER_NUM_BUFFERS: usize = 10;
+
+/// 受信キュー
+const QUEUE_RX: usize = 0;
+/// 送信キュー
+const QUEUE_TX: usize = 1;
+
+/// virtio-net デバイス
+///
+/// チェックサムのオフロードなどは提供せず、Ethernet フレームをそのまま送受信します。
+pub struct VirtioNet {
+    /// MAC アドレス
+    mac: [u8; 6],
+    /// フレームの接続先
+    backend: Box<dyn NetBackend>,
+    /// 受信したが、まだゲストに渡していないフレーム
+    pending: Option<Vec<u8>>,
+    /// 次にバックエンドから受信するまでの poll の回数
+    poll_countdown: u32,
+}
+impl VirtioNet {
+    /// MAC アドレス mac を持ち、backend に接続された、新しい VirtioNet を作成します。
+    pub fn new(mac: [u8; 6], backend: Box<dyn NetBackend>) -> Self {
+        Self { mac, backend, pending: None, poll_countdown: 0 }
+    }
+}
+impl VirtioDevice for VirtioNet {
+    fn device_id(&self) -> u32 {
+        DEVICE_ID
+    }
+
+    fn features(&self) -> u64 {
+        VIRTIO_NET_F_MAC | VIRTIO_NET_F_STATUS
+    }
+
+    fn queue_count(&self) -> usize {
+        2
+    }
+
+    fn read_config(&self, offset: u64, size: u64) -> u64 {
+        // NOTE: mac[6], status (u16)
+        let mut config = self.mac.to_vec();
+        config.extend_from_slice(&VIRTIO_NET_S_LINK_UP.to_le_bytes());
+        read_config_bytes(&config, offset, size)
+    }
+
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], dma: &mut Dma) {
+        // NOTE: 受信キューへの通知はバッファの追加なので poll で処理する
+        if queue != QUEUE_TX {
+            return;
+        }
+        let queue = &mut queues[queue];
+        while let Some(chain) = queue.pop(dma) {
+            if let Some(data) = chain.read(dma)
+                && let Some(frame) = data.get(HEADER_SIZE..)
+            {
+                self.backend.send(frame);
+            }
+            queue.push(dma, chain, 0);
+        }
+    }
+
+    fn poll(&mut self, queues: &mut [Virtqueue], dma: &mut Dma) {
+        // NOTE: バックエンドの受信はシステムコールを伴うことがあるので、毎サイクルは行わない
+        let receive = self.poll_countdown == 0;
+        self.poll_countdown = if receive { POLL_INTERVAL } else { self.poll_countdown - 1 };
+
+        loop {
+            if self.pending.is_none() && receive {
+                self.pending = self.backend.receive();
+            }
+            let Some(frame) = &self.pending else { break };
+            let Some(chain) = queues[QUEUE_RX].pop(dma) else { break };
+            // NOTE: オフロードは使わないので、ヘッダは num_buffers = 1 以外すべて 0
+            let mut data = vec![0; HEADER_SIZE];
+            data[HEADER_NUM_BUFFERS] = 1;
+            data.extend_from_slice(frame);
+            let len = chain.write(dma, &data).unwrap_or(0);
+            queues[QUEUE_RX].push(dma, chain, len as u32);
+            self.pending = None;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pending = None;
+        self.poll_countdown = 0;
+    }
+}
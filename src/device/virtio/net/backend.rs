@@ -0,0 +1,134 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// virtio-net が送受信する Ethernet フレームの接続先
+pub trait NetBackend {
+    /// フレームを 1 つ送信します。
+    fn send(&mut self, frame: &[u8]);
+
+    /// 受信したフレームがあれば、1 つ取り出します。(ブロックしない)
+    fn receive(&mut self) -> Option<Vec<u8>>;
+}
+
+/// 同じプロセス内の 2 台のマシンを直結するバックエンド
+///
+/// `pair` で作成した一方が送信したフレームを、もう一方が受信します。
+pub struct LoopbackBackend {
+    /// 相手が受信するフレーム
+    outgoing: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    /// 相手から受信したフレーム
+    incoming: Rc<RefCell<VecDeque<Vec<u8>>>>,
+}
+impl LoopbackBackend {
+    /// 互いに接続された、新しい LoopbackBackend の組を作成します。
+    pub fn pair() -> (Self, Self) {
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+        (Self { outgoing: a.clone(), incoming: b.clone() }, Self { outgoing: b, incoming: a })
+    }
+}
+impl NetBackend for LoopbackBackend {
+    fn send(&mut self, frame: &[u8]) {
+        self.outgoing.borrow_mut().push_back(frame.to_vec());
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        self.incoming.borrow_mut().pop_front()
+    }
+}
+
+/// Unix ドメインのデータグラムソケットで、別のエミュレータのプロセスと接続するバックエンド
+///
+/// 1 つのデータグラムが 1 つのフレームに対応します。相手のソケットが存在しない間の送信は破棄されます。
+#[cfg(unix)]
+pub struct UnixDatagramBackend {
+    /// 自分のソケット
+    socket: std::os::unix::net::UnixDatagram,
+    /// 相手のソケットのパス
+    peer: std::path::PathBuf,
+    /// 受信に使うバッファ (受信のたびに確保しないよう使い回す)
+    buf: Box<[u8]>,
+}
+#[cfg(unix)]
+impl UnixDatagramBackend {
+    /// local にソケットを作成し、peer のソケットと送受信します。
+    pub fn bind(local: impl AsRef<Path>, peer: impl AsRef<Path>) -> io::Result<Self> {
+        let socket = std::os::unix::net::UnixDatagram::bind(local)?;
+        socket.set_nonblocking(true)?;
+        // NOTE: Ethernet フレームは最大でも 64KiB に収まる
+        Ok(Self { socket, peer: peer.as_ref().to_path_buf(), buf: vec![0; 65536].into_boxed_slice() })
+    }
+}
+#[cfg(unix)]
+impl NetBackend for UnixDatagramBackend {
+    fn send(&mut self, frame: &[u8]) {
+        let _ = self.socket.send_to(frame, &self.peer);
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        let len = self.socket.recv(&mut self.buf).ok()?;
+        Some(self.buf[..len].to_vec())
+    }
+}
+
+/// pcap ファイルに記録する、1 つのフレームの最大バイト数 (snaplen)
+const PCAP_SNAPLEN: usize = 65535;
+
+/// 送受信したフレームを pcap 形式のファイルに記録するバックエンド
+///
+/// フレームはそのまま内側のバックエンドに受け渡します。記録したファイルは Wireshark などで読み込めます。
+pub struct PcapBackend {
+    /// 内側のバックエンド
+    inner: Box<dyn NetBackend>,
+    /// pcap ファイル
+    file: BufWriter<File>,
+}
+impl PcapBackend {
+    /// path に pcap ファイルを作成し、inner との送受信を記録します。
+    pub fn create(inner: Box<dyn NetBackend>, path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        // NOTE: magic, version 2.4, thiszone, sigfigs, snaplen, network (1 = Ethernet)
+        file.write_all(&0xa1b2_c3d4u32.to_le_bytes())?;
+        file.write_all(&2u16.to_le_bytes())?;
+        file.write_all(&4u16.to_le_bytes())?;
+        file.write_all(&[0; 8])?;
+        file.write_all(&(PCAP_SNAPLEN as u32).to_le_bytes())?;
+        file.write_all(&1u32.to_le_bytes())?;
+        file.flush()?;
+        Ok(Self { inner, file })
+    }
+
+    /// フレームを 1 つ記録します。
+    ///
+    /// snaplen を超える部分は記録せず、元の長さのみを記録します。
+    fn record(&mut self, frame: &[u8]) -> io::Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let captured = &frame[..frame.len().min(PCAP_SNAPLEN)];
+        self.file.write_all(&(now.as_secs() as u32).to_le_bytes())?;
+        self.file.write_all(&now.subsec_micros().to_le_bytes())?;
+        self.file.write_all(&(captured.len() as u32).to_le_bytes())?;
+        self.file.write_all(&(frame.len() as u32).to_le_bytes())?;
+        self.file.write_all(captured)?;
+        // NOTE: エミュレータが終了しても記録が残るよう、フレームごとにフラッシュする
+        self.file.flush()
+    }
+}
+impl NetBackend for PcapBackend {
+    fn send(&mut self, frame: &[u8]) {
+        let _ = self.record(frame);
+        self.inner.send(frame);
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        let frame = self.inner.receive()?;
+        let _ = self.record(&frame);
+        Some(frame)
+    }
+}
@@ -0,0 +1,705 @@
+mod message;
+
+use std::{
+    collections::HashMap,
+    fs::{self, DirBuilder, File, FileTimes, OpenOptions, Permissions},
+    io,
+    os::unix::fs::{DirBuilderExt, FileExt, MetadataExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::bus::Dma;
+
+use super::{VirtioDevice, Virtqueue, read_config_bytes};
+use message::{Qid, Reader, Writer};
+
+/// virtio-9p の Device ID
+const DEVICE_ID: u32 = 9;
+
+/// VIRTIO_9P_MOUNT_TAG: 設定領域にマウントタグがある
+const VIRTIO_9P_MOUNT_TAG: u64 = 1 << 0;
+
+// NOTE: 9P2000.L のメッセージの種類 (応答は要求 + 1)
+
+/// Rlerror: エラー
+const RLERROR: u8 = 7;
+/// Tlopen: ファイルを開く
+const TLOPEN: u8 = 12;
+/// Tlcreate: ファイルを作成して開く
+const TLCREATE: u8 = 14;
+/// Trename: ファイルの名前を変更する
+const TRENAME: u8 = 20;
+/// Treadlink: シンボリックリンクの内容を読み込む
+const TREADLINK: u8 = 22;
+/// Tgetattr: ファイルの属性を取得する
+const TGETATTR: u8 = 24;
+/// Tsetattr: ファイルの属性を変更する
+const TSETATTR: u8 = 26;
+/// Treaddir: ディレクトリのエントリを読み込む
+const TREADDIR: u8 = 40;
+/// Tfsync: ファイルの内容をストレージに書き出す
+const TFSYNC: u8 = 50;
+/// Tmkdir: ディレクトリを作成する
+const TMKDIR: u8 = 72;
+/// Trenameat: ディレクトリとエントリ名を指定して名前を変更する
+const TRENAMEAT: u8 = 74;
+/// Tunlinkat: ディレクトリとエントリ名を指定して削除する
+const TUNLINKAT: u8 = 76;
+/// Tversion: プロトコルのバージョンを交渉する
+const TVERSION: u8 = 100;
+/// Tattach: ルートディレクトリに fid を割り当てる
+const TATTACH: u8 = 104;
+/// Tflush: 処理中の要求を取り消す
+const TFLUSH: u8 = 108;
+/// Twalk: パスをたどって fid を割り当てる
+const TWALK: u8 = 110;
+/// Tread: ファイルを読み込む
+const TREAD: u8 = 116;
+/// Twrite: ファイルに書き込む
+const TWRITE: u8 = 118;
+/// Tclunk: fid を解放する
+const TCLUNK: u8 = 120;
+
+/// サポートするプロトコルのバージョン
+const VERSION: &str = "9P2000.L";
+/// メッセージの最大サイズ
+const MSIZE_MAX: u32 = 512 * 1024;
+/// Rread, Rreaddir のヘッダ (size[4] type[1] tag[2] count[4]) のサイズ
+const IO_HEADER_SIZE: u32 = 11;
+/// 1 回の Twalk でたどれる最大の要素数
+const MAXWELEM: usize = 16;
+
+/// Tlopen, Tlcreate の flags: アクセスモード
+const O_ACCMODE: u32 = 0o3;
+/// アクセスモード: 書き込みのみ
+const O_WRONLY: u32 = 0o1;
+/// アクセスモード: 読み書き
+const O_RDWR: u32 = 0o2;
+/// Tlcreate の flags: 既に存在する場合は失敗する
+const O_EXCL: u32 = 0o200;
+/// Tlopen, Tlcreate の flags: 長さを 0 にする
+const O_TRUNC: u32 = 0o1000;
+
+/// Tunlinkat の flags: ディレクトリを削除する
+const AT_REMOVEDIR: u32 = 0x200;
+
+/// Tgetattr の応答で有効なフィールド (P9_GETATTR_BASIC)
+const GETATTR_BASIC: u64 = 0x7ff;
+
+/// Tsetattr の valid: mode
+const SETATTR_MODE: u32 = 1 << 0;
+/// Tsetattr の valid: uid
+const SETATTR_UID: u32 = 1 << 1;
+/// Tsetattr の valid: gid
+const SETATTR_GID: u32 = 1 << 2;
+/// Tsetattr の valid: size
+const SETATTR_SIZE: u32 = 1 << 3;
+/// Tsetattr の valid: atime (ATIME_SET がなければ現在時刻)
+const SETATTR_ATIME: u32 = 1 << 4;
+/// Tsetattr の valid: mtime (MTIME_SET がなければ現在時刻)
+const SETATTR_MTIME: u32 = 1 << 5;
+/// Tsetattr の valid: atime に指定された時刻を使う
+const SETATTR_ATIME_SET: u32 = 1 << 7;
+/// Tsetattr の valid: mtime に指定された時刻を使う
+const SETATTR_MTIME_SET: u32 = 1 << 8;
+
+/// Treaddir のエントリの type: ディレクトリ
+const DT_DIR: u8 = 4;
+/// Treaddir のエントリの type: 通常のファイル
+const DT_REG: u8 = 8;
+/// Treaddir のエントリの type: シンボリックリンク
+const DT_LNK: u8 = 10;
+
+/// Rlerror で返すエラー番号 (Linux の errno)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Errno(u32);
+impl Errno {
+    const ENOENT: Self = Self(2);
+    const EIO: Self = Self(5);
+    const EBADF: Self = Self(9);
+    const EEXIST: Self = Self(17);
+    const ENOTDIR: Self = Self(20);
+    const EINVAL: Self = Self(22);
+    const EROFS: Self = Self(30);
+    const ELOOP: Self = Self(40);
+    const EOPNOTSUPP: Self = Self(95);
+}
+impl From<io::Error> for Errno {
+    fn from(error: io::Error) -> Self {
+        Self(error.raw_os_error().map_or(Self::EIO.0, |errno| errno as u32))
+    }
+}
+
+/// 要求の処理結果 (応答の本体, またはエラー番号)
+type Response = Result<Writer, Errno>;
+
+/// ディレクトリのエントリ
+struct DirEntry {
+    /// エントリの qid
+    qid: Qid,
+    /// エントリの種類 (DT_*)
+    kind: u8,
+    /// エントリ名
+    name: String,
+}
+
+/// ゲストが割り当てたファイルの識別子 (fid) の状態
+struct Fid {
+    /// 共有ディレクトリからの相対パス
+    path: PathBuf,
+    /// Tlopen, Tlcreate で開いたファイル
+    file: Option<File>,
+    /// Treaddir で読み込んでいるディレクトリのエントリ
+    entries: Option<Vec<DirEntry>>,
+}
+impl Fid {
+    /// 相対パス path を指す、新しい Fid を作成します。
+    fn new(path: PathBuf) -> Self {
+        Self { path, file: None, entries: None }
+    }
+}
+
+/// エントリ名として使えるかを判定します。
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/')
+}
+
+/// 秒とナノ秒から時刻を作成します。
+fn system_time(sec: u64, nsec: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::new(sec, nsec as u32)
+}
+
+/// ホストのディレクトリを 9P2000.L で共有する virtio-9p デバイス
+///
+/// ゲストからは `mount -t 9p -o trans=virtio <tag> <dir>` でマウントできます。
+/// 共有ディレクトリ内のシンボリックリンクはホスト上ではたどらず、ゲストが Treadlink で内容を読み込んで解決します。
+pub struct Virtio9p {
+    /// 共有するディレクトリ
+    root: PathBuf,
+    /// マウントタグ
+    tag: String,
+    /// 書き込みを許可しないか
+    read_only: bool,
+    /// 交渉したメッセージの最大サイズ
+    msize: u32,
+    /// 割り当てられた fid
+    fids: HashMap<u32, Fid>,
+}
+impl Virtio9p {
+    /// ディレクトリ root をマウントタグ tag で共有する、新しい Virtio9p を作成します。
+    pub fn new(root: impl AsRef<Path>, tag: impl Into<String>, read_only: bool) -> io::Result<Self> {
+        let root = root.as_ref().canonicalize()?;
+        if !root.is_dir() {
+            return Err(io::ErrorKind::NotADirectory.into());
+        }
+        Ok(Self { root, tag: tag.into(), read_only, msize: 8192, fids: HashMap::new() })
+    }
+
+    /// 要求 request を処理し、応答を取得します。
+    fn handle(&mut self, request: &[u8]) -> Vec<u8> {
+        let mut reader = Reader::new(request);
+        let (Some(_size), Some(kind), Some(tag)) = (reader.u32(), reader.u8(), reader.u16()) else {
+            return Vec::new();
+        };
+        let response = match kind {
+            TVERSION => self.version(&mut reader),
+            TATTACH => self.attach(&mut reader),
+            TFLUSH => Ok(Writer::default()),
+            TWALK => self.walk(&mut reader),
+            TCLUNK => self.clunk(&mut reader),
+            TLOPEN => self.lopen(&mut reader),
+            TLCREATE => self.lcreate(&mut reader),
+            TREAD => self.read(&mut reader),
+            TWRITE => self.write(&mut reader),
+            TREADDIR => self.readdir(&mut reader),
+            TGETATTR => self.getattr(&mut reader),
+            TSETATTR => self.setattr(&mut reader),
+            TFSYNC => self.fsync(&mut reader),
+            TMKDIR => self.mkdir(&mut reader),
+            TUNLINKAT => self.unlinkat(&mut reader),
+            TRENAMEAT => self.renameat(&mut reader),
+            TRENAME => self.rename(&mut reader),
+            TREADLINK => self.readlink(&mut reader),
+            _ => Err(Errno::EOPNOTSUPP),
+        };
+        match response {
+            Ok(body) => body.finish(kind + 1, tag),
+            Err(Errno(errno)) => {
+                let mut body = Writer::default();
+                body.u32(errno);
+                body.finish(RLERROR, tag)
+            },
+        }
+    }
+
+    /// 書き込みが許可されているかを確認します。
+    fn check_writable(&self) -> Result<(), Errno> {
+        if self.read_only { Err(Errno::EROFS) } else { Ok(()) }
+    }
+
+    /// fid を取得します。
+    fn fid(&mut self, fid: u32) -> Result<&mut Fid, Errno> {
+        self.fids.get_mut(&fid).ok_or(Errno::EBADF)
+    }
+
+    /// 共有ディレクトリからの相対パス path の、ホスト上のパスを取得します。
+    ///
+    /// 最後の要素以外にシンボリックリンクを含むパスは、共有ディレクトリの外を指しうるので ELOOP で失敗します。
+    /// NOTE: 最後の要素がシンボリックリンクの場合は、それぞれの操作でたどらないようにする
+    fn resolve(&self, path: &Path) -> Result<PathBuf, Errno> {
+        let host = self.root.join(path);
+        // NOTE: root は正規化済みで、相対パスは "." や ".." を含まないので、親ディレクトリの正規化で変わるのはシンボリックリンクのみ
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            let parent = self.root.join(parent);
+            if parent.canonicalize()? != parent {
+                return Err(Errno::ELOOP);
+            }
+        }
+        Ok(host)
+    }
+
+    /// fid が指すファイルの、ホスト上のパスを取得します。
+    fn host_path(&self, fid: u32) -> Result<PathBuf, Errno> {
+        let fid = self.fids.get(&fid).ok_or(Errno::EBADF)?;
+        self.resolve(&fid.path)
+    }
+
+    /// ディレクトリを指す fid と、その中のエントリ名 name から、ホスト上のパスを取得します。
+    fn child_path(&self, dir: u32, name: &str) -> Result<PathBuf, Errno> {
+        if !valid_name(name) {
+            return Err(Errno::EINVAL);
+        }
+        let dir = self.fids.get(&dir).ok_or(Errno::EBADF)?;
+        self.resolve(&dir.path.join(name))
+    }
+
+    /// ホスト上のパス path がシンボリックリンクでないことを確認します。
+    fn check_not_symlink(path: &Path) -> Result<(), Errno> {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_symlink() => Err(Errno::ELOOP),
+            _ => Ok(()),
+        }
+    }
+
+    /// ホスト上のパス old のファイルが new に移動したので、old とその中を指す fid のパスを書き換えます。
+    fn move_fids(&mut self, old: &Path, new: &Path) {
+        let (Ok(old), Ok(new)) = (old.strip_prefix(&self.root), new.strip_prefix(&self.root)) else { return };
+        for fid in self.fids.values_mut() {
+            if let Ok(rest) = fid.path.strip_prefix(old) {
+                fid.path = if rest.as_os_str().is_empty() { new.to_path_buf() } else { new.join(rest) };
+            }
+        }
+    }
+
+    /// ホスト上のパス path のファイルの qid を取得します。
+    fn qid(path: &Path) -> Result<Qid, Errno> {
+        Ok(Qid::from_metadata(&fs::symlink_metadata(path)?))
+    }
+
+    /// Tversion: msize[4] version[s]
+    fn version(&mut self, reader: &mut Reader) -> Response {
+        let msize = reader.u32().ok_or(Errno::EINVAL)?;
+        let version = reader.string().ok_or(Errno::EINVAL)?;
+        // NOTE: バージョンの交渉はセッションを初期化する
+        self.fids.clear();
+        self.msize = msize.min(MSIZE_MAX);
+        let mut body = Writer::default();
+        body.u32(self.msize).string(if version == VERSION { VERSION } else { "unknown" });
+        Ok(body)
+    }
+
+    /// Tattach: fid[4] afid[4] uname[s] aname[s] n_uname[4]
+    fn attach(&mut self, reader: &mut Reader) -> Response {
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let qid = Self::qid(&self.root)?;
+        self.fids.insert(fid, Fid::new(PathBuf::new()));
+        let mut body = Writer::default();
+        body.qid(qid);
+        Ok(body)
+    }
+
+    /// Twalk: fid[4] newfid[4] nwname[2] nwname*(wname[s])
+    fn walk(&mut self, reader: &mut Reader) -> Response {
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let newfid = reader.u32().ok_or(Errno::EINVAL)?;
+        let count = reader.u16().ok_or(Errno::EINVAL)? as usize;
+        if count > MAXWELEM {
+            return Err(Errno::EINVAL);
+        }
+        let mut path = self.fids.get(&fid).ok_or(Errno::EBADF)?.path.clone();
+        // NOTE: newfid が fid と同じ場合は fid 自体を置き換えるが、それ以外で使用中の fid は上書きしない
+        if newfid != fid && self.fids.contains_key(&newfid) {
+            return Err(Errno::EEXIST);
+        }
+        let mut qids = Vec::new();
+        for _ in 0..count {
+            let name = reader.string().ok_or(Errno::EINVAL)?;
+            // NOTE: 共有ディレクトリより上にはたどれない
+            let next = match name {
+                ".." => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+                _ if valid_name(name) => path.join(name),
+                _ => return Err(Errno::ENOENT),
+            };
+            match self.resolve(&next).and_then(|path| Self::qid(&path)) {
+                Ok(qid) => qids.push(qid),
+                // NOTE: 最初の要素をたどれなかった場合のみエラーで、それ以外はたどれたところまでを返す
+                Err(errno) if qids.is_empty() => return Err(errno),
+                Err(_) => break,
+            }
+            path = next;
+        }
+        if qids.len() == count {
+            self.fids.insert(newfid, Fid::new(path));
+        }
+        let mut body = Writer::default();
+        body.u16(qids.len() as u16);
+        for qid in qids {
+            body.qid(qid);
+        }
+        Ok(body)
+    }
+
+    /// Tclunk: fid[4]
+    fn clunk(&mut self, reader: &mut Reader) -> Response {
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        self.fids.remove(&fid).ok_or(Errno::EBADF)?;
+        Ok(Writer::default())
+    }
+
+    /// flags に従って、ファイルを開く OpenOptions を作成します。
+    fn open_options(&self, flags: u32) -> Result<OpenOptions, Errno> {
+        let access = flags & O_ACCMODE;
+        let write = access == O_WRONLY || access == O_RDWR;
+        if write || flags & O_TRUNC != 0 {
+            self.check_writable()?;
+        }
+        // NOTE: O_APPEND の書き込み位置はゲストが決めて Twrite の offset で渡すので、ホストでは指定しない
+        let mut options = OpenOptions::new();
+        options.read(access != O_WRONLY).write(write).truncate(write && flags & O_TRUNC != 0);
+        Ok(options)
+    }
+
+    /// Tlopen: fid[4] flags[4]
+    fn lopen(&mut self, reader: &mut Reader) -> Response {
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let flags = reader.u32().ok_or(Errno::EINVAL)?;
+        let path = self.host_path(fid)?;
+        let metadata = fs::symlink_metadata(&path)?;
+        // NOTE: シンボリックリンクは開かず、ゲストが Treadlink で読み込んで解決する
+        if metadata.is_symlink() {
+            return Err(Errno::ELOOP);
+        }
+        let qid = Qid::from_metadata(&metadata);
+        // NOTE: ディレクトリは Treaddir でパスから読み込むので、開く必要はない
+        let file = if metadata.is_dir() { None } else { Some(self.open_options(flags)?.open(&path)?) };
+        let entry = self.fid(fid)?;
+        entry.file = file;
+        entry.entries = None;
+        let mut body = Writer::default();
+        body.qid(qid).u32(0);
+        Ok(body)
+    }
+
+    /// Tlcreate: fid[4] name[s] flags[4] mode[4] gid[4]
+    fn lcreate(&mut self, reader: &mut Reader) -> Response {
+        self.check_writable()?;
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let name = reader.string().ok_or(Errno::EINVAL)?;
+        let flags = reader.u32().ok_or(Errno::EINVAL)?;
+        let mode = reader.u32().ok_or(Errno::EINVAL)?;
+        let path = self.child_path(fid, name)?;
+        // NOTE: 既存のシンボリックリンクを開くと、その指す先に作成してしまう
+        Self::check_not_symlink(&path)?;
+
+        let mut options = self.open_options(flags)?;
+        // NOTE: 作成には書き込みのアクセスが必要なので、読み込みのみで開く場合も書き込みを許可する
+        if flags & O_ACCMODE == 0 {
+            options.write(true);
+        }
+        if flags & O_EXCL != 0 {
+            options.create_new(true);
+        } else {
+            options.create(true);
+        }
+        let file = options.mode(mode & 0o7777).open(&path)?;
+        let qid = Self::qid(&path)?;
+
+        // NOTE: fid はディレクトリから、作成したファイルを指すように変わる
+        let entry = self.fid(fid)?;
+        entry.path.push(name);
+        entry.file = Some(file);
+        entry.entries = None;
+        let mut body = Writer::default();
+        body.qid(qid).u32(0);
+        Ok(body)
+    }
+
+    /// 1 回の読み込みで返せる最大のバイト数を取得します。
+    fn io_limit(&self, count: u32) -> usize {
+        count.min(self.msize.saturating_sub(IO_HEADER_SIZE)) as usize
+    }
+
+    /// Tread: fid[4] offset[8] count[4]
+    fn read(&mut self, reader: &mut Reader) -> Response {
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let offset = reader.u64().ok_or(Errno::EINVAL)?;
+        let count = reader.u32().ok_or(Errno::EINVAL)?;
+        let mut data = vec![0; self.io_limit(count)];
+        let file = self.fid(fid)?.file.as_ref().ok_or(Errno::EBADF)?;
+        let len = file.read_at(&mut data, offset)?;
+        let mut body = Writer::default();
+        body.u32(len as u32).bytes(&data[..len]);
+        Ok(body)
+    }
+
+    /// Twrite: fid[4] offset[8] count[4] data[count]
+    fn write(&mut self, reader: &mut Reader) -> Response {
+        self.check_writable()?;
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let offset = reader.u64().ok_or(Errno::EINVAL)?;
+        let count = reader.u32().ok_or(Errno::EINVAL)?;
+        let data = reader.bytes(count as usize).ok_or(Errno::EINVAL)?;
+        let file = self.fid(fid)?.file.as_ref().ok_or(Errno::EBADF)?;
+        let len = file.write_at(data, offset)?;
+        let mut body = Writer::default();
+        body.u32(len as u32);
+        Ok(body)
+    }
+
+    /// ホスト上のディレクトリ path のエントリ ("." と ".." を含む) を読み込みます。
+    fn read_entries(&self, path: &Path) -> Result<Vec<DirEntry>, Errno> {
+        let parent = if path == self.root { path } else { path.parent().unwrap_or(path) };
+        let mut entries = vec![
+            DirEntry { qid: Self::qid(path)?, kind: DT_DIR, name: ".".into() },
+            DirEntry { qid: Self::qid(parent)?, kind: DT_DIR, name: "..".into() },
+        ];
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else { continue };
+            let metadata = entry.metadata()?;
+            let kind = if metadata.is_dir() {
+                DT_DIR
+            } else if metadata.is_symlink() {
+                DT_LNK
+            } else {
+                DT_REG
+            };
+            entries.push(DirEntry { qid: Qid::from_metadata(&metadata), kind, name });
+        }
+        Ok(entries)
+    }
+
+    /// Treaddir: fid[4] offset[8] count[4]
+    fn readdir(&mut self, reader: &mut Reader) -> Response {
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let offset = reader.u64().ok_or(Errno::EINVAL)?;
+        let count = reader.u32().ok_or(Errno::EINVAL)?;
+        let limit = self.io_limit(count);
+        let path = self.host_path(fid)?;
+        if !fs::symlink_metadata(&path)?.is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+        // NOTE: 先頭から読み込むときにエントリの一覧を作り直し、offset はその添字として扱う
+        if offset == 0 || self.fid(fid)?.entries.is_none() {
+            let entries = self.read_entries(&path)?;
+            self.fid(fid)?.entries = Some(entries);
+        }
+        let entries = self.fid(fid)?.entries.as_ref().unwrap();
+
+        let mut data = Writer::default();
+        for (i, entry) in entries.iter().enumerate().skip(offset as usize) {
+            // NOTE: qid[13] offset[8] type[1] name[s]
+            if data.as_bytes().len() + 24 + entry.name.len() > limit {
+                break;
+            }
+            data.qid(entry.qid).u64(i as u64 + 1).u8(entry.kind).string(&entry.name);
+        }
+        let mut body = Writer::default();
+        body.u32(data.as_bytes().len() as u32).bytes(data.as_bytes());
+        Ok(body)
+    }
+
+    /// Tgetattr: fid[4] request_mask[8]
+    fn getattr(&mut self, reader: &mut Reader) -> Response {
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let metadata = fs::symlink_metadata(self.host_path(fid)?)?;
+        let mut body = Writer::default();
+        body.u64(GETATTR_BASIC)
+            .qid(Qid::from_metadata(&metadata))
+            .u32(metadata.mode())
+            .u32(metadata.uid())
+            .u32(metadata.gid())
+            .u64(metadata.nlink())
+            .u64(metadata.rdev())
+            .u64(metadata.size())
+            .u64(metadata.blksize())
+            .u64(metadata.blocks())
+            .u64(metadata.atime() as u64)
+            .u64(metadata.atime_nsec() as u64)
+            .u64(metadata.mtime() as u64)
+            .u64(metadata.mtime_nsec() as u64)
+            .u64(metadata.ctime() as u64)
+            .u64(metadata.ctime_nsec() as u64);
+        // NOTE: btime, gen, data_version は提供しない
+        body.u64(0).u64(0).u64(0).u64(0);
+        Ok(body)
+    }
+
+    /// Tsetattr: fid[4] valid[4] mode[4] uid[4] gid[4] size[8] atime_sec[8] atime_nsec[8] mtime_sec[8] mtime_nsec[8]
+    fn setattr(&mut self, reader: &mut Reader) -> Response {
+        self.check_writable()?;
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let valid = reader.u32().ok_or(Errno::EINVAL)?;
+        let mode = reader.u32().ok_or(Errno::EINVAL)?;
+        let uid = reader.u32().ok_or(Errno::EINVAL)?;
+        let gid = reader.u32().ok_or(Errno::EINVAL)?;
+        let size = reader.u64().ok_or(Errno::EINVAL)?;
+        let mut times = [0; 4];
+        for time in &mut times {
+            *time = reader.u64().ok_or(Errno::EINVAL)?;
+        }
+        let path = self.host_path(fid)?;
+        // NOTE: 所有者以外の変更はシンボリックリンクの指す先に適用されてしまうので、所有者の変更のみを許可する
+        if valid & !(SETATTR_UID | SETATTR_GID) != 0 {
+            Self::check_not_symlink(&path)?;
+        }
+
+        if valid & SETATTR_MODE != 0 {
+            fs::set_permissions(&path, Permissions::from_mode(mode & 0o7777))?;
+        }
+        if valid & (SETATTR_UID | SETATTR_GID) != 0 {
+            let uid = (valid & SETATTR_UID != 0).then_some(uid);
+            let gid = (valid & SETATTR_GID != 0).then_some(gid);
+            std::os::unix::fs::lchown(&path, uid, gid)?;
+        }
+        if valid & SETATTR_SIZE != 0 {
+            OpenOptions::new().write(true).open(&path)?.set_len(size)?;
+        }
+        if valid & (SETATTR_ATIME | SETATTR_MTIME) != 0 {
+            let now = SystemTime::now();
+            let mut file_times = FileTimes::new();
+            if valid & SETATTR_ATIME != 0 {
+                let set = valid & SETATTR_ATIME_SET != 0;
+                file_times = file_times.set_accessed(if set { system_time(times[0], times[1]) } else { now });
+            }
+            if valid & SETATTR_MTIME != 0 {
+                let set = valid & SETATTR_MTIME_SET != 0;
+                file_times = file_times.set_modified(if set { system_time(times[2], times[3]) } else { now });
+            }
+            File::open(&path)?.set_times(file_times)?;
+        }
+        // NOTE: ctime はホストが更新するので、指定されても無視する
+        Ok(Writer::default())
+    }
+
+    /// Tfsync: fid[4] datasync[4]
+    fn fsync(&mut self, reader: &mut Reader) -> Response {
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        if let Some(file) = &self.fid(fid)?.file {
+            file.sync_all()?;
+        }
+        Ok(Writer::default())
+    }
+
+    /// Tmkdir: dfid[4] name[s] mode[4] gid[4]
+    fn mkdir(&mut self, reader: &mut Reader) -> Response {
+        self.check_writable()?;
+        let dfid = reader.u32().ok_or(Errno::EINVAL)?;
+        let name = reader.string().ok_or(Errno::EINVAL)?;
+        let mode = reader.u32().ok_or(Errno::EINVAL)?;
+        let path = self.child_path(dfid, name)?;
+        DirBuilder::new().mode(mode & 0o7777).create(&path)?;
+        let mut body = Writer::default();
+        body.qid(Self::qid(&path)?);
+        Ok(body)
+    }
+
+    /// Tunlinkat: dirfd[4] name[s] flags[4]
+    fn unlinkat(&mut self, reader: &mut Reader) -> Response {
+        self.check_writable()?;
+        let dirfd = reader.u32().ok_or(Errno::EINVAL)?;
+        let name = reader.string().ok_or(Errno::EINVAL)?;
+        let flags = reader.u32().ok_or(Errno::EINVAL)?;
+        let path = self.child_path(dirfd, name)?;
+        if flags & AT_REMOVEDIR != 0 {
+            fs::remove_dir(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+        Ok(Writer::default())
+    }
+
+    /// Trenameat: olddirfid[4] oldname[s] newdirfid[4] newname[s]
+    fn renameat(&mut self, reader: &mut Reader) -> Response {
+        self.check_writable()?;
+        let old_dir = reader.u32().ok_or(Errno::EINVAL)?;
+        let old_name = reader.string().ok_or(Errno::EINVAL)?;
+        let new_dir = reader.u32().ok_or(Errno::EINVAL)?;
+        let new_name = reader.string().ok_or(Errno::EINVAL)?;
+        let old_path = self.child_path(old_dir, old_name)?;
+        let new_path = self.child_path(new_dir, new_name)?;
+        fs::rename(&old_path, &new_path)?;
+        self.move_fids(&old_path, &new_path);
+        Ok(Writer::default())
+    }
+
+    /// Treadlink: fid[4]
+    fn readlink(&mut self, reader: &mut Reader) -> Response {
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let target = fs::read_link(self.host_path(fid)?)?;
+        let target = target.to_str().ok_or(Errno::EINVAL)?;
+        let mut body = Writer::default();
+        body.string(target);
+        Ok(body)
+    }
+
+    /// Trename: fid[4] dfid[4] name[s]
+    fn rename(&mut self, reader: &mut Reader) -> Response {
+        self.check_writable()?;
+        let fid = reader.u32().ok_or(Errno::EINVAL)?;
+        let dfid = reader.u32().ok_or(Errno::EINVAL)?;
+        let name = reader.string().ok_or(Errno::EINVAL)?;
+        let old_path = self.host_path(fid)?;
+        let new_path = self.child_path(dfid, name)?;
+        fs::rename(&old_path, &new_path)?;
+        self.move_fids(&old_path, &new_path);
+        Ok(Writer::default())
+    }
+}
+impl VirtioDevice for Virtio9p {
+    fn device_id(&self) -> u32 {
+        DEVICE_ID
+    }
+
+    fn features(&self) -> u64 {
+        VIRTIO_9P_MOUNT_TAG
+    }
+
+    fn queue_count(&self) -> usize {
+        1
+    }
+
+    fn read_config(&self, offset: u64, size: u64) -> u64 {
+        // NOTE: tag_len (u16), tag
+        let mut config = (self.tag.len() as u16).to_le_bytes().to_vec();
+        config.extend_from_slice(self.tag.as_bytes());
+        read_config_bytes(&config, offset, size)
+    }
+
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], dma: &mut Dma) {
+        let queue = &mut queues[queue];
+        while let Some(chain) = queue.pop(dma) {
+            let response = chain.read(dma).map(|request| self.handle(&request)).unwrap_or_default();
+            let len = chain.write(dma, &response).unwrap_or(0);
+            queue.push(dma, chain, len as u32);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.fids.clear();
+        self.msize = 8192;
+    }
+}
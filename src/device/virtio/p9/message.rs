@@ -0,0 +1,138 @@
+use std::{fs::Metadata, os::unix::fs::MetadataExt};
+
+/// qid.type: ディレクトリ
+const QTDIR: u8 = 0x80;
+/// qid.type: シンボリックリンク
+const QTSYMLINK: u8 = 0x02;
+/// qid.type: 通常のファイル
+const QTFILE: u8 = 0x00;
+
+/// ファイルを識別する qid (type[1] version[4] path[8])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Qid {
+    /// ファイルの種類
+    pub(super) kind: u8,
+    /// ファイルのバージョン
+    pub(super) version: u32,
+    /// ファイルに固有の番号 (ホストの inode 番号を使う)
+    pub(super) path: u64,
+}
+impl Qid {
+    /// ファイルのメタデータから qid を作成します。
+    pub(super) fn from_metadata(metadata: &Metadata) -> Self {
+        let kind = if metadata.is_dir() {
+            QTDIR
+        } else if metadata.is_symlink() {
+            QTSYMLINK
+        } else {
+            QTFILE
+        };
+        // NOTE: version は変更の検出に使われるので、更新日時から作る
+        Self { kind, version: (metadata.mtime() as u32) ^ (metadata.mtime_nsec() as u32), path: metadata.ino() }
+    }
+}
+
+/// 9P のメッセージ (リトルエンディアン) を先頭から読み込む
+pub(super) struct Reader<'a> {
+    /// まだ読み込んでいないバイト列
+    data: &'a [u8],
+}
+impl<'a> Reader<'a> {
+    /// data を読み込む、新しい Reader を作成します。
+    pub(super) fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// len バイトを読み込みます。
+    pub(super) fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (bytes, rest) = self.data.split_at_checked(len)?;
+        self.data = rest;
+        Some(bytes)
+    }
+
+    /// u8 を読み込みます。
+    pub(super) fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    /// u16 を読み込みます。
+    pub(super) fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    /// u32 を読み込みます。
+    pub(super) fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// u64 を読み込みます。
+    pub(super) fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// 文字列 (len[2] + UTF-8) を読み込みます。
+    pub(super) fn string(&mut self) -> Option<&'a str> {
+        let len = self.u16()? as usize;
+        std::str::from_utf8(self.bytes(len)?).ok()
+    }
+}
+
+/// 9P のメッセージ (リトルエンディアン) を末尾に書き込む
+#[derive(Default)]
+pub(super) struct Writer {
+    /// 書き込んだバイト列
+    data: Vec<u8>,
+}
+impl Writer {
+    /// 書き込んだバイト列を取得します。
+    pub(super) fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// バイト列をそのまま書き込みます。
+    pub(super) fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.data.extend_from_slice(bytes);
+        self
+    }
+
+    /// u8 を書き込みます。
+    pub(super) fn u8(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[value])
+    }
+
+    /// u16 を書き込みます。
+    pub(super) fn u16(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// u32 を書き込みます。
+    pub(super) fn u32(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// u64 を書き込みます。
+    pub(super) fn u64(&mut self, value: u64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// 文字列 (len[2] + UTF-8) を書き込みます。
+    pub(super) fn string(&mut self, value: &str) -> &mut Self {
+        self.u16(value.len() as u16).bytes(value.as_bytes())
+    }
+
+    /// qid を書き込みます。
+    pub(super) fn qid(&mut self, qid: Qid) -> &mut Self {
+        self.u8(qid.kind).u32(qid.version).u64(qid.path)
+    }
+
+    /// 応答のヘッダ (size[4] type[1] tag[2]) を付けて、メッセージを完成させます。
+    pub(super) fn finish(self, kind: u8, tag: u16) -> Vec<u8> {
+        let size = (7 + self.data.len()) as u32;
+        let mut message = Vec::with_capacity(size as usize);
+        message.extend_from_slice(&size.to_le_bytes());
+        message.push(kind);
+        message.extend_from_slice(&tag.to_le_bytes());
+        message.extend_from_slice(&self.data);
+        message
+    }
+}
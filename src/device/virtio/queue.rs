@@ -0,0 +1,194 @@
+use crate::bus::Dma;
+
+// NOTE: split virtqueue は、ドライバが書き込むディスクリプタテーブル・available リングと、デバイスが書き込む used リングからなる
+
+/// ディスクリプタの flags: 次のディスクリプタが連結されている
+const VIRTQ_DESC_F_NEXT: u64 = 1;
+/// ディスクリプタの flags: デバイスが書き込むバッファ (そうでなければデバイスが読み込むバッファ)
+const VIRTQ_DESC_F_WRITE: u64 = 2;
+/// available リングの flags: ドライバが割り込みを必要としていない
+const VIRTQ_AVAIL_F_NO_INTERRUPT: u64 = 1;
+
+/// ディスクリプタテーブルの 1 要素のサイズ
+const DESCRIPTOR_SIZE: u64 = 16;
+/// 1 つのリクエストで、デバイスが読み込むバッファ・書き込むバッファそれぞれの合計バイト数の上限
+const MAX_REQUEST_SIZE: usize = 4 * 1024 * 1024;
+
+/// ディスクリプタが指す、ゲストのメモリ上のバッファ
+#[derive(Debug, Clone, Copy)]
+struct Buffer {
+    /// ゲストの物理アドレス
+    addr: u64,
+    /// バイト数
+    len: u32,
+}
+
+/// 連結されたディスクリプタ (1 つのリクエスト)
+#[derive(Debug)]
+pub struct DescriptorChain {
+    /// 先頭のディスクリプタの番号 (used リングに返す ID)
+    head: u16,
+    /// デバイスが読み込むバッファ
+    readable: Vec<Buffer>,
+    /// デバイスが書き込むバッファ
+    writable: Vec<Buffer>,
+}
+impl DescriptorChain {
+    /// デバイスが読み込むバッファの合計バイト数を取得します。
+    pub fn readable_len(&self) -> usize {
+        self.readable.iter().map(|buffer| buffer.len as usize).sum()
+    }
+
+    /// デバイスが書き込むバッファの合計バイト数を取得します。
+    pub fn writable_len(&self) -> usize {
+        self.writable.iter().map(|buffer| buffer.len as usize).sum()
+    }
+
+    /// デバイスが読み込むバッファの内容を、連結して読み込みます。
+    pub fn read(&self, dma: &mut Dma) -> Option<Vec<u8>> {
+        let mut data = vec![0; self.readable_len()];
+        let mut offset = 0;
+        for buffer in &self.readable {
+            let end = offset + buffer.len as usize;
+            dma.read_bytes(buffer.addr, &mut data[offset..end]).ok()?;
+            offset = end;
+        }
+        Some(data)
+    }
+
+    /// デバイスが書き込むバッファに、先頭から順に data を書き込みます。
+    ///
+    /// バッファに収まらない部分は切り捨て、書き込んだバイト数を返します。
+    pub fn write(&self, dma: &mut Dma, data: &[u8]) -> Option<usize> {
+        self.write_at(dma, 0, data)
+    }
+
+    /// デバイスが書き込むバッファを連結した領域の、先頭から offset バイトの位置に data を書き込みます。
+    ///
+    /// バッファに収まらない部分は切り捨て、書き込んだバイト数を返します。
+    pub fn write_at(&self, dma: &mut Dma, offset: usize, data: &[u8]) -> Option<usize> {
+        let mut skip = offset;
+        let mut written = 0;
+        for buffer in &self.writable {
+            if written == data.len() {
+                break;
+            }
+            let len = buffer.len as usize;
+            if skip >= len {
+                skip -= len;
+                continue;
+            }
+            let end = data.len().min(written + len - skip);
+            dma.write_bytes(buffer.addr + skip as u64, &data[written..end]).ok()?;
+            written = end;
+            skip = 0;
+        }
+        Some(written)
+    }
+}
+
+/// split virtqueue
+#[derive(Debug, Default)]
+pub struct Virtqueue {
+    /// キューのサイズ (QueueNum)
+    pub(super) size: u16,
+    /// ドライバがキューを使用可能にしたか (QueueReady)
+    pub(super) ready: bool,
+    /// ディスクリプタテーブルのアドレス
+    pub(super) desc: u64,
+    /// available リング (driver area) のアドレス
+    pub(super) avail: u64,
+    /// used リング (device area) のアドレス
+    pub(super) used: u64,
+    /// 次に処理する available リングの位置
+    last_avail_idx: u16,
+    /// 次に書き込む used リングの位置
+    used_idx: u16,
+    /// ドライバへの割り込みが必要か
+    interrupt: bool,
+}
+impl Virtqueue {
+    /// ドライバから渡された、次のリクエストを取り出します。
+    ///
+    /// リクエストがない場合や、キューが使用可能でない場合は None を返します。
+    /// ディスクリプタが不正なリクエストは、何も書き込まずに used リングに返して読み飛ばします。
+    pub fn pop(&mut self, dma: &mut Dma) -> Option<DescriptorChain> {
+        if !self.ready || self.size == 0 {
+            return None;
+        }
+        loop {
+            let avail_idx = dma.read(self.avail + 2, 2).ok()? as u16;
+            if avail_idx == self.last_avail_idx {
+                return None;
+            }
+            let slot = (self.last_avail_idx % self.size) as u64;
+            let head = dma.read(self.avail + 4 + slot * 2, 2).ok()? as u16;
+            self.last_avail_idx = self.last_avail_idx.wrapping_add(1);
+
+            match self.read_chain(dma, head) {
+                Some(chain) => return Some(chain),
+                // NOTE: 取り出したリクエストを返さないと、ドライバはその完了を待ち続けてしまう
+                None => self.push_head(dma, head, 0),
+            }
+        }
+    }
+
+    /// head から連結されたディスクリプタを読み込みます。
+    ///
+    /// 番号が範囲外のディスクリプタや循環、RAM に収まらないバッファを含む場合や、リクエストが大きすぎる場合は None を返します。
+    fn read_chain(&self, dma: &mut Dma, head: u16) -> Option<DescriptorChain> {
+        let mut chain = DescriptorChain { head, readable: Vec::new(), writable: Vec::new() };
+        let mut index = head;
+        // NOTE: 循環したディスクリプタで無限ループにならないよう、キューのサイズで打ち切る
+        for _ in 0..self.size {
+            if index >= self.size {
+                return None;
+            }
+            let desc = self.desc + index as u64 * DESCRIPTOR_SIZE;
+            let buffer = Buffer { addr: dma.read(desc, 8).ok()?, len: dma.read(desc + 8, 4).ok()? as u32 };
+            let flags = dma.read(desc + 12, 2).ok()?;
+            if buffer.len > 0 && !dma.contains(buffer.addr, buffer.len as u64) {
+                return None;
+            }
+            if flags & VIRTQ_DESC_F_WRITE != 0 {
+                chain.writable.push(buffer);
+            } else {
+                chain.readable.push(buffer);
+            }
+            // NOTE: デバイスはリクエストの内容をメモリ上に確保するので、ゲストが指定できるサイズを制限する
+            if chain.readable_len() > MAX_REQUEST_SIZE || chain.writable_len() > MAX_REQUEST_SIZE {
+                return None;
+            }
+            if flags & VIRTQ_DESC_F_NEXT == 0 {
+                return Some(chain);
+            }
+            index = dma.read(desc + 14, 2).ok()? as u16;
+        }
+        None
+    }
+
+    /// 処理を終えたリクエストを、デバイスが書き込んだバイト数 len とともに used リングに返します。
+    pub fn push(&mut self, dma: &mut Dma, chain: DescriptorChain, len: u32) {
+        self.push_head(dma, chain.head, len);
+    }
+
+    /// 先頭のディスクリプタの番号が head のリクエストを、used リングに返します。
+    fn push_head(&mut self, dma: &mut Dma, head: u16, len: u32) {
+        let slot = (self.used_idx % self.size) as u64;
+        let elem = self.used + 4 + slot * 8;
+        let _ = dma.write(elem, head as u64, 4);
+        let _ = dma.write(elem + 4, len as u64, 4);
+        self.used_idx = self.used_idx.wrapping_add(1);
+        let _ = dma.write(self.used + 2, self.used_idx as u64, 2);
+
+        let flags = dma.read(self.avail, 2).unwrap_or(0);
+        if flags & VIRTQ_AVAIL_F_NO_INTERRUPT == 0 {
+            self.interrupt = true;
+        }
+    }
+
+    /// ドライバへの割り込みが必要かを取得し、その要求をクリアします。
+    pub(super) fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+}
@@ -0,0 +1,94 @@
+use std::{fs::File, io::{self, Read}};
+
+use crate::bus::Dma;
+
+use super::{VirtioDevice, Virtqueue};
+
+/// virtio-rng の Device ID
+const DEVICE_ID: u32 = 4;
+
+/// 1 回のリクエストで返す最大のバイト数
+const REQUEST_MAX: usize = 4096;
+
+/// virtio-rng が返す乱数の生成元
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntropySource {
+    /// シード値から生成する疑似乱数 (同じシード値なら、実行のたびに同じ列になる)
+    Seeded(u64),
+    /// ホストの /dev/urandom
+    HostRandom,
+}
+
+/// 乱数の生成器
+enum Generator {
+    /// SplitMix64 の状態
+    Seeded(u64),
+    /// /dev/urandom
+    Host(File),
+}
+
+/// virtio-rng デバイス
+pub struct VirtioRng {
+    /// 乱数の生成器
+    generator: Generator,
+}
+impl VirtioRng {
+    /// source を生成元とする、新しい VirtioRng を作成します。
+    pub fn new(source: EntropySource) -> io::Result<Self> {
+        let generator = match source {
+            EntropySource::Seeded(seed) => Generator::Seeded(seed),
+            EntropySource::HostRandom => Generator::Host(File::open("/dev/urandom")?),
+        };
+        Ok(Self { generator })
+    }
+
+    /// buf を乱数で埋めます。
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        match &mut self.generator {
+            Generator::Seeded(state) => {
+                for chunk in buf.chunks_mut(8) {
+                    // NOTE: SplitMix64
+                    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+                    let mut z = *state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                    z ^= z >> 31;
+                    chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+                }
+                Ok(())
+            },
+            Generator::Host(file) => file.read_exact(buf),
+        }
+    }
+}
+impl VirtioDevice for VirtioRng {
+    fn device_id(&self) -> u32 {
+        DEVICE_ID
+    }
+
+    fn features(&self) -> u64 {
+        0
+    }
+
+    fn queue_count(&self) -> usize {
+        1
+    }
+
+    fn read_config(&self, _offset: u64, _size: u64) -> u64 {
+        // NOTE: virtio-rng には設定領域がない
+        0
+    }
+
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], dma: &mut Dma) {
+        let queue = &mut queues[queue];
+        while let Some(chain) = queue.pop(dma) {
+            let mut data = vec![0; chain.writable_len().min(REQUEST_MAX)];
+            // NOTE: 乱数を得られなかった場合は、何も書き込まずに返す
+            let len = match self.fill(&mut data) {
+                Ok(()) => chain.write(dma, &data).unwrap_or(0),
+                Err(_) => 0,
+            };
+            queue.push(dma, chain, len as u32);
+        }
+    }
+}
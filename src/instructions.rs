@@ -1,6 +1,6 @@
 use std::fmt::Debug;
 
-use crate::{Imm, RegIdx, Shamt};
+use crate::{Imm, RawInstruction, RegIdx, Shamt};
 
 #[derive(Debug)]
 pub enum Instruction {
@@ -69,6 +69,95 @@ pub enum Instruction {
     // NOTE: RV64I S-Type
     SD { rs1: RegIdx, rs2: RegIdx, offset: Imm },
 
+    // NOTE: RV32A / RV64A (aq, rl ビットはシングルコアなので保持しない)
+    LRW { rd: RegIdx, rs1: RegIdx },
+    SCW { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOSWAPW { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOADDW { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOXORW { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOANDW { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOORW { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOMINW { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOMAXW { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOMINUW { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOMAXUW { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    LRD { rd: RegIdx, rs1: RegIdx },
+    SCD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOSWAPD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOADDD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOXORD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOANDD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOORD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOMIND { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOMAXD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOMINUD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    AMOMAXUD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+
+    // NOTE: RV32F / RV64F (rm は丸めモード、rs3 は R4-Type の第 3 オペランド)
+    FLW { rd: RegIdx, rs1: RegIdx, offset: Imm },
+    FSW { rs1: RegIdx, rs2: RegIdx, offset: Imm },
+    FMADDS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rs3: RegIdx, rm: u8 },
+    FMSUBS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rs3: RegIdx, rm: u8 },
+    FNMSUBS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rs3: RegIdx, rm: u8 },
+    FNMADDS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rs3: RegIdx, rm: u8 },
+    FADDS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rm: u8 },
+    FSUBS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rm: u8 },
+    FMULS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rm: u8 },
+    FDIVS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rm: u8 },
+    FSQRTS { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FSGNJS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FSGNJNS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FSGNJXS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FMINS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FMAXS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FCVTWS { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTWUS { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTLS { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTLUS { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTSW { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTSWU { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTSL { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTSLU { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FMVXW { rd: RegIdx, rs1: RegIdx },
+    FMVWX { rd: RegIdx, rs1: RegIdx },
+    FCLASSS { rd: RegIdx, rs1: RegIdx },
+    FEQS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FLTS { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FLES { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    // NOTE: RV32D / RV64D
+    FLD { rd: RegIdx, rs1: RegIdx, offset: Imm },
+    FSD { rs1: RegIdx, rs2: RegIdx, offset: Imm },
+    FMADDD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rs3: RegIdx, rm: u8 },
+    FMSUBD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rs3: RegIdx, rm: u8 },
+    FNMSUBD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rs3: RegIdx, rm: u8 },
+    FNMADDD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rs3: RegIdx, rm: u8 },
+    FADDD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rm: u8 },
+    FSUBD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rm: u8 },
+    FMULD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rm: u8 },
+    FDIVD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx, rm: u8 },
+    FSQRTD { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FSGNJD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FSGNJND { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FSGNJXD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FMIND { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FMAXD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FCVTWD { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTWUD { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTLD { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTLUD { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTDW { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTDWU { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTDL { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTDLU { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FMVXD { rd: RegIdx, rs1: RegIdx },
+    FMVDX { rd: RegIdx, rs1: RegIdx },
+    FCLASSD { rd: RegIdx, rs1: RegIdx },
+    FEQD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FLTD { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FLED { rd: RegIdx, rs1: RegIdx, rs2: RegIdx },
+    FCVTSD { rd: RegIdx, rs1: RegIdx, rm: u8 },
+    FCVTDS { rd: RegIdx, rs1: RegIdx, rm: u8 },
+
     // NOTE: RV32I B-Type
     BEQ { rs1: RegIdx, rs2: RegIdx, offset: Imm },
     BNE { rs1: RegIdx, rs2: RegIdx, offset: Imm },
@@ -85,6 +174,12 @@ pub enum Instruction {
     JAL { rd: RegIdx, offset: Imm },
     JALR { rd: RegIdx, rs1: RegIdx, offset: Imm },
 
+    // NOTE: RV32I MISC-MEM / Zifencei (pred, succ は I, O, R, W の 4bit)
+    FENCE { pred: u8, succ: u8, fm: u8 },
+    FENCETSO,
+    PAUSE,
+    FENCEI,
+
     // NOTE: RV32I System / Zicsr
     ECALL,
     EBREAK,
@@ -94,11 +189,19 @@ pub enum Instruction {
     CSRRWI { rd: RegIdx, imm: u8, csr: u16 },
     CSRRSI { rd: RegIdx, imm: u8, csr: u16 },
     CSRRCI { rd: RegIdx, imm: u8, csr: u16 },
+
+    // NOTE: 特権命令
+    MRET,
+    SRET,
+    WFI,
+    SFENCEVMA { rs1: RegIdx, rs2: RegIdx },
 }
 
 pub struct InstructionContext {
     pub instruction: Instruction,
     pub next_pc: u64,
+    /// デコード前の生の命令 (不正命令例外の mtval 用)
+    pub raw: RawInstruction,
 }
 impl Debug for InstructionContext {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -1,11 +1,18 @@
 mod bus;
 mod cpu;
+mod device;
 mod memory;
 mod types;
 mod instructions;
 
-pub use bus::Bus;
-pub use cpu::Cpu;
+pub use bus::{Bus, Dma, MapError};
+pub use cpu::{Cpu, TlbStats};
+#[cfg(unix)]
+pub use device::{UnixDatagramBackend, UnixSocketBackend, Virtio9p};
+pub use device::{Clint, ClockSource, Device, DeviceError, Htif, MemoryBackend, Plic, StdioBackend, TestFinisher, Uart, UartBackend};
+pub use device::{
+    DescriptorChain, DiskMode, EntropySource, LoopbackBackend, NetBackend, PcapBackend, VirtioBlk, VirtioConsole, VirtioDevice, VirtioMmio, VirtioNet, VirtioRng, Virtqueue,
+};
 pub use memory::Memory;
 pub use types::*;
 pub use instructions::{Instruction, InstructionContext};
@@ -10,6 +10,11 @@ impl Memory {
         }
     }
 
+    /// メモリのサイズ (バイト数) を取得します。
+    pub fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
     /// メモリからデータを読み込みます。
     pub fn read(&self, addr: u64, size: u64) -> u64 {
         let mut value = 0;
@@ -20,10 +25,22 @@ impl Memory {
         value
     }
 
+    /// メモリから buf の長さ分のバイト列を読み込みます。
+    pub fn read_bytes(&self, addr: u64, buf: &mut [u8]) {
+        let start = addr as usize;
+        buf.copy_from_slice(&self.data[start..start + buf.len()]);
+    }
+
     /// メモリにデータを書き込みます。
-    pub fn write(&mut self, addr: u64, value: u64, size: u64) -> () {
+    pub fn write(&mut self, addr: u64, value: u64, size: u64) {
         for i in 0..size {
             self.data[(addr + i) as usize] = ((value >> (i * 8)) & 0xff) as u8;
         }
     }
+
+    /// メモリにバイト列を書き込みます。
+    pub fn write_bytes(&mut self, addr: u64, data: &[u8]) {
+        let start = addr as usize;
+        self.data[start..start + data.len()].copy_from_slice(data);
+    }
 }
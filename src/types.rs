@@ -13,14 +13,111 @@ pub type Shamt = u32;
 /// レジスタ長
 pub const XLEN: u8 = 64;
 
-// TODO: 将来、Trap に変換される
-/// エラー型
+/// 特権モード
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PrivilegeMode {
+    /// ユーザーモード
+    User = 0,
+    /// スーパーバイザーモード
+    Supervisor = 1,
+    /// マシンモード
+    Machine = 3,
+}
+impl PrivilegeMode {
+    /// mstatus.MPP などに格納されている 2bit の値から特権モードを取得します。
+    pub const fn from_bits(bits: u64) -> Self {
+        match bits & 0b11 {
+            0b00 => PrivilegeMode::User,
+            0b01 => PrivilegeMode::Supervisor,
+            _ => PrivilegeMode::Machine,
+        }
+    }
+    /// 特権モードを 2bit の値に変換します。
+    pub const fn bits(self) -> u64 {
+        self as u64
+    }
+}
+
+/// 仮想アドレス変換方式 (値は satp.MODE のエンコーディング)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranslationMode {
+    /// 3 段のページテーブルによる 39bit 仮想アドレス
+    Sv39 = 8,
+    /// 4 段のページテーブルによる 48bit 仮想アドレス
+    Sv48 = 9,
+    /// 5 段のページテーブルによる 57bit 仮想アドレス
+    Sv57 = 10,
+}
+impl TranslationMode {
+    /// すべての仮想アドレス変換方式
+    pub const ALL: [TranslationMode; 3] = [TranslationMode::Sv39, TranslationMode::Sv48, TranslationMode::Sv57];
+
+    /// satp.MODE の値から仮想アドレス変換方式を取得します。(Bare や予約済みの値の場合は None)
+    pub const fn from_bits(bits: u64) -> Option<Self> {
+        match bits {
+            8 => Some(TranslationMode::Sv39),
+            9 => Some(TranslationMode::Sv48),
+            10 => Some(TranslationMode::Sv57),
+            _ => None,
+        }
+    }
+    /// 仮想アドレス変換方式を satp.MODE の値に変換します。
+    pub const fn bits(self) -> u64 {
+        self as u64
+    }
+
+    /// ページテーブルの段数を取得します。
+    pub const fn levels(self) -> u64 {
+        match self {
+            TranslationMode::Sv39 => 3,
+            TranslationMode::Sv48 => 4,
+            TranslationMode::Sv57 => 5,
+        }
+    }
+}
+
+/// 例外
+///
+/// 命令の実行中に発生した同期例外を表し、Cpu によってトラップに変換されます。
 #[derive(Debug)]
 pub enum Exception {
-    /// 未知の命令
+    /// 未知の命令 (Illegal Instruction)
     UnknownInstruction(RawInstruction),
-    /// 不正なメモリアクセス
-    InvalidMemoryAccess(Address),
-    /// 不正な CSR レジスタアクセス
+    /// 不正な CSR レジスタアクセス (Illegal Instruction)
     InvalidCsrAccess(u16),
+    /// 命令アドレスのミスアライン
+    InstructionAddressMisaligned(Address),
+    /// 命令フェッチ時のアクセスフォールト
+    InstructionAccessFault(Address),
+    /// ブレークポイント (EBREAK)
+    Breakpoint(Address),
+    /// ロードアドレスのミスアライン
+    LoadAddressMisaligned(Address),
+    /// ロード時のアクセスフォールト
+    LoadAccessFault(Address),
+    /// ストア / AMO アドレスのミスアライン
+    StoreAddressMisaligned(Address),
+    /// ストア / AMO 時のアクセスフォールト
+    StoreAccessFault(Address),
+    /// U モードからの環境呼び出し (ECALL)
+    EnvironmentCallFromUMode,
+    /// S モードからの環境呼び出し (ECALL)
+    EnvironmentCallFromSMode,
+    /// M モードからの環境呼び出し (ECALL)
+    EnvironmentCallFromMMode,
+    /// 命令フェッチ時のページフォールト
+    InstructionPageFault(Address),
+    /// ロード時のページフォールト
+    LoadPageFault(Address),
+    /// ストア / AMO 時のページフォールト
+    StorePageFault(Address),
+}
+
+/// ゲストが実行を終了した理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// 終了コード code で電源が切られた (0 は成功を表す)
+    Poweroff(u32),
+    /// 再起動が要求された
+    Reset,
 }
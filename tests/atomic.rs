@@ -0,0 +1,39 @@
+use riscv_emu::{Bus, Cpu, Memory};
+
+#[test]
+fn test_lr_sc_and_amo() {
+    let code: Vec<u32> = vec![
+        0x00001297, // auipc     t0, 1             (t0 = 0x8000_1000)
+        0x00500313, // li        t1, 5
+        0x0062b023, // sd        t1, 0(t0)
+        0x1002b52f, // lr.d      a0, (t0)          (a0 = 5)
+        0x00150313, // addi      t1, a0, 1
+        0x1862b5af, // sc.d      a1, t1, (t0)      (a1 = 0: 成功)
+        0x1862b62f, // sc.d      a2, t1, (t0)      (a2 = 1: 予約は破棄済み)
+        0xffd00393, // li        t2, -3
+        0x0072a6af, // amoadd.w  a3, t2, (t0)      (a3 = 6, mem = 3)
+        0x0002b703, // ld        a4, 0(t0)         (a4 = 3)
+        0xc072b7af, // amominu.d a5, t2, (t0)      (a5 = 3, mem = 3)
+        0x1002a82f, // lr.w      a6, (t0)          (a6 = 3)
+        0x0002a023, // sw        zero, 0(t0)       (予約を破棄)
+        0x1862a8af, // sc.w      a7, t1, (t0)      (a7 = 1)
+        0x0000006f, // j         .
+    ];
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..32 {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.read_register(10), 5);
+    assert_eq!(cpu.read_register(11), 0);
+    assert_eq!(cpu.read_register(12), 1);
+    assert_eq!(cpu.read_register(13), 6);
+    assert_eq!(cpu.read_register(14), 3);
+    assert_eq!(cpu.read_register(15), 3);
+    assert_eq!(cpu.read_register(16), 3);
+    assert_eq!(cpu.read_register(17), 1);
+}
@@ -0,0 +1,85 @@
+use std::{cell::Cell, rc::Rc};
+
+use riscv_emu::{Bus, Cpu, Device, DeviceError, MapError, Memory};
+
+/// 書き込まれた値を保持し、経過したサイクル数を読み出せるテスト用のデバイス
+struct Counter {
+    /// オフセット 0 のレジスタ
+    value: u64,
+    /// tick が呼ばれた回数 (オフセット 8 のレジスタ)
+    ticks: Rc<Cell<u64>>,
+}
+impl Device for Counter {
+    fn read(&mut self, offset: u64, size: u64) -> Result<u64, DeviceError> {
+        match (offset, size) {
+            (0, 8) => Ok(self.value),
+            (8, 8) => Ok(self.ticks.get()),
+            _ => Err(DeviceError),
+        }
+    }
+
+    fn write(&mut self, offset: u64, value: u64, size: u64) -> Result<(), DeviceError> {
+        match (offset, size) {
+            (0, 8) => {
+                self.value = value;
+                Ok(())
+            },
+            _ => Err(DeviceError),
+        }
+    }
+
+    fn tick(&mut self) {
+        self.ticks.set(self.ticks.get() + 1);
+    }
+}
+
+#[test]
+fn test_device_access_from_cpu() {
+    let ticks = Rc::new(Cell::new(0));
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device(0x1000_0000, 16, Box::new(Counter { value: 0, ticks: ticks.clone() })).unwrap();
+
+    let code: [u32; 5] = [
+        0x100002b7, // lui t0, 0x10000
+        0x02a00313, // li  t1, 42
+        0x0062b023, // sd  t1, 0(t0)
+        0x0002b503, // ld  a0, 0(t0)
+        0x0082b583, // ld  a1, 8(t0)
+    ];
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..code.len() {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.read_register(10), 42);
+    // NOTE: ld a1 の実行前に、5 サイクル分の tick が呼ばれている
+    assert_eq!(cpu.read_register(11), 5);
+    assert_eq!(ticks.get(), 5);
+}
+
+#[test]
+fn test_address_map() {
+    let mut bus = Bus::new(Memory::new(0x1000));
+    let counter = || Box::new(Counter { value: 0, ticks: Rc::new(Cell::new(0)) });
+
+    // NOTE: 既存の領域と重なる配置は拒否される
+    assert_eq!(bus.map_device(0x8000_0ff8, 16, counter()), Err(MapError::Overlap { base: 0x8000_0000, size: 0x1000 }));
+    assert_eq!(bus.map_device(0x7fff_fff8, 16, counter()), Err(MapError::Overlap { base: 0x8000_0000, size: 0x1000 }));
+    assert_eq!(bus.map_memory(0x7fff_f000, Memory::new(0x2000)), Err(MapError::Overlap { base: 0x8000_0000, size: 0x1000 }));
+    assert_eq!(bus.map_device(0x1000, 0, counter()), Err(MapError::InvalidRange));
+    assert_eq!(bus.map_device(u64::MAX - 7, 16, counter()), Err(MapError::InvalidRange));
+
+    // NOTE: 隣接する配置は許可される
+    bus.map_device(0x7fff_fff0, 16, counter()).unwrap();
+    bus.map_memory(0x8000_1000, Memory::new(0x1000)).unwrap();
+
+    // NOTE: 領域をまたぐアクセスや、何も配置されていないアドレスへのアクセスはフォールトになる
+    bus.write(0x8000_1000, 0x1234, 8).unwrap();
+    assert_eq!(bus.read(0x8000_1000, 8).unwrap(), 0x1234);
+    assert!(bus.read(0x8000_0ffc, 8).is_err());
+    assert!(bus.read(0x7fff_fff8, 4).is_err()); // NOTE: デバイスがエラーを返す
+    assert!(bus.write(0x0, 0, 1).is_err());
+}
@@ -4,7 +4,7 @@ use elf::endian::LittleEndian;
 use elf::ElfBytes;
 use elf::abi::PT_LOAD;
 
-use riscv_emu::{Bus, Cpu, Exception, Instruction, Memory, RawInstruction, RawShortInstruction};
+use riscv_emu::{Bus, Cpu, Exception, ExitReason, Htif, Memory, MemoryBackend};
 
 fn run_vm(path: &Path) -> Result<(), Exception> {
     let file_data = fs::read(path).expect("Could not read file");
@@ -29,35 +29,174 @@ fn run_vm(path: &Path) -> Result<(), Exception> {
         }
     }
 
-    let bus = Bus::new(memory);
+    // NOTE: テストの結果は、HTIF の tohost に書き込まれる
+    let mut bus = Bus::new(memory);
+    let htif = Htif::from_elf(slice, Box::new(MemoryBackend::new())).expect("tohost symbol not found");
+    bus.attach_device(Box::new(htif));
     let mut cpu = Cpu::new(bus);
 
-    let mut inst_count = 0;
-    loop {
-        if inst_count > 1_000_000 {
-            panic!("Instruction limit reached.");
-        }
-        inst_count += 1;
-
-        cpu.cycle();
+    match cpu.run(1_000_000) {
+        Some(ExitReason::Poweroff(0)) => Ok(()),
+        Some(ExitReason::Poweroff(test_case)) => panic!("Test case {test_case} failed."),
+        Some(reason) => panic!("Unexpected exit: {reason:?}"),
+        None => panic!("Instruction limit reached."),
     }
-
-    Ok(())
 }
 fn run_vm_glob(pattern: &str) -> Result<(), Exception> {
+    let mut count = 0;
     for entry in glob::glob(pattern).expect("Failed to read glob pattern") {
         match entry {
             Ok(path) => {
                 let assembly_path = path.parent().unwrap().parent().unwrap().join("dumps").join(path.file_name().unwrap()).with_added_extension("dump");
                 println!("Running test for:\n    Bytecode: {:?}\n    Assembly: {:?}", path, assembly_path);
-                run_vm(&path)?
+                run_vm(&path)?;
+                count += 1;
             }
             Err(e) => println!("{:?}", e),
         }
     }
+    // NOTE: サブモジュールを取得していない場合は、テストを実行せずに成功する
+    if count == 0 {
+        eprintln!("No bytecodes match {pattern}. Please run: git submodule update --init --recursive");
+    }
     Ok(())
 }
 
+/// riscv-tests の p 環境と同じ流れ (U モードで実行し、ecall でトラップして tohost に結果を書き込む) のテストを作成します。
+///
+/// テスト 2 は 1 + 1 = 2、テスト 3 は 6 * 7 = expected を確認します。
+fn riscv_test_code(expected: u32) -> Vec<u32> {
+    vec![
+        0xfff00293, // li    t0, -1
+        0x3b029073, // csrw  pmpaddr0, t0
+        0x01f00293, // li    t0, 0x1f
+        0x3a029073, // csrw  pmpcfg0, t0
+        0x00000297, // auipc t0, 0
+        0x07c28293, // addi  t0, t0, 124  (t0 = trap_vector)
+        0x30529073, // csrw  mtvec, t0
+        0x000022b7, // lui   t0, 2
+        0x80028293, // addi  t0, t0, -2048
+        0x3002b073, // csrc  mstatus, t0  (MPP = U)
+        0x00000297, // auipc t0, 0
+        0x01028293, // addi  t0, t0, 16
+        0x34129073, // csrw  mepc, t0
+        0x30200073, // mret
+        // test_2:
+        0x00200193, // li    gp, 2
+        0x00100513, // li    a0, 1
+        0x00100593, // li    a1, 1
+        0x00b50633, // add   a2, a0, a1
+        0x00200393, // li    t2, 2
+        0x02761663, // bne   a2, t2, fail
+        // test_3:
+        0x00300193, // li    gp, 3
+        0x00600513, // li    a0, 6
+        0x00700593, // li    a1, 7
+        0x02b50633, // mul   a2, a0, a1
+        0x00000393 | (expected << 20), // li t2, expected
+        0x00761a63, // bne   a2, t2, fail
+        // pass:
+        0x00100193, // li    gp, 1
+        0x05d00893, // li    a7, 93
+        0x00000513, // li    a0, 0
+        0x00000073, // ecall
+        // fail:
+        0x00119193, // slli  gp, gp, 1
+        0x0011e193, // ori   gp, gp, 1
+        0x05d00893, // li    a7, 93
+        0x00018513, // mv    a0, gp
+        0x00000073, // ecall
+        // trap_vector:
+        0x00001f17, // auipc t5, 1
+        0xf74f0f13, // addi  t5, t5, -140 (t5 = tohost)
+        0x003f2023, // sw    gp, 0(t5)
+        0x000f2223, // sw    zero, 4(t5)
+        0x0000006f, // j     .
+    ]
+}
+
+/// code を 0x8000_0000 に読み込み、tohost (0x8000_1000) のシンボルを持つ ELF を作成します。
+fn riscv_test_elf(code: &[u32]) -> Vec<u8> {
+    let text: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let strtab = b"\0tohost\0".to_vec();
+    let mut symtab = vec![0; 24]; // NOTE: 0 番目のシンボルは空
+    symtab.extend_from_slice(&1u32.to_le_bytes()); // st_name
+    symtab.extend_from_slice(&[0x11, 0]); // st_info (GLOBAL, OBJECT), st_other
+    symtab.extend_from_slice(&1u16.to_le_bytes()); // st_shndx
+    symtab.extend_from_slice(&0x8000_1000u64.to_le_bytes()); // st_value
+    symtab.extend_from_slice(&8u64.to_le_bytes()); // st_size
+
+    let text_offset = 64 + 56;
+    let symtab_offset = text_offset + text.len();
+    let strtab_offset = symtab_offset + symtab.len();
+    let shoff = strtab_offset + strtab.len();
+
+    let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    elf.extend_from_slice(&2u16.to_le_bytes()); // e_type (EXEC)
+    elf.extend_from_slice(&0xf3u16.to_le_bytes()); // e_machine (RISC-V)
+    elf.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    elf.extend_from_slice(&0x8000_0000u64.to_le_bytes()); // e_entry
+    elf.extend_from_slice(&64u64.to_le_bytes()); // e_phoff
+    elf.extend_from_slice(&(shoff as u64).to_le_bytes()); // e_shoff
+    elf.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    for half in [64u16, 56, 1, 64, 3, 0] {
+        elf.extend_from_slice(&half.to_le_bytes()); // e_ehsize, e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx
+    }
+
+    // NOTE: プログラムヘッダ (PT_LOAD, RX)
+    elf.extend_from_slice(&PT_LOAD.to_le_bytes());
+    elf.extend_from_slice(&5u32.to_le_bytes()); // p_flags
+    elf.extend_from_slice(&(text_offset as u64).to_le_bytes()); // p_offset
+    elf.extend_from_slice(&0x8000_0000u64.to_le_bytes()); // p_vaddr
+    elf.extend_from_slice(&0x8000_0000u64.to_le_bytes()); // p_paddr
+    elf.extend_from_slice(&(text.len() as u64).to_le_bytes()); // p_filesz
+    elf.extend_from_slice(&(text.len() as u64).to_le_bytes()); // p_memsz
+    elf.extend_from_slice(&4u64.to_le_bytes()); // p_align
+    elf.extend_from_slice(&text);
+    elf.extend_from_slice(&symtab);
+    elf.extend_from_slice(&strtab);
+
+    // NOTE: セクションヘッダ (空, .symtab, .strtab)
+    let section = |sh_type: u32, offset: usize, size: usize, link: u32, info: u32, entsize: u64| {
+        let mut header = Vec::new();
+        header.extend_from_slice(&0u32.to_le_bytes()); // sh_name
+        header.extend_from_slice(&sh_type.to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes()); // sh_flags
+        header.extend_from_slice(&0u64.to_le_bytes()); // sh_addr
+        header.extend_from_slice(&(offset as u64).to_le_bytes());
+        header.extend_from_slice(&(size as u64).to_le_bytes());
+        header.extend_from_slice(&link.to_le_bytes());
+        header.extend_from_slice(&info.to_le_bytes());
+        header.extend_from_slice(&1u64.to_le_bytes()); // sh_addralign
+        header.extend_from_slice(&entsize.to_le_bytes());
+        header
+    };
+    elf.extend(section(0, 0, 0, 0, 0, 0));
+    elf.extend(section(2, symtab_offset, symtab.len(), 2, 1, 24));
+    elf.extend(section(3, strtab_offset, strtab.len(), 0, 0, 0));
+    elf
+}
+
+/// riscv_test_code(expected) の ELF を一時ファイルに書き出して実行します。
+fn run_riscv_test(name: &str, expected: u32) -> Result<(), Exception> {
+    let path = std::env::temp_dir().join(format!("riscv-emu-{}-{}.elf", name, std::process::id()));
+    fs::write(&path, riscv_test_elf(&riscv_test_code(expected))).unwrap();
+    let result = std::panic::catch_unwind(|| run_vm(&path));
+    fs::remove_file(&path).unwrap();
+    result.unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+#[test]
+fn test_riscv_test_pass() -> Result<(), Exception> {
+    run_riscv_test("pass", 42)
+}
+
+#[test]
+#[should_panic(expected = "Test case 3 failed.")]
+fn test_riscv_test_fail() {
+    let _ = run_riscv_test("fail", 41);
+}
+
 // #[test]
 // fn test_all_bytecodes() -> Result<(), Exception> {
 //     let fixtures_dir = Path::new("tests/fixtures/bytecodes");
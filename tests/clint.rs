@@ -0,0 +1,117 @@
+use std::{thread, time::Duration};
+
+use riscv_emu::{Bus, Clint, ClockSource, Cpu, Device, Memory};
+
+/// CLINT を配置し、s0 = CLINT の先頭アドレスとして body を実行します。
+///
+/// M モードのハンドラは、handler を実行した後に停止します。
+fn run_clint(body: &[u32], handler: &[u32]) -> Cpu {
+    let offset = 4 * (4 + body.len() + 1) as u32;
+    let mut code = vec![
+        0x00000297, // auipc t0, 0
+        0x00028293 | (offset << 20), // addi t0, t0, handler
+        0x30529073, // csrw  mtvec, t0
+        0x02000437, // lui   s0, 0x2000   (s0 = CLINT)
+    ];
+    code.extend_from_slice(body);
+    code.push(0x0000006f); // j .
+    code.extend_from_slice(handler);
+    code.push(0x0000006f); // j .
+
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device(Clint::BASE, Clint::SIZE, Box::new(Clint::new(ClockSource::InstructionCount))).unwrap();
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..128 {
+        cpu.cycle();
+    }
+    cpu
+}
+
+#[test]
+fn test_clint_timer_interrupt() {
+    let cpu = run_clint(&[
+        0x0000c337, // lui   t1, 0xc
+        0xff830313, // addi  t1, t1, -8
+        0x00640333, // add   t1, s0, t1   (t1 = mtime)
+        0x00033383, // ld    t2, 0(t1)
+        0x02038393, // addi  t2, t2, 32
+        0x00004e37, // lui   t3, 4
+        0x01c40e33, // add   t3, s0, t3   (t3 = mtimecmp)
+        0x007e3023, // sd    t2, 0(t3)
+        0x08000e93, // li    t4, 0x80     (MTIE)
+        0x304ea073, // csrs  mie, t4
+        0x30046073, // csrsi mstatus, 8
+    ], &[
+        0x34202573, // csrr  a0, mcause
+        0xc01025f3, // rdtime a1
+        0x000e3603, // ld    a2, 0(t3)
+        0x344026f3, // csrr  a3, mip
+    ]);
+    assert_eq!(cpu.read_register(10), (1 << 63) | 7);
+    // NOTE: time CSR は mtime の値を映し、mtime が mtimecmp に達した時点で割り込みが発生する
+    let (time, mtimecmp) = (cpu.read_register(11), cpu.read_register(12));
+    assert!((mtimecmp..mtimecmp + 4).contains(&time), "time = {time}, mtimecmp = {mtimecmp}");
+    assert_eq!(cpu.read_register(13), 0x80);
+}
+
+#[test]
+fn test_clint_software_interrupt() {
+    let cpu = run_clint(&[
+        0x00800e93, // li    t4, 8        (MSIE)
+        0x304ea073, // csrs  mie, t4
+        0x30046073, // csrsi mstatus, 8
+        0x00100313, // li    t1, 1
+        0x00642023, // sw    t1, 0(s0)    (msip = 1)
+    ], &[
+        0x34202573, // csrr  a0, mcause
+        0x00042583, // lw    a1, 0(s0)
+        0x00042023, // sw    zero, 0(s0)  (msip = 0)
+        0x34402673, // csrr  a2, mip
+    ]);
+    assert_eq!(cpu.read_register(10), (1 << 63) | 3);
+    assert_eq!(cpu.read_register(11), 1);
+    assert_eq!(cpu.read_register(12), 0);
+}
+
+#[test]
+fn test_clint_registers() {
+    let mut clint = Clint::new(ClockSource::InstructionCount);
+    assert_eq!(clint.interrupts(), 0);
+
+    // NOTE: 64bit のレジスタは、32bit ずつでも読み書きできる
+    clint.write(0xbffc, 0x1, 4).unwrap();
+    clint.write(0xbff8, 0xffff_fffe, 4).unwrap();
+    assert_eq!(clint.read(0xbff8, 8).unwrap(), 0x1_ffff_fffe);
+    clint.tick();
+    clint.tick();
+    assert_eq!((clint.read(0xbff8, 4).unwrap(), clint.read(0xbffc, 4).unwrap()), (0, 2));
+
+    clint.write(0x4000, 0x2_0000_0001, 8).unwrap();
+    assert_eq!(clint.interrupts(), 0);
+    clint.tick();
+    assert_eq!(clint.interrupts(), 1 << 7);
+    clint.write(0x4004, 0x3, 4).unwrap();
+    assert_eq!(clint.read(0x4000, 8).unwrap(), 0x3_0000_0001);
+    assert_eq!(clint.interrupts(), 0);
+
+    clint.write(0x0, 0xffff_ffff, 4).unwrap();
+    assert_eq!(clint.read(0x0, 4).unwrap(), 1);
+    assert_eq!(clint.interrupts(), 1 << 3);
+    assert!(clint.read(0x4002, 4).is_err());
+    assert!(clint.read(0x4, 4).is_err());
+}
+
+#[test]
+fn test_clint_wall_clock() {
+    let mut clint = Clint::new(ClockSource::WallClock { frequency: 1_000_000 });
+    clint.write(0xbff8, 1000, 8).unwrap();
+    thread::sleep(Duration::from_millis(5));
+    // NOTE: 実時間モードでは、tick ではなくホストの経過時間で mtime が進む
+    let mtime = clint.read(0xbff8, 8).unwrap();
+    assert!(mtime >= 1000 + 5000, "mtime = {mtime}");
+    assert!(clint.mtime().unwrap() >= mtime);
+}
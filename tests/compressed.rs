@@ -0,0 +1,13 @@
+use riscv_emu::{Bus, Cpu, Instruction, Memory};
+
+#[test]
+fn test_c_addi4spn_decoding() {
+    let cpu = Cpu::new(Bus::new(Memory::new(1024)));
+    let decode = |raw| cpu.decode(raw).map(|ctx| ctx.instruction);
+
+    // NOTE: rd' = 0 は x8 を表すので、不正命令ではない
+    assert!(matches!(decode(0x0800), Ok(Instruction::ADDI { rd: 8, rs1: 2, imm: 16 }))); // c.addi4spn s0, sp, 16
+    assert!(matches!(decode(0x1ffc), Ok(Instruction::ADDI { rd: 15, rs1: 2, imm: 1020 }))); // c.addi4spn a5, sp, 1020
+    // NOTE: nzuimm = 0 は予約済み (0x0000 は不正命令)
+    assert!(decode(0x0000).is_err());
+}
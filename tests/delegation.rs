@@ -0,0 +1,165 @@
+use riscv_emu::{Bus, Cpu, Memory, PrivilegeMode};
+
+/// トラップの処理結果
+struct Delegated {
+    /// S モードのハンドラで読み取った値 (scause, sepc, stval, sstatus)
+    supervisor: (u64, u64, u64, u64),
+    /// M モードのハンドラで読み取った mcause
+    mcause: u64,
+    /// 全ビットを書き込んだ後の medeleg
+    medeleg: u64,
+    /// トラップ後の特権モード
+    mode: PrivilegeMode,
+}
+
+/// medeleg の全ビットを立ててから U モード (setup によっては S モード) に降り、trigger を実行します。
+fn run_delegated(setup: u32, trigger: u32) -> Delegated {
+    let code: Vec<u32> = vec![
+        0xfff00293, // li    t0, -1
+        0x3b029073, // csrw  pmpaddr0, t0
+        0x01f00293, // li    t0, 0x1f
+        0x3a029073, // csrw  pmpcfg0, t0  (NAPOT, RWX: 全アドレス空間へのアクセスを許可)
+        0x00000297, // auipc t0, 0
+        0x05c28293, // addi  t0, t0, 92   (t0 = mhandler)
+        0x30529073, // csrw  mtvec, t0
+        0x00000297, // auipc t0, 0
+        0x03c28293, // addi  t0, t0, 60   (t0 = shandler)
+        0x10529073, // csrw  stvec, t0
+        0xfff00293, // li    t0, -1
+        0x30229073, // csrw  medeleg, t0
+        0x302027f3, // csrr  a5, medeleg
+        0x000013b7, // lui   t2, 1
+        0x80038393, // addi  t2, t2, -2048 (t2 = MPP = S)
+        setup,      //
+        0x00000317, // auipc t1, 0
+        0x01030313, // addi  t1, t1, 16   (t1 = lower)
+        0x34131073, // csrw  mepc, t1
+        0x30200073, // mret
+        // lower:
+        trigger,    //                    (0x8000_0050)
+        0x0000006f, // j     .
+        // shandler:
+        0x14202573, // csrr  a0, scause
+        0x141025f3, // csrr  a1, sepc
+        0x14302673, // csrr  a2, stval
+        0x100026f3, // csrr  a3, sstatus
+        0x0000006f, // j     .
+        // mhandler:
+        0x34202773, // csrr  a4, mcause
+        0x0000006f, // j     .
+    ];
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..48 {
+        cpu.cycle();
+    }
+    Delegated {
+        supervisor: (cpu.read_register(10), cpu.read_register(11), cpu.read_register(12), cpu.read_register(13)),
+        mcause: cpu.read_register(14),
+        medeleg: cpu.read_register(15),
+        mode: cpu.mode(),
+    }
+}
+
+const NOP: u32 = 0x00000013;
+const SET_MPP_S: u32 = 0x3003a073; // csrs mstatus, t2
+
+#[test]
+fn test_medeleg_warl() {
+    let result = run_delegated(NOP, 0x00000073);
+    // NOTE: M モードからの ECALL (11) と予約済みのビットは委譲できない
+    assert_eq!(result.medeleg & (1 << 11), 0);
+    assert_eq!(result.medeleg & (1 << 10), 0);
+    assert_eq!(result.medeleg & (1 << 14), 0);
+    // NOTE: ページフォールトは委譲できる
+    assert_ne!(result.medeleg & (1 << 12), 0);
+    assert_ne!(result.medeleg & (1 << 13), 0);
+    assert_ne!(result.medeleg & (1 << 15), 0);
+}
+
+#[test]
+fn test_supervisor_csr_views() {
+    let code: Vec<u32> = vec![
+        0xfff00293, // li    t0, -1
+        0x10029073, // csrw  sstatus, t0
+        0x30002573, // csrr  a0, mstatus
+        0x100025f3, // csrr  a1, sstatus
+        0x00200313, // li    t1, 2
+        0x30331073, // csrw  mideleg, t1  (SSIP のみ委譲)
+        0x10429073, // csrw  sie, t0
+        0x30402673, // csrr  a2, mie
+        0x104026f3, // csrr  a3, sie
+        0x30401073, // csrw  mie, zero
+        0x22000313, // li    t1, 0x220
+        0x34431073, // csrw  mip, t1      (STIP, SEIP)
+        0x14402773, // csrr  a4, sip
+        0x22200313, // li    t1, 0x222
+        0x30331073, // csrw  mideleg, t1  (SSIP, STIP, SEIP を委譲)
+        0x14429073, // csrw  sip, t0
+        0x344027f3, // csrr  a5, mip
+        0x14401073, // csrw  sip, zero
+        0x14402873, // csrr  a6, sip
+        0x10200313, // li    t1, 0x102
+        0x10531073, // csrw  stvec, t1    (MODE = 2 は予約済み)
+        0x105028f3, // csrr  a7, stvec
+        0x10100313, // li    t1, 0x101
+        0x10531073, // csrw  stvec, t1    (MODE = Vectored)
+        0x10502973, // csrr  s2, stvec
+        0x0000006f, // j     .
+    ];
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..code.len() {
+        cpu.cycle();
+    }
+
+    // NOTE: sstatus への書き込みは、M モード専用のビット (MIE, MPIE, MPP, MPRV, TVM, TW, TSR) を変更しない
+    assert_eq!(cpu.read_register(10) & 0x72_1888, 0);
+    assert_eq!(cpu.read_register(11) & 0x0c_0122, 0x0c_0122); // SIE, SPIE, SPP, SUM, MXR
+    // NOTE: sie, sip は mideleg で委譲された割り込みのみ読み書きできる
+    assert_eq!((cpu.read_register(12), cpu.read_register(13)), (0x002, 0x002));
+    assert_eq!(cpu.read_register(14), 0);
+    // NOTE: sip から書き込めるのは SSIP のみ
+    assert_eq!(cpu.read_register(15), 0x222);
+    assert_eq!(cpu.read_register(16), 0x220);
+    // NOTE: stvec の予約済みの MODE は保持されない
+    assert_eq!(cpu.read_register(17), 0x100);
+    assert_eq!(cpu.read_register(18), 0x101);
+}
+
+#[test]
+fn test_delegated_ecall_from_user_mode() {
+    let result = run_delegated(NOP, 0x00000073); // ecall
+    let (scause, sepc, _, sstatus) = result.supervisor;
+    assert_eq!(scause, 8);
+    assert_eq!(sepc, 0x8000_0050);
+    assert_eq!((sstatus >> 8) & 1, 0); // SPP = U
+    assert_eq!(result.mcause, 0);
+    assert_eq!(result.mode, PrivilegeMode::Supervisor);
+}
+
+#[test]
+fn test_delegated_ecall_from_supervisor_mode() {
+    let result = run_delegated(SET_MPP_S, 0x00000073); // ecall
+    let (scause, sepc, _, sstatus) = result.supervisor;
+    assert_eq!(scause, 9);
+    assert_eq!(sepc, 0x8000_0050);
+    assert_eq!((sstatus >> 8) & 1, 1); // SPP = S
+    assert_eq!(result.mcause, 0);
+}
+
+#[test]
+fn test_delegated_illegal_instruction() {
+    let result = run_delegated(SET_MPP_S, 0x30200073); // mret
+    let (scause, _, stval, _) = result.supervisor;
+    assert_eq!(scause, 2);
+    assert_eq!(stval, 0x30200073);
+    assert_eq!(result.mcause, 0);
+}
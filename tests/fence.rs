@@ -0,0 +1,40 @@
+use riscv_emu::{Bus, Cpu, Instruction, Memory};
+
+#[test]
+fn test_fence_decoding() {
+    let cpu = Cpu::new(Bus::new(Memory::new(1024)));
+    let decode = |raw| cpu.decode(raw).unwrap().instruction;
+
+    assert!(matches!(decode(0x0ff0000f), Instruction::FENCE { pred: 0b1111, succ: 0b1111, fm: 0 })); // fence
+    assert!(matches!(decode(0x0210000f), Instruction::FENCE { pred: 0b0010, succ: 0b0001, fm: 0 })); // fence r, w
+    assert!(matches!(decode(0x8330000f), Instruction::FENCETSO)); // fence.tso
+    assert!(matches!(decode(0x0100000f), Instruction::PAUSE)); // pause
+    assert!(matches!(decode(0x0000100f), Instruction::FENCEI)); // fence.i
+}
+
+#[test]
+fn test_self_modifying_store_is_visible() {
+    // NOTE: 命令をキャッシュしていないので FENCE.I がなくても書き換えた命令は見える (FENCE.I の効果自体は確認できない)
+    let code: Vec<u32> = vec![
+        0x00000297, // auipc t0, 0
+        0x02a00337, // lui   t1, 0x2a00
+        0x51330313, // addi  t1, t1, 0x513 (t1 = li a0, 42)
+        0x0262a023, // sw    t1, 32(t0)
+        0x0000100f, // fence.i
+        0x0ff0000f, // fence
+        0x8330000f, // fence.tso
+        0x0100000f, // pause
+        0x00100513, // li    a0, 1         (書き換えられる)
+        0x0000006f, // j     .
+    ];
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..16 {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.read_register(10), 42);
+}
@@ -1,4 +1,4 @@
-use riscv_emu::{Bus, Cpu, Instruction, Memory, RawInstruction, RawShortInstruction};
+use riscv_emu::{Bus, Cpu, Memory};
 
 #[test]
 fn test_fibonacci() -> Result<(), Box<dyn std::error::Error>> {
@@ -6,6 +6,9 @@ fn test_fibonacci() -> Result<(), Box<dyn std::error::Error>> {
     let mut bus = Bus::new(memory);
 
     let code: Vec<u8> = vec![
+        0x17, 0x0e, 0x00, 0x00, // auipc t3, 0
+        0x13, 0x0e, 0x4e, 0x03, // addi t3, t3, 52   (t3 = handler)
+        0x73, 0x10, 0x5e, 0x30, // csrw mtvec, t3
         0x93, 0x02, 0x05, 0x00, // mv   t0, a0       (カウンタとしてnを退避)
         0x13, 0x05, 0x00, 0x00, // li   a0, 0        (current = 0)
         0x13, 0x03, 0x10, 0x00, // li   t1, 1        (next = 1)
@@ -18,7 +21,11 @@ fn test_fibonacci() -> Result<(), Box<dyn std::error::Error>> {
         0xe3, 0x98, 0x02, 0xfe, // bne  t0, zero, -16(if n != 0 goto loop)
         // ret:
         // 0x67, 0x80, 0x00, 0x00, // ret               (jalr x0, x1, 0)
-        0x73, 0x00, 0x10, 0x00, // ebreak
+        0x73, 0x00, 0x10, 0x00, // ebreak           (0x8000_0030)
+        // handler:
+        0xf3, 0x25, 0x20, 0x34, // csrr a1, mcause
+        0x73, 0x26, 0x10, 0x34, // csrr a2, mepc
+        0x6f, 0x00, 0x00, 0x00, // j    .
     ];
     for (i, b) in code.iter().enumerate() {
         bus.write(0x8000_0000 + i as u64, *b as u64, 1).unwrap();
@@ -28,9 +35,14 @@ fn test_fibonacci() -> Result<(), Box<dyn std::error::Error>> {
     cpu.write_register(10, 10); // a0 = 10 (フィボナッチ数列の項数)
     cpu.write_register(1, 12345678); // return address
 
-    loop {
+    // NOTE: ebreak でトラップし、ハンドラの j . に到達するまでに十分なサイクル数だけ実行する
+    for _ in 0..100 {
         cpu.cycle();
-        // Fib(10) = 55
-        // assert_eq!(cpu.read_register(10), 55);
     }
+    // Fib(10) = 55
+    assert_eq!(cpu.read_register(10), 55);
+    // NOTE: ebreak によるブレークポイント例外 (mcause = 3) で止まっている
+    assert_eq!(cpu.read_register(11), 3);
+    assert_eq!(cpu.read_register(12), 0x8000_0030);
+    Ok(())
 }
@@ -0,0 +1,125 @@
+use riscv_emu::{Bus, Cpu, Memory};
+
+fn run(code: &[u32], cycles: usize) -> Cpu {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..cycles {
+        cpu.cycle();
+    }
+    cpu
+}
+
+#[test]
+fn test_float_arithmetic() {
+    let cpu = run(&[
+        0x000062b7, // lui      t0, 6
+        0x3002a073, // csrs     mstatus, t0             (FS = Dirty)
+        0x00100293, // li       t0, 1
+        0xd222f053, // fcvt.d.l ft0, t0
+        0x00300313, // li       t1, 3
+        0xd22370d3, // fcvt.d.l ft1, t1
+        0x1a101153, // fdiv.d   ft2, ft0, ft1, rtz
+        0xe2010553, // fmv.x.d  a0, ft2
+        0x1a1031d3, // fdiv.d   ft3, ft0, ft1, rup
+        0xe20185d3, // fmv.x.d  a1, ft3
+        0x00102673, // frflags  a2                      (a2 = NX)
+        0x00101073, // fsflags  zero
+        0x4011f253, // fcvt.s.d ft4, ft3
+        0xe00206d3, // fmv.x.w  a3, ft4
+        0xf20282d3, // fmv.d.x  ft5, t0                 (NaN Boxing されていない単精度値)
+        0x0052f353, // fadd.s   ft6, ft5, ft5
+        0xe0030753, // fmv.x.w  a4, ft6                 (a4 = Canonical NaN)
+        0x001027f3, // frflags  a5                      (a5 = NX: fcvt.s.d のみ)
+        0xfff00393, // li       t2, -1
+        0xd223f3d3, // fcvt.d.l ft7, t2
+        0x5a03fe53, // fsqrt.d  ft8, ft7
+        0xe20e0853, // fmv.x.d  a6, ft8                 (a6 = Canonical NaN)
+        0x001028f3, // frflags  a7                      (a7 = NV | NX)
+        0x30002973, // csrr     s2, mstatus
+        0x0000006f, // j        .
+    ], 32);
+    assert_eq!(cpu.read_register(10), 0x3fd5_5555_5555_5555);
+    assert_eq!(cpu.read_register(11), 0x3fd5_5555_5555_5556);
+    assert_eq!(cpu.read_register(12), 0b00001);
+    assert_eq!(cpu.read_register(13), 0x3eaa_aaab);
+    assert_eq!(cpu.read_register(14), 0x7fc0_0000);
+    assert_eq!(cpu.read_register(15), 0b00001);
+    assert_eq!(cpu.read_register(16), 0x7ff8_0000_0000_0000);
+    assert_eq!(cpu.read_register(17), 0b10001);
+    let mstatus = cpu.read_register(18);
+    assert_eq!((mstatus >> 13) & 0b11, 0b11); // FS = Dirty
+    assert_eq!(mstatus >> 63, 1); // SD
+}
+
+#[test]
+fn test_compressed_float_load_store() {
+    let cpu = run(&[
+        0x000062b7, // lui     t0, 6
+        0x3002a073, // csrs    mstatus, t0
+        0x00001117, // auipc   sp, 1
+        0x12300293, // li      t0, 0x123
+        0xf2028453, // fmv.d.x fs0, t0
+        0x2022_a422, // c.fsdsp fs0, 8(sp) / c.fldsp ft0, 8(sp)
+        0xe2000553, // fmv.x.d a0, ft0
+        0x00010593, // mv      a1, sp
+        0xa990_2590, // c.fld   fa2, 8(a1) / c.fsd fa2, 16(a1)
+        0x0105b603, // ld      a2, 16(a1)
+        0x0000006f, // j       .
+    ], 16);
+    assert_eq!(cpu.read_register(10), 0x123);
+    assert_eq!(cpu.read_register(12), 0x123);
+}
+
+/// setup の後に trigger を実行し、M モードのハンドラで読み取った (mcause, mtval) を返します。
+fn run_trap(setup: &[u32], trigger: u32) -> (u64, u64) {
+    let mut code = vec![
+        0x00000297, // auipc t0, 0
+        0x01028293, // addi  t0, t0, 16  (t0 = handler)
+        0x30529073, // csrw  mtvec, t0
+        0x0100006f, // j     main
+        // handler:
+        0x34202573, // csrr  a0, mcause
+        0x343025f3, // csrr  a1, mtval
+        0x0000006f, // j     .
+        // main:
+    ];
+    code.extend_from_slice(setup);
+    code.push(trigger);
+    code.push(0x0000006f); // j .
+
+    let cpu = run(&code, 16);
+    (cpu.read_register(10), cpu.read_register(11))
+}
+
+const ENABLE_FS: [u32; 2] = [
+    0x000062b7, // lui  t0, 6
+    0x3002a073, // csrs mstatus, t0
+];
+const FADD_D_DYN: u32 = 0x02107053; // fadd.d ft0, ft0, ft1, dyn
+
+#[test]
+fn test_float_disabled_is_illegal() {
+    // NOTE: リセット直後は mstatus.FS = Off
+    let (mcause, mtval) = run_trap(&[], FADD_D_DYN);
+    assert_eq!(mcause, 2);
+    assert_eq!(mtval, FADD_D_DYN as u64);
+
+    let (mcause, _) = run_trap(&[], 0x00202573); // frrm a0
+    assert_eq!(mcause, 2);
+
+    let (mcause, _) = run_trap(&ENABLE_FS, FADD_D_DYN);
+    assert_eq!(mcause, 0);
+}
+
+#[test]
+fn test_reserved_rounding_mode_is_illegal() {
+    let (mcause, _) = run_trap(&ENABLE_FS, 0x02105053); // fadd.d ft0, ft0, ft1, rm = 5
+    assert_eq!(mcause, 2);
+
+    let (mcause, _) = run_trap(&[ENABLE_FS[0], ENABLE_FS[1], 0x0022d073], FADD_D_DYN); // fsrmi 5
+    assert_eq!(mcause, 2);
+}
@@ -0,0 +1,156 @@
+use riscv_emu::{Bus, Cpu, ExitReason, Htif, Memory, MemoryBackend};
+
+const TOHOST: u64 = 0x8000_1000;
+const FROMHOST: u64 = 0x8000_1040;
+const MAGIC_MEM: u64 = 0x8000_1100;
+const MESSAGE: u64 = 0x8000_1200;
+
+/// シンボルテーブルのみを持つ、最小限の RV64 の ELF を作成します。
+fn elf_with_symbols(symbols: &[(&str, u64)]) -> Vec<u8> {
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; 24]; // NOTE: 0 番目のシンボルは空
+    for (name, value) in symbols {
+        symtab.extend_from_slice(&(strtab.len() as u32).to_le_bytes()); // st_name
+        symtab.extend_from_slice(&[0x11, 0]); // st_info (GLOBAL, OBJECT), st_other
+        symtab.extend_from_slice(&1u16.to_le_bytes()); // st_shndx
+        symtab.extend_from_slice(&value.to_le_bytes()); // st_value
+        symtab.extend_from_slice(&8u64.to_le_bytes()); // st_size
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+    }
+
+    let symtab_offset = 64;
+    let strtab_offset = symtab_offset + symtab.len();
+    let shoff = strtab_offset + strtab.len();
+
+    let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    elf.extend_from_slice(&2u16.to_le_bytes()); // e_type (EXEC)
+    elf.extend_from_slice(&0xf3u16.to_le_bytes()); // e_machine (RISC-V)
+    elf.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    elf.extend_from_slice(&0x8000_0000u64.to_le_bytes()); // e_entry
+    elf.extend_from_slice(&0u64.to_le_bytes()); // e_phoff
+    elf.extend_from_slice(&(shoff as u64).to_le_bytes()); // e_shoff
+    elf.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    for half in [64u16, 56, 0, 64, 3, 0] {
+        elf.extend_from_slice(&half.to_le_bytes()); // e_ehsize, e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx
+    }
+    elf.extend_from_slice(&symtab);
+    elf.extend_from_slice(&strtab);
+
+    // NOTE: セクションヘッダ (空, .symtab, .strtab)
+    let section = |sh_type: u32, offset: usize, size: usize, link: u32, info: u32, entsize: u64| {
+        let mut header = Vec::new();
+        header.extend_from_slice(&0u32.to_le_bytes()); // sh_name
+        header.extend_from_slice(&sh_type.to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes()); // sh_flags
+        header.extend_from_slice(&0u64.to_le_bytes()); // sh_addr
+        header.extend_from_slice(&(offset as u64).to_le_bytes());
+        header.extend_from_slice(&(size as u64).to_le_bytes());
+        header.extend_from_slice(&link.to_le_bytes());
+        header.extend_from_slice(&info.to_le_bytes());
+        header.extend_from_slice(&1u64.to_le_bytes()); // sh_addralign
+        header.extend_from_slice(&entsize.to_le_bytes());
+        header
+    };
+    elf.extend(section(0, 0, 0, 0, 0, 0));
+    elf.extend(section(2, symtab_offset, symtab.len(), 2, 1, 24));
+    elf.extend(section(3, strtab_offset, strtab.len(), 0, 0, 0));
+    elf
+}
+
+#[test]
+fn test_htif_from_elf() {
+    let console = MemoryBackend::new();
+    let elf = elf_with_symbols(&[("begin_signature", 0x8000_2000), ("tohost", TOHOST), ("fromhost", FROMHOST)]);
+    let htif = Htif::from_elf(&elf, Box::new(console.clone())).unwrap();
+    assert!(Htif::from_elf(&elf_with_symbols(&[("fromhost", FROMHOST)]), Box::new(console.clone())).is_none());
+    assert!(Htif::from_elf(b"not an elf", Box::new(console.clone())).is_none());
+
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.attach_device(Box::new(htif));
+
+    let code: [u32; 19] = [
+        0x400012b7, // lui   t0, 0x40001
+        0x80028293, // addi  t0, t0, -2048
+        0x00129293, // slli  t0, t0, 1    (t0 = tohost)
+        0x10028313, // addi  t1, t0, 0x100 (t1 = magic_mem)
+        0x0062b023, // sd    t1, 0(t0)    (システムコール)
+        0x0402b383, // ld    t2, 64(t0)   (fromhost)
+        0xfe038ee3, // beqz  t2, -4
+        0x0402b023, // sd    zero, 64(t0)
+        0x1002b503, // ld    a0, 256(t0)  (戻り値)
+        0x10100e13, // li    t3, 0x101
+        0x030e1e13, // slli  t3, t3, 48
+        0x041e0e13, // addi  t3, t3, 'A'
+        0x01c2b023, // sd    t3, 0(t0)    (コンソールに 1 文字出力)
+        0x0402b383, // ld    t2, 64(t0)
+        0xfe038ee3, // beqz  t2, -4
+        0x00038593, // mv    a1, t2
+        0x00700e13, // li    t3, 7
+        0x01c2b023, // sd    t3, 0(t0)    (終了コード 3 で終了)
+        0x0000006f, // j     .
+    ];
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+    // NOTE: magic_mem = write(1, MESSAGE, 3)
+    for (i, arg) in [64, 1, MESSAGE, 3].into_iter().enumerate() {
+        bus.write(MAGIC_MEM + i as u64 * 8, arg, 8).unwrap();
+    }
+    for (i, byte) in b"hi\n".iter().enumerate() {
+        bus.write(MESSAGE + i as u64, *byte as u64, 1).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    assert_eq!(cpu.run(1000), Some(ExitReason::Poweroff(3)));
+    assert_eq!(cpu.read_register(10), 3);
+    assert_eq!(cpu.read_register(11), (1 << 56) | (1 << 48));
+    assert_eq!(console.take_output(), b"hi\nA");
+
+    // NOTE: 終了した後は、サイクルを進めても何も実行されない
+    cpu.cycle();
+    assert_eq!(cpu.exit_reason(), Some(ExitReason::Poweroff(3)));
+}
+
+#[test]
+fn test_htif_write_out_of_ram() {
+    let console = MemoryBackend::new();
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.attach_device(Box::new(Htif::new(TOHOST, Some(FROMHOST), Box::new(console.clone()))));
+
+    let code: [u32; 9] = [
+        0x400012b7, // lui   t0, 0x40001
+        0x80028293, // addi  t0, t0, -2048
+        0x00129293, // slli  t0, t0, 1    (t0 = tohost)
+        0x10028313, // addi  t1, t0, 0x100 (t1 = magic_mem)
+        0x0062b023, // sd    t1, 0(t0)    (システムコール)
+        0x0402b383, // ld    t2, 64(t0)   (fromhost)
+        0xfe038ee3, // beqz  t2, -4
+        0x1002b503, // ld    a0, 256(t0)  (戻り値)
+        0x0000006f, // j     .
+    ];
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+    // NOTE: magic_mem = write(1, MESSAGE, u64::MAX)
+    for (i, arg) in [64, 1, MESSAGE, u64::MAX].into_iter().enumerate() {
+        bus.write(MAGIC_MEM + i as u64 * 8, arg, 8).unwrap();
+    }
+
+    // NOTE: RAM に収まらないバッファは確保せず、-EFAULT を返す
+    let mut cpu = Cpu::new(bus);
+    assert_eq!(cpu.run(100), None);
+    assert_eq!(cpu.read_register(10), -14i64 as u64);
+    assert!(console.take_output().is_empty());
+}
+
+#[test]
+fn test_htif_instruction_limit() {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.attach_device(Box::new(Htif::new(TOHOST, None, Box::new(MemoryBackend::new()))));
+    bus.write(0x8000_0000, 0x0000006f, 4).unwrap(); // j .
+
+    let mut cpu = Cpu::new(bus);
+    assert_eq!(cpu.run(100), None);
+    assert_eq!(cpu.exit_reason(), None);
+}
@@ -0,0 +1,63 @@
+use riscv_emu::{Bus, Cpu, Memory};
+
+fn load(code: &[u32]) -> Cpu {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+    Cpu::new(bus)
+}
+
+#[test]
+fn test_vectored_software_interrupt() {
+    let mut cpu = load(&[
+        0x00000297, // auipc t0, 0
+        0x02828293, // addi  t0, t0, 40   (t0 = vector table)
+        0x0012e293, // ori   t0, t0, 1    (MODE = Vectored)
+        0x30529073, // csrw  mtvec, t0
+        0x00200313, // li    t1, 2        (SSIP)
+        0x30432073, // csrs  mie, t1
+        0x34432073, // csrs  mip, t1
+        0x30046073, // csrsi mstatus, 8   (MIE = 1)
+        0x00100593, // li    a1, 1        (0x8000_0020: 割り込みにより実行されない)
+        0x0000006f, // j     .
+        // vector table:
+        0x0000006f, // j     .            (例外)
+        0x34202573, // csrr  a0, mcause   (Supervisor Software Interrupt)
+        0x341025f3, // csrr  a1, mepc
+        0x0000006f, // j     .
+    ]);
+    for _ in 0..32 {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.read_register(10), (1 << 63) | 1);
+    assert_eq!(cpu.read_register(11), 0x8000_0020);
+}
+
+#[test]
+fn test_wfi_waits_for_interrupt() {
+    let code = |set_pending: u32| [
+        0x00200313, // li    t1, 2        (SSIP)
+        0x30432073, // csrs  mie, t1
+        set_pending,
+        0x10500073, // wfi
+        0x02a00513, // li    a0, 42
+        0x0000006f, // j     .
+    ];
+
+    // NOTE: 割り込みが保留されていなければ、WFI で止まり続ける
+    let mut cpu = load(&code(0x00000013)); // nop
+    for _ in 0..32 {
+        cpu.cycle();
+    }
+    assert!(cpu.is_waiting());
+    assert_eq!(cpu.read_register(10), 0);
+
+    // NOTE: mstatus.MIE = 0 でも、保留中の割り込みがあれば WFI から復帰する (トラップはしない)
+    let mut cpu = load(&code(0x34432073)); // csrs mip, t1
+    for _ in 0..32 {
+        cpu.cycle();
+    }
+    assert!(!cpu.is_waiting());
+    assert_eq!(cpu.read_register(10), 42);
+}
@@ -0,0 +1,375 @@
+use riscv_emu::{Bus, Cpu, Memory, TlbStats, TranslationMode};
+
+/// ルートページテーブルの物理アドレス (satp.PPN = 0x80100)
+const ROOT: u64 = 0x8010_0000;
+/// 2 段目のページテーブルの物理アドレス
+const LEVEL1: u64 = 0x8010_1000;
+/// 3 段目のページテーブルの物理アドレス
+const LEVEL0: u64 = 0x8010_2000;
+/// Sv48 のルートページテーブルの物理アドレス (0 番目のエントリが ROOT を指す)
+const SV48_ROOT: u64 = 0x8010_3000;
+/// Sv57 のルートページテーブルの物理アドレス (0 番目のエントリが SV48_ROOT を指す)
+const SV57_ROOT: u64 = 0x8010_4000;
+
+const V: u64 = 1 << 0;
+const R: u64 = 1 << 1;
+const W: u64 = 1 << 2;
+const X: u64 = 1 << 3;
+const U: u64 = 1 << 4;
+const A: u64 = 1 << 6;
+const D: u64 = 1 << 7;
+
+/// 物理アドレスと権限ビットから PTE を作成します。
+fn pte(addr: u64, flags: u64) -> u64 {
+    ((addr >> 12) << 10) | flags
+}
+
+/// ページテーブルを構築して Sv39 を有効にし、S モードに降りて body を実行します。
+fn run_paged(setup: &[u32], body: &[u32]) -> Cpu {
+    run_paged_in(TranslationMode::Sv39, setup, body)
+}
+
+/// ページテーブルを構築して mode のアドレス変換を有効にし、S モードに降りて body を実行します。
+///
+/// - 0x8000_0000 からの 1GiB は恒等写像 (ギガページ、U = 0) です。
+/// - 0x4000_0000 (s0) からの 4 ページは、それぞれ RW (A = D = 0), R, RWXU, X のページです。
+///
+/// setup は satp の設定後に M モードのまま実行されます。
+/// M モードのハンドラは (a0, a1) = (mcause, mtval) を、S モードのハンドラは (a2, a3) = (scause, stval) を記録します。
+fn run_paged_in(mode: TranslationMode, setup: &[u32], body: &[u32]) -> Cpu {
+    let root = match mode {
+        TranslationMode::Sv39 => ROOT,
+        TranslationMode::Sv48 => SV48_ROOT,
+        TranslationMode::Sv57 => SV57_ROOT,
+    };
+    let mut code = vec![
+        0xfff00293, // li    t0, -1
+        0x3b029073, // csrw  pmpaddr0, t0
+        0x01f00293, // li    t0, 0x1f
+        0x3a029073, // csrw  pmpcfg0, t0  (NAPOT, RWX: 全アドレス空間へのアクセスを許可)
+        0x00000297, // auipc t0, 0
+        0x01828293, // addi  t0, t0, 24   (t0 = mhandler)
+        0x30529073, // csrw  mtvec, t0
+        0x00c28293, // addi  t0, t0, 12   (t0 = shandler)
+        0x10529073, // csrw  stvec, t0
+        0x01c0006f, // j     main
+        // mhandler:
+        0x34202573, // csrr  a0, mcause
+        0x343025f3, // csrr  a1, mtval
+        0x0000006f, // j     .
+        // shandler:
+        0x14202673, // csrr  a2, scause
+        0x143026f3, // csrr  a3, stval
+        0x0000006f, // j     .
+        // main:
+        0x00000293 | (mode.bits() as u32) << 20, // li t0, MODE
+        0x03c29293, // slli  t0, t0, 60
+        0x00080337, // lui   t1, 0x80
+        0x00030313 | ((root >> 12) as u32 & 0xfff) << 20, // addi t1, t1, PPN & 0xfff
+        0x0062e2b3, // or    t0, t0, t1
+        0x18029073, // csrw  satp, t0
+        0x000013b7, // lui   t2, 1
+        0x80038393, // addi  t2, t2, -2048 (t2 = MPP = S)
+        0x3003a073, // csrs  mstatus, t2
+        0x40000437, // lui   s0, 0x40000
+    ];
+    code.extend_from_slice(setup);
+    code.extend_from_slice(&[
+        0x00000317, // auipc t1, 0
+        0x01030313, // addi  t1, t1, 16   (t1 = body)
+        0x34131073, // csrw  mepc, t1
+        0x30200073, // mret
+    ]);
+    code.extend_from_slice(body);
+    code.push(0x0000006f); // j .
+
+    let mut bus = Bus::new(Memory::new(4 * 1024 * 1024));
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+    bus.write(SV57_ROOT, pte(SV48_ROOT, V), 8).unwrap();
+    bus.write(SV48_ROOT, pte(ROOT, V), 8).unwrap();
+    bus.write(ROOT + 2 * 8, pte(0x8000_0000, V | R | W | X | A | D), 8).unwrap();
+    bus.write(ROOT + 8, pte(LEVEL1, V), 8).unwrap();
+    bus.write(LEVEL1, pte(LEVEL0, V), 8).unwrap();
+    bus.write(LEVEL0, pte(0x8020_0000, V | R | W), 8).unwrap();
+    bus.write(LEVEL0 + 8, pte(0x8020_1000, V | R | A), 8).unwrap();
+    bus.write(LEVEL0 + 2 * 8, pte(0x8020_2000, V | R | W | X | U | A | D), 8).unwrap();
+    bus.write(LEVEL0 + 3 * 8, pte(0x8020_3000, V | X | A), 8).unwrap();
+    bus.write(0x8020_0ffc, 0xaabb_ccdd, 4).unwrap();
+    bus.write(0x8020_1000, 0x1234, 8).unwrap();
+    bus.write(0x8020_2000, 0x2222, 8).unwrap();
+    bus.write(0x8020_3000, 0x3333, 8).unwrap();
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..64 {
+        cpu.cycle();
+    }
+    cpu
+}
+
+/// M モードにトラップした際の (mcause, mtval) を取得します。
+fn run_fault(setup: &[u32], body: &[u32]) -> (u64, u64) {
+    let cpu = run_paged(setup, body);
+    (cpu.read_register(10), cpu.read_register(11))
+}
+
+/// menvcfg.ADUE = 1 (ハードウェアで A / D ビットを更新する)
+const ENABLE_ADUE: [u32; 3] = [
+    0x00100293, // li    t0, 1
+    0x03d29293, // slli  t0, t0, 61
+    0x30a2a073, // csrs  menvcfg, t0
+];
+
+#[test]
+fn test_sv39_translation() {
+    let cpu = run_paged(&ENABLE_ADUE, &[
+        0x05500313, // li    t1, 0x55
+        0x00643023, // sd    t1, 0(s0)
+        0x00043703, // ld    a4, 0(s0)
+        0x000012b7, // lui   t0, 1
+        0x005402b3, // add   t0, s0, t0
+        0x0002b783, // ld    a5, 0(t0)    (読み取り専用のページ)
+        0xffc2b883, // ld    a7, -4(t0)   (ページ境界をまたぐ)
+        0x400812b7, // lui   t0, 0x40081
+        0x00129293, // slli  t0, t0, 1    (t0 = 0x8010_2000)
+        0x0002b803, // ld    a6, 0(t0)    (恒等写像を通して PTE を読む)
+    ]);
+    assert_eq!(cpu.read_register(10), 0);
+    assert_eq!(cpu.read_register(14), 0x55);
+    assert_eq!(cpu.read_register(15), 0x1234);
+    assert_eq!(cpu.read_register(17), 0x1234_aabb_ccdd);
+    // NOTE: ストアによって A / D ビットが立つ
+    assert_eq!(cpu.read_register(16), pte(0x8020_0000, V | R | W | A | D));
+}
+
+#[test]
+fn test_sv39_page_faults() {
+    // NOTE: 読み取り専用のページへのストア
+    let store = [
+        0x000012b7, // lui   t0, 1
+        0x005402b3, // add   t0, s0, t0
+        0x0062b423, // sd    t1, 8(t0)
+    ];
+    assert_eq!(run_fault(&ENABLE_ADUE, &store), (15, 0x4000_1008));
+
+    // NOTE: 読み取り専用のページへの AMO も、ストアのページフォールト
+    let amo = [
+        0x000012b7, // lui      t0, 1
+        0x005402b3, // add      t0, s0, t0
+        0x0062b72f, // amoadd.d a4, t1, (t0)
+    ];
+    assert_eq!(run_fault(&ENABLE_ADUE, &amo), (15, 0x4000_1000));
+
+    // NOTE: マップされていないページからのロード
+    let unmapped = [
+        0x000052b7, // lui   t0, 5
+        0x005402b3, // add   t0, s0, t0
+        0x1002b72f, // lr.d  a4, (t0)
+    ];
+    assert_eq!(run_fault(&ENABLE_ADUE, &unmapped), (13, 0x4000_5000));
+
+    // NOTE: 符号拡張されていない仮想アドレス
+    let non_canonical = [
+        0x00100293, // li    t0, 1
+        0x02729293, // slli  t0, t0, 39
+        0x0002b703, // ld    a4, 0(t0)
+    ];
+    assert_eq!(run_fault(&ENABLE_ADUE, &non_canonical), (13, 1 << 39));
+
+    // NOTE: S モードは U = 1 のページの命令を実行できない
+    let fetch = [
+        0x000022b7, // lui   t0, 2
+        0x005402b3, // add   t0, s0, t0
+        0x00028067, // jr    t0
+    ];
+    assert_eq!(run_fault(&ENABLE_ADUE, &fetch), (12, 0x4000_2000));
+
+    // NOTE: ページ境界をまたぐアクセスでは、フォールトした後半のページのアドレスが報告される
+    let split = [
+        0x000022b7, // lui   t0, 2
+        0x005402b3, // add   t0, s0, t0
+        0xffc2b883, // ld    a7, -4(t0)
+    ];
+    assert_eq!(run_fault(&ENABLE_ADUE, &split), (13, 0x4000_2000));
+
+    // NOTE: menvcfg.ADUE = 0 の場合、A = 0 のページへのアクセスはページフォールトになる
+    let access = [
+        0x00043703, // ld    a4, 0(s0)
+    ];
+    assert_eq!(run_fault(&[], &access), (13, 0x4000_0000));
+}
+
+#[test]
+fn test_sv39_sum_mxr_mprv() {
+    let user_page = [
+        0x000022b7, // lui   t0, 2
+        0x005402b3, // add   t0, s0, t0
+        0x0002b783, // ld    a5, 0(t0)
+    ];
+    let exec_only_page = [
+        0x000032b7, // lui   t0, 3
+        0x005402b3, // add   t0, s0, t0
+        0x0002b783, // ld    a5, 0(t0)
+    ];
+
+    // NOTE: SUM = 0 の場合、S モードは U = 1 のページを読めない
+    assert_eq!(run_fault(&[], &user_page), (13, 0x4000_2000));
+    let cpu = run_paged(&[
+        0x000402b7, // lui   t0, 0x40
+        0x3002a073, // csrs  mstatus, t0  (SUM = 1)
+    ], &user_page);
+    assert_eq!(cpu.read_register(10), 0);
+    assert_eq!(cpu.read_register(15), 0x2222);
+
+    // NOTE: MXR = 0 の場合、実行専用のページは読めない
+    assert_eq!(run_fault(&[], &exec_only_page), (13, 0x4000_3000));
+    let cpu = run_paged(&[
+        0x000802b7, // lui   t0, 0x80
+        0x3002a073, // csrs  mstatus, t0  (MXR = 1)
+    ], &exec_only_page);
+    assert_eq!(cpu.read_register(10), 0);
+    assert_eq!(cpu.read_register(15), 0x3333);
+
+    // NOTE: MPRV = 1 の場合、M モードのロードは MPP (= S) のモードでアドレス変換される
+    let cpu = run_paged(&[
+        0x000202b7, // lui   t0, 0x20
+        0x3002a073, // csrs  mstatus, t0  (MPRV = 1)
+        0x000012b7, // lui   t0, 1
+        0x005402b3, // add   t0, s0, t0
+        0x0002b803, // ld    a6, 0(t0)
+    ], &[]);
+    assert_eq!(cpu.read_register(10), 0);
+    assert_eq!(cpu.read_register(16), 0x1234);
+}
+
+#[test]
+fn test_sv48_sv57_translation() {
+    for mode in [TranslationMode::Sv48, TranslationMode::Sv57] {
+        let cpu = run_paged_in(mode, &[], &[
+            0x000012b7, // lui   t0, 1
+            0x005402b3, // add   t0, s0, t0
+            0x0002b783, // ld    a5, 0(t0)
+            0x00100293, // li    t0, 1
+            0x02729293, // slli  t0, t0, 39
+            0x0002b703, // ld    a4, 0(t0)    (Sv39 では符号拡張されていないアドレス)
+        ]);
+        assert_eq!(cpu.read_register(15), 0x1234);
+        // NOTE: 上位のページテーブルのエントリが無効なのでページフォールトになる
+        assert_eq!((cpu.read_register(10), cpu.read_register(11)), (13, 1 << 39));
+    }
+}
+
+#[test]
+fn test_satp_mode_warl() {
+    let code = [
+        0x00800293, // li    t0, 8
+        0x03c29293, // slli  t0, t0, 60
+        0x18029073, // csrw  satp, t0     (Sv39)
+        0x18002573, // csrr  a0, satp
+        0x00900293, // li    t0, 9
+        0x03c29293, // slli  t0, t0, 60
+        0x18029073, // csrw  satp, t0     (Sv48)
+        0x180025f3, // csrr  a1, satp
+        0x00a00293, // li    t0, 10
+        0x03c29293, // slli  t0, t0, 60
+        0x18029073, // csrw  satp, t0     (Sv57)
+        0x18002673, // csrr  a2, satp
+        0x00100293, // li    t0, 1
+        0x03c29293, // slli  t0, t0, 60
+        0x18029073, // csrw  satp, t0     (予約済み)
+        0x180026f3, // csrr  a3, satp
+    ];
+    let run = |modes: Option<&[TranslationMode]>| {
+        let mut bus = Bus::new(Memory::new(1024 * 1024));
+        for (i, word) in code.iter().enumerate() {
+            bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+        }
+        let mut cpu = Cpu::new(bus);
+        if let Some(modes) = modes {
+            cpu.set_translation_modes(modes);
+        }
+        for _ in 0..code.len() {
+            cpu.cycle();
+        }
+        [10, 11, 12, 13].map(|reg| cpu.read_register(reg) >> 60)
+    };
+
+    // NOTE: 既定ではすべての方式をサポートし、予約済みの値の書き込みは無視される
+    assert_eq!(run(None), [8, 9, 10, 10]);
+    // NOTE: サポートしていない方式の書き込みは無視され、satp は変化しない
+    assert_eq!(run(Some(&[TranslationMode::Sv39])), [8, 8, 8, 8]);
+    assert_eq!(run(Some(&[TranslationMode::Sv48])), [0, 9, 9, 9]);
+    assert_eq!(run(Some(&[])), [0, 0, 0, 0]);
+}
+
+#[test]
+fn test_tlb_sfence_vma() {
+    let cpu = run_paged(&[], &[
+        0x000012b7, // lui        t0, 1
+        0x005402b3, // add        t0, s0, t0
+        0x0002b703, // ld         a4, 0(t0)
+        0x200813b7, // lui        t2, 0x20081
+        0xc4338393, // addi       t2, t2, -957 (t2 = 0x8020_3000 を指す PTE)
+        0x40081e37, // lui        t3, 0x40081
+        0x001e1e13, // slli       t3, t3, 1
+        0x007e3423, // sd         t2, 8(t3)
+        0x0002b783, // ld         a5, 0(t0)
+        0x00500e93, // li         t4, 5
+        0x13d00073, // sfence.vma zero, t4
+        0x0002b803, // ld         a6, 0(t0)
+        0x12028073, // sfence.vma t0, zero
+        0x0002b883, // ld         a7, 0(t0)
+    ]);
+    assert_eq!(cpu.read_register(10), 0);
+    assert_eq!(cpu.read_register(14), 0x1234);
+    // NOTE: SFENCE.VMA を実行するまでは、TLB に残っている古い変換が使われる
+    assert_eq!(cpu.read_register(15), 0x1234);
+    // NOTE: 別の ASID を指定した SFENCE.VMA では破棄されない
+    assert_eq!(cpu.read_register(16), 0x1234);
+    assert_eq!(cpu.read_register(17), 0x3333);
+
+    assert_eq!(cpu.data_tlb_stats(), TlbStats { hits: 2, misses: 3 });
+    // NOTE: 命令は 1 つのページに収まっており、アドレスを指定した SFENCE.VMA ではそのページは破棄されない
+    assert_eq!(cpu.instruction_tlb_stats().misses, 1);
+}
+
+#[test]
+fn test_tlb_stats_dirty_update() {
+    let cpu = run_paged(&ENABLE_ADUE, &[
+        0x00043703, // ld    a4, 0(s0)    (A のみが立つ)
+        0x00643023, // sd    t1, 0(s0)    (D を立てるため、ページテーブルをたどり直す)
+        0x00043783, // ld    a5, 0(s0)
+    ]);
+    assert_eq!(cpu.read_register(10), 0);
+    // NOTE: エントリが見つかっても、ページテーブルをたどり直した場合はミスとして数える
+    assert_eq!(cpu.data_tlb_stats(), TlbStats { hits: 1, misses: 2 });
+}
+
+#[test]
+fn test_trap_virtual_memory() {
+    let enable_tvm = [
+        0x001002b7, // lui   t0, 0x100
+        0x3002a073, // csrs  mstatus, t0  (TVM = 1)
+    ];
+    // NOTE: mstatus.TVM = 1 の場合、S モードでの satp へのアクセスや SFENCE.VMA は不正命令
+    assert_eq!(run_fault(&enable_tvm, &[0x18002773]), (2, 0x18002773)); // csrr a4, satp
+    assert_eq!(run_fault(&enable_tvm, &[0x12000073]), (2, 0x12000073)); // sfence.vma
+    assert_eq!(run_fault(&[], &[0x18002773, 0x12000073]), (0, 0));
+}
+
+#[test]
+fn test_delegated_page_fault() {
+    let cpu = run_paged(&[
+        0x000022b7, // lui   t0, 2
+        0x30229073, // csrw  medeleg, t0  (Load Page Fault を委譲)
+    ], &[
+        0x000052b7, // lui   t0, 5
+        0x005402b3, // add   t0, s0, t0
+        0x0002b703, // ld    a4, 0(t0)
+    ]);
+    // NOTE: S モードのハンドラが、フォールトした仮想アドレスを stval で受け取る
+    assert_eq!(cpu.read_register(10), 0);
+    assert_eq!(cpu.read_register(12), 13);
+    assert_eq!(cpu.read_register(13), 0x4000_5000);
+}
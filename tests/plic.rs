@@ -0,0 +1,153 @@
+use riscv_emu::{Bus, Cpu, Device, Memory, MemoryBackend, Plic, Uart};
+
+/// コンテキスト c のクレーム / 完了レジスタ
+const fn claim(context: u64) -> u64 {
+    0x20_0004 + context * 0x1000
+}
+
+#[test]
+fn test_plic_priority_and_claim() {
+    let mut plic = Plic::new(40);
+    for (id, priority) in [(1, 1), (2, 3), (33, 3), (40, 9)] {
+        plic.write(id * 4, priority, 4).unwrap();
+    }
+    // NOTE: 優先度は 3bit で、割り込み源 0 や存在しない割り込み源の優先度は 0 に固定される
+    assert_eq!(plic.read(40 * 4, 4).unwrap(), 1);
+    plic.write(0, 5, 4).unwrap();
+    plic.write(41 * 4, 5, 4).unwrap();
+    assert_eq!((plic.read(0, 4).unwrap(), plic.read(41 * 4, 4).unwrap()), (0, 0));
+
+    plic.write(0x2000, u32::MAX as u64, 4).unwrap();
+    plic.write(0x2004, u32::MAX as u64, 4).unwrap();
+    assert_eq!((plic.read(0x2000, 4).unwrap(), plic.read(0x2004, 4).unwrap()), (0xffff_fffe, 0x1ff));
+
+    plic.set_irq_lines(&[1, 2, 33, 41]);
+    assert_eq!((plic.read(0x1000, 4).unwrap(), plic.read(0x1004, 4).unwrap()), (0b110, 0b10));
+    assert_eq!(plic.interrupts(), 1 << 11);
+
+    // NOTE: 優先度の高い順に、同じ優先度なら番号の小さい順にクレームされる
+    assert_eq!(plic.read(claim(0), 4).unwrap(), 2);
+    assert_eq!(plic.read(claim(0), 4).unwrap(), 33);
+
+    // NOTE: 閾値以下の優先度の割り込みは通知されない
+    plic.write(0x20_0000, 1, 4).unwrap();
+    assert_eq!(plic.interrupts(), 0);
+    assert_eq!(plic.read(claim(0), 4).unwrap(), 0);
+    plic.write(0x20_0000, 0, 4).unwrap();
+    assert_eq!(plic.read(claim(0), 4).unwrap(), 1);
+
+    // NOTE: 完了するまでは、割り込み線がアサートされていても再び保留されない
+    plic.set_irq_lines(&[2]);
+    assert_eq!(plic.read(0x1000, 4).unwrap(), 0);
+    plic.write(claim(1), 2, 4).unwrap(); // NOTE: S モードのコンテキストでは許可されていないので無視される
+    plic.set_irq_lines(&[2]);
+    assert_eq!(plic.read(0x1000, 4).unwrap(), 0);
+    plic.write(claim(0), 2, 4).unwrap();
+    plic.set_irq_lines(&[2]);
+    assert_eq!(plic.read(0x1000, 4).unwrap(), 0b100);
+
+    // NOTE: S モードのコンテキストは SEIP を駆動する
+    plic.write(0x2000, 0, 4).unwrap();
+    plic.write(0x2080, 0b100, 4).unwrap();
+    assert_eq!(plic.interrupts(), 1 << 9);
+    assert!(plic.read(claim(0), 1).is_err());
+}
+
+#[test]
+fn test_plic_uart_interrupt() {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device(Plic::BASE, Plic::SIZE, Box::new(Plic::new(32))).unwrap();
+    let uart = Uart::new(Box::new(MemoryBackend::new()));
+    bus.map_device_with_irq(Uart::BASE, Uart::SIZE, Uart::IRQ, Box::new(uart)).unwrap();
+
+    let code: [u32; 28] = [
+        0x00000297, // auipc t0, 0
+        0x05428293, // addi  t0, t0, 84
+        0x30529073, // csrw  mtvec, t0
+        0x0c000437, // lui   s0, 0xc000   (s0 = PLIC)
+        0x00100313, // li    t1, 1
+        0x02642423, // sw    t1, 40(s0)   (priority[10] = 1)
+        0x000023b7, // lui   t2, 2
+        0x007403b3, // add   t2, s0, t2
+        0x40000313, // li    t1, 0x400
+        0x0063a023, // sw    t1, 0(t2)    (コンテキスト 0 で割り込み源 10 を許可)
+        0x0863a023, // sw    t1, 128(t2)  (コンテキスト 1 で割り込み源 10 を許可)
+        0x00200e37, // lui   t3, 0x200
+        0x01c40e33, // add   t3, s0, t3   (t3 = コンテキスト 0 の閾値)
+        0x00001337, // lui   t1, 1
+        0x8003031b, // addiw t1, t1, -2048 (MEIE)
+        0x30432073, // csrs  mie, t1
+        0x30046073, // csrsi mstatus, 8
+        0x100004b7, // lui   s1, 0x10000  (s1 = UART)
+        0x00200313, // li    t1, 2
+        0x006480a3, // sb    t1, 1(s1)    (IER = ETBEI)
+        0x0000006f, // j     .
+        // handler:
+        0x34202573, // csrr  a0, mcause
+        0x34402673, // csrr  a2, mip
+        0x004e2583, // lw    a1, 4(t3)    (クレーム)
+        0x344026f3, // csrr  a3, mip
+        0x00be2223, // sw    a1, 4(t3)    (完了)
+        0x34402773, // csrr  a4, mip
+        0x0000006f, // j     .
+    ];
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..code.len() + 4 {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.read_register(10), (1 << 63) | 11);
+    assert_eq!(cpu.read_register(11), Uart::IRQ as u64);
+    // NOTE: 両方のコンテキストに通知されるが、クレームで保留が解除され、完了後に割り込み線がアサートされていれば再び保留される
+    assert_eq!(cpu.read_register(12), 0xa00);
+    assert_eq!(cpu.read_register(13), 0);
+    assert_eq!(cpu.read_register(14), 0xa00);
+}
+
+#[test]
+fn test_plic_seip_not_latched_by_csrc() {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device(Plic::BASE, Plic::SIZE, Box::new(Plic::new(32))).unwrap();
+    let uart = Uart::new(Box::new(MemoryBackend::new()));
+    bus.map_device_with_irq(Uart::BASE, Uart::SIZE, Uart::IRQ, Box::new(uart)).unwrap();
+
+    let code: [u32; 22] = [
+        0x0c000437, // lui   s0, 0xc000   (s0 = PLIC)
+        0x00100313, // li    t1, 1
+        0x02642423, // sw    t1, 40(s0)   (priority[10] = 1)
+        0x000023b7, // lui   t2, 2
+        0x007403b3, // add   t2, s0, t2
+        0x40000313, // li    t1, 0x400
+        0x0863a023, // sw    t1, 128(t2)  (コンテキスト 1 で割り込み源 10 を許可)
+        0x100004b7, // lui   s1, 0x10000  (s1 = UART)
+        0x00200313, // li    t1, 2
+        0x006480a3, // sb    t1, 1(s1)    (IER = ETBEI)
+        0x00000013, // nop
+        0x344025f3, // csrr  a1, mip
+        0x02000293, // li    t0, 32       (STIP)
+        0x3442b073, // csrc  mip, t0      (割り込み線がアサートされている間の読み込み-変更-書き込み)
+        0x000480a3, // sb    zero, 1(s1)  (IER = 0)
+        0x00201e37, // lui   t3, 0x201
+        0x01c40e33, // add   t3, s0, t3   (t3 = コンテキスト 1 の閾値)
+        0x004e2603, // lw    a2, 4(t3)    (クレーム)
+        0x00ce2223, // sw    a2, 4(t3)    (完了)
+        0x00000013, // nop
+        0x34402573, // csrr  a0, mip
+        0x0000006f, // j     .
+    ];
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..code.len() + 4 {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.read_register(11) & (1 << 9), 1 << 9);
+    assert_eq!(cpu.read_register(12), Uart::IRQ as u64);
+    // NOTE: csrc は割り込み線の値をソフトウェアの SEIP に書き戻さないので、割り込み線が下がれば SEIP も 0 になる
+    assert_eq!(cpu.read_register(10) & (1 << 9), 0);
+}
@@ -0,0 +1,200 @@
+use riscv_emu::{Bus, Cpu, Memory};
+
+/// M モードで setup を実行して PMP を設定した後、MRET で U モードに降りて body を実行します。
+///
+/// M モードのハンドラは (a0, a1) = (mcause, mtval) を記録します。
+fn run_pmp(entries: Option<usize>, setup: &[u32], body: &[u32]) -> Cpu {
+    let handler = 4 * (3 + PRELUDE.len() + setup.len() + 4 + body.len() + 1) as u32;
+    let mut code = vec![
+        0x00000297, // auipc t0, 0
+        0x00028293 | (handler << 20), // addi t0, t0, handler
+        0x30529073, // csrw  mtvec, t0
+    ];
+    code.extend_from_slice(&PRELUDE);
+    code.extend_from_slice(setup);
+    code.extend_from_slice(&[
+        0x00000317, // auipc t1, 0
+        0x01030313, // addi  t1, t1, 16   (t1 = body)
+        0x34131073, // csrw  mepc, t1
+        0x30200073, // mret
+    ]);
+    code.extend_from_slice(body);
+    code.extend_from_slice(&[
+        0x0000006f, // j     .
+        // handler:
+        0x34202573, // csrr  a0, mcause
+        0x343025f3, // csrr  a1, mtval
+        0x0000006f, // j     .
+    ]);
+
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    if let Some(entries) = entries {
+        cpu.set_pmp_entries(entries);
+    }
+    for _ in 0..64 {
+        cpu.cycle();
+    }
+    cpu
+}
+
+/// M モードにトラップした際の (mcause, mtval) を取得します。
+fn run_fault(setup: &[u32], body: &[u32]) -> (u64, u64) {
+    let cpu = run_pmp(None, setup, body);
+    (cpu.read_register(10), cpu.read_register(11))
+}
+
+/// t3 = DATA とし、pmpaddr0 = DATA, pmpaddr2 = 全アドレス空間 (NAPOT) に設定する
+const PRELUDE: [u32; 7] = [
+    0x40001e37, // lui   t3, 0x40001
+    0x800e0e13, // addi  t3, t3, -2048
+    0x001e1e13, // slli  t3, t3, 1    (t3 = DATA)
+    0xfff00293, // li    t0, -1
+    0x3b229073, // csrw  pmpaddr2, t0
+    0x002e5293, // srli  t0, t3, 2
+    0x3b029073, // csrw  pmpaddr0, t0
+];
+const DATA: u64 = 0x8000_1000;
+
+/// pmpcfg0: エントリ 0 = NA4 (R), エントリ 2 = NAPOT (RWX)
+const NA4_READ_ONLY: [u32; 3] = [
+    0x001f02b7, // lui   t0, 0x1f0
+    0x01128293, // addi  t0, t0, 0x11
+    0x3a029073, // csrw  pmpcfg0, t0
+];
+/// pmpcfg0: エントリ 0 = NA4 (R, ロック), エントリ 2 = NAPOT (RWX)
+const NA4_LOCKED: [u32; 3] = [
+    0x001f02b7, // lui   t0, 0x1f0
+    0x09128293, // addi  t0, t0, 0x91
+    0x3a029073, // csrw  pmpcfg0, t0
+];
+
+#[test]
+fn test_pmp_priority_and_permissions() {
+    let cpu = run_pmp(None, &NA4_READ_ONLY, &[
+        0x000e2603, // lw    a2, 0(t3)
+        0x004e2683, // lw    a3, 4(t3)
+        0x00de2223, // sw    a3, 4(t3)    (エントリ 2 に一致)
+        0x00ce2023, // sw    a2, 0(t3)
+    ]);
+    // NOTE: 最も番号の小さいエントリ 0 が優先されるので、エントリ 2 で許可されていても書き込めない
+    assert_eq!((cpu.read_register(10), cpu.read_register(11)), (7, DATA));
+
+    // NOTE: アクセスの一部だけがエントリに一致する場合は失敗する
+    assert_eq!(run_fault(&NA4_READ_ONLY, &[0x000e3603]), (5, DATA)); // ld a2, 0(t3)
+    assert_eq!(run_fault(&NA4_READ_ONLY, &[0x000e0067]), (1, DATA)); // jr t3
+}
+
+#[test]
+fn test_pmp_tor() {
+    let tor = [
+        0x00001337, // lui   t1, 1
+        0x006e0333, // add   t1, t3, t1
+        0x00235313, // srli  t1, t1, 2
+        0x3b131073, // csrw  pmpaddr1, t1
+        0x001f12b7, // lui   t0, 0x1f1
+        0x90028293, // addi  t0, t0, -1792 (エントリ 1 = TOR (R), エントリ 2 = NAPOT (RWX))
+        0x3a029073, // csrw  pmpcfg0, t0
+    ];
+    let cpu = run_pmp(None, &tor, &[
+        0x000e2603, // lw    a2, 0(t3)
+        0x00ce2423, // sw    a2, 8(t3)
+    ]);
+    assert_eq!((cpu.read_register(10), cpu.read_register(11)), (7, DATA + 8));
+}
+
+#[test]
+fn test_pmp_tor_inverted() {
+    let tor = [
+        0x100e0313, // addi  t1, t3, 0x100
+        0x00235313, // srli  t1, t1, 2
+        0x3b031073, // csrw  pmpaddr0, t1
+        0x0fce0313, // addi  t1, t3, 0xfc
+        0x00235313, // srli  t1, t1, 2
+        0x3b131073, // csrw  pmpaddr1, t1
+        0x001f12b7, // lui   t0, 0x1f1
+        0x80028293, // addi  t0, t0, -2048 (エントリ 1 = TOR (なし), エントリ 2 = NAPOT (RWX))
+        0x3a029073, // csrw  pmpcfg0, t0
+    ];
+    // NOTE: pmpaddr0 > pmpaddr1 の TOR は空の範囲なので、その境界をまたぐアクセスにも一致しない
+    let cpu = run_pmp(None, &tor, &[
+        0x0fae3603, // ld    a2, 0xfa(t3)
+        0x00100693, // li    a3, 1
+    ]);
+    assert_eq!((cpu.read_register(10), cpu.read_register(13)), (0, 1));
+}
+
+#[test]
+fn test_pmp_machine_mode_and_lock() {
+    // NOTE: M モードのアクセスは、ロックされていないエントリを無視する
+    let cpu = run_pmp(None, &[NA4_READ_ONLY.as_slice(), &[0x000e2023]].concat(), &[]); // sw zero, 0(t3)
+    assert_eq!(cpu.read_register(10), 0);
+
+    let cpu = run_pmp(None, &[NA4_LOCKED.as_slice(), &[0x000e2023]].concat(), &[]); // sw zero, 0(t3)
+    assert_eq!((cpu.read_register(10), cpu.read_register(11)), (7, DATA));
+
+    // NOTE: ロックされたエントリの pmpcfg, pmpaddr への書き込みは無視される
+    let cpu = run_pmp(None, &[NA4_LOCKED.as_slice(), &[
+        0x3a001073, // csrw  pmpcfg0, zero
+        0x3a002773, // csrr  a4, pmpcfg0
+        0x3b001073, // csrw  pmpaddr0, zero
+        0x3b0027f3, // csrr  a5, pmpaddr0
+    ]].concat(), &[]);
+    assert_eq!(cpu.read_register(14), 0x91);
+    assert_eq!(cpu.read_register(15), DATA >> 2);
+
+    // NOTE: ロックされた TOR エントリの下限となる pmpaddr も書き換えられない
+    let cpu = run_pmp(None, &[
+        0x001f92b7, // lui   t0, 0x1f9
+        0x90028293, // addi  t0, t0, -1792 (エントリ 1 = TOR (R, ロック))
+        0x3a029073, // csrw  pmpcfg0, t0
+        0x3b001073, // csrw  pmpaddr0, zero
+        0x3b0027f3, // csrr  a5, pmpaddr0
+    ], &[]);
+    assert_eq!(cpu.read_register(15), DATA >> 2);
+}
+
+#[test]
+fn test_pmp_csr_warl() {
+    let code = [
+        0x00200293, // li    t0, 2
+        0x3a029073, // csrw  pmpcfg0, t0
+        0x3a002673, // csrr  a2, pmpcfg0
+        0x07f00293, // li    t0, 0x7f
+        0x3a029073, // csrw  pmpcfg0, t0
+        0x3a0026f3, // csrr  a3, pmpcfg0
+        0xfff00293, // li    t0, -1
+        0x3b029073, // csrw  pmpaddr0, t0
+        0x3b002773, // csrr  a4, pmpaddr0
+        0x3c029073, // csrw  pmpaddr16, t0
+        0x3c0027f3, // csrr  a5, pmpaddr16
+        0x3a102873, // csrr  a6, pmpcfg1
+    ];
+    let cpu = run_pmp(None, &code, &[]);
+    // NOTE: R = 0, W = 1 は予約済みの組み合わせで、予約済みのビットも 0 に固定される
+    assert_eq!(cpu.read_register(12), 0);
+    assert_eq!(cpu.read_register(13), 0x1f);
+    assert_eq!(cpu.read_register(14), (1 << 54) - 1);
+    // NOTE: 既定では 16 エントリなので、pmpaddr16 は 0 に固定される
+    assert_eq!(cpu.read_register(15), 0);
+    // NOTE: RV64 では奇数番目の pmpcfg は存在しない
+    assert_eq!(cpu.read_register(10), 2);
+
+    let cpu = run_pmp(Some(64), &code, &[]);
+    assert_eq!(cpu.read_register(15), (1 << 54) - 1);
+}
+
+#[test]
+fn test_pmp_no_matching_entry() {
+    // NOTE: PMP が実装されている場合、どのエントリにも一致しない U モードのアクセスは失敗する
+    let cpu = run_pmp(None, &[], &[0x000e2603]); // lw a2, 0(t3)
+    assert_eq!(cpu.read_register(10), 1);
+
+    // NOTE: PMP が実装されていなければ、すべてのアクセスが許可される
+    let cpu = run_pmp(Some(0), &[], &[0x000e2603]); // lw a2, 0(t3)
+    assert_eq!(cpu.read_register(10), 0);
+}
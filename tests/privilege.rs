@@ -0,0 +1,114 @@
+use riscv_emu::{Bus, Cpu, Memory, PrivilegeMode};
+
+/// MRET で U モード (setup によっては S モード) に降りてから trigger を実行し、M モードのハンドラで読み取った (mcause, mepc, mtval, mstatus) を返します。
+///
+/// setup は t2 = MPP (S) の状態で実行されます。trigger のアドレスは、setup が 1 命令なら 0x8000_0038 です。
+fn run_in_lower_mode(setup: &[u32], trigger: u32) -> (u64, u64, u64, u64) {
+    let handler = 48 + (setup.len() as u32 - 1) * 4;
+    let code: Vec<u32> = [&[
+        0xfff00293, // li    t0, -1
+        0x3b029073, // csrw  pmpaddr0, t0
+        0x01f00293, // li    t0, 0x1f
+        0x3a029073, // csrw  pmpcfg0, t0  (NAPOT, RWX: 全アドレス空間へのアクセスを許可)
+        0x00000297, // auipc t0, 0
+        0x00028293 | handler << 20, // addi t0, t0, handler
+        0x30529073, // csrw  mtvec, t0
+        0x000013b7, // lui   t2, 1
+        0x80038393, // addi  t2, t2, -2048 (t2 = MPP = S)
+    ][..], setup, &[
+        0x00000317, // auipc t1, 0
+        0x01030313, // addi  t1, t1, 16   (t1 = lower)
+        0x34131073, // csrw  mepc, t1
+        0x30200073, // mret
+        // lower:
+        trigger,    //
+        0x0000006f, // j     .
+        // handler:
+        0x34202573, // csrr  a0, mcause
+        0x341025f3, // csrr  a1, mepc
+        0x34302673, // csrr  a2, mtval
+        0x300026f3, // csrr  a3, mstatus
+        0x0000006f, // j     .
+    ]].concat();
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..32 {
+        cpu.cycle();
+    }
+    assert_eq!(cpu.mode(), PrivilegeMode::Machine);
+    (cpu.read_register(10), cpu.read_register(11), cpu.read_register(12), cpu.read_register(13))
+}
+
+const NOP: u32 = 0x00000013;
+const SET_MPP_S: u32 = 0x3003a073; // csrs mstatus, t2
+const SET_MPP_S_TSR: [u32; 3] = [SET_MPP_S, 0x004003b7, SET_MPP_S]; // csrs mstatus, t2; lui t2, 0x400; csrs mstatus, t2
+const SET_MPP_S_TW: [u32; 3] = [SET_MPP_S, 0x002003b7, SET_MPP_S]; // csrs mstatus, t2; lui t2, 0x200; csrs mstatus, t2
+
+#[test]
+fn test_ecall_from_user_mode() {
+    let (mcause, mepc, _, mstatus) = run_in_lower_mode(&[NOP], 0x00000073); // ecall
+    assert_eq!(mcause, 8);
+    assert_eq!(mepc, 0x8000_0038);
+    assert_eq!((mstatus >> 11) & 0b11, 0b00); // MPP = U
+}
+
+#[test]
+fn test_ecall_from_supervisor_mode() {
+    let (mcause, mepc, _, mstatus) = run_in_lower_mode(&[SET_MPP_S], 0x00000073); // ecall
+    assert_eq!(mcause, 9);
+    assert_eq!(mepc, 0x8000_0038);
+    assert_eq!((mstatus >> 11) & 0b11, 0b01); // MPP = S
+}
+
+#[test]
+fn test_mret_from_supervisor_mode_is_illegal() {
+    let (mcause, _, mtval, _) = run_in_lower_mode(&[SET_MPP_S], 0x30200073); // mret
+    assert_eq!(mcause, 2);
+    assert_eq!(mtval, 0x30200073);
+}
+
+#[test]
+fn test_sret_and_wfi_from_user_mode_are_illegal() {
+    let (mcause, _, mtval, _) = run_in_lower_mode(&[NOP], 0x10200073); // sret
+    assert_eq!(mcause, 2);
+    assert_eq!(mtval, 0x10200073);
+
+    let (mcause, _, mtval, _) = run_in_lower_mode(&[NOP], 0x10500073); // wfi
+    assert_eq!(mcause, 2);
+    assert_eq!(mtval, 0x10500073);
+}
+
+#[test]
+fn test_sret_from_supervisor_mode_with_tsr_is_illegal() {
+    let (mcause, mepc, mtval, _) = run_in_lower_mode(&SET_MPP_S_TSR, 0x10200073); // sret
+    assert_eq!(mcause, 2);
+    assert_eq!(mepc, 0x8000_0040);
+    assert_eq!(mtval, 0x10200073);
+
+    // NOTE: TSR = 0 なら SRET は実行され、sepc = 0 (U モード) に戻って命令フェッチで失敗する
+    let (mcause, mepc, _, _) = run_in_lower_mode(&[SET_MPP_S], 0x10200073); // sret
+    assert_eq!((mcause, mepc), (1, 0));
+}
+
+#[test]
+fn test_wfi_from_supervisor_mode_with_tw_is_illegal() {
+    let (mcause, mepc, mtval, _) = run_in_lower_mode(&SET_MPP_S_TW, 0x10500073); // wfi
+    assert_eq!(mcause, 2);
+    assert_eq!(mepc, 0x8000_0040);
+    assert_eq!(mtval, 0x10500073);
+}
+
+#[test]
+fn test_csr_access_above_privilege_is_illegal() {
+    let (mcause, _, mtval, _) = run_in_lower_mode(&[SET_MPP_S], 0x30002773); // csrr a4, mstatus
+    assert_eq!(mcause, 2);
+    assert_eq!(mtval, 0x30002773);
+
+    // NOTE: mcounteren が 0 なので、S モードからは cycle も読めない
+    let (mcause, _, _, _) = run_in_lower_mode(&[SET_MPP_S], 0xc0002773); // rdcycle a4
+    assert_eq!(mcause, 2);
+}
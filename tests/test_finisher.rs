@@ -0,0 +1,56 @@
+use riscv_emu::{Bus, Cpu, Device, ExitReason, Memory, TestFinisher};
+
+/// テストデバイスを配置して、code を実行します。
+fn run_finisher(code: &[u32]) -> Cpu {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device(TestFinisher::BASE, TestFinisher::SIZE, Box::new(TestFinisher::new())).unwrap();
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+    let mut cpu = Cpu::new(bus);
+    cpu.run(100);
+    cpu
+}
+
+#[test]
+fn test_finisher_poweroff() {
+    let cpu = run_finisher(&[
+        0x001002b7, // lui   t0, 0x100
+        0x00001337, // lui   t1, 1
+        0x2343031b, // addiw t1, t1, 0x234
+        0x0062a023, // sw    t1, 0(t0)    (無視される)
+        0x0002a503, // lw    a0, 0(t0)
+        0x002a3337, // lui   t1, 0x2a3
+        0x33330313, // addi  t1, t1, 0x333
+        0x0062a023, // sw    t1, 0(t0)    (終了コード 42 で失敗)
+        0x00100593, // li    a1, 1
+        0x0000006f, // j     .
+    ]);
+    assert_eq!(cpu.exit_reason(), Some(ExitReason::Poweroff(42)));
+    assert_eq!(cpu.read_register(10), 0);
+    // NOTE: 終了が要求された後の命令は実行されない
+    assert_eq!(cpu.read_register(11), 0);
+
+    let cpu = run_finisher(&[
+        0x001002b7, // lui   t0, 0x100
+        0x00007337, // lui   t1, 7
+        0x77730313, // addi  t1, t1, 0x777
+        0x0062a023, // sw    t1, 0(t0)    (再起動)
+        0x0000006f, // j     .
+    ]);
+    assert_eq!(cpu.exit_reason(), Some(ExitReason::Reset));
+}
+
+#[test]
+fn test_finisher_registers() {
+    let mut finisher = TestFinisher::new();
+    assert!(finisher.write(0, 0x5555, 8).is_err());
+    assert!(finisher.write(4, 0x5555, 4).is_err());
+    assert_eq!(finisher.exit_reason(), None);
+
+    finisher.write(0, 0xffff_5555, 4).unwrap();
+    assert_eq!(finisher.exit_reason(), Some(ExitReason::Poweroff(0)));
+    // NOTE: 最初に要求された終了が保持される
+    finisher.write(0, 0x7777, 4).unwrap();
+    assert_eq!(finisher.exit_reason(), Some(ExitReason::Poweroff(0)));
+}
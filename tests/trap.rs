@@ -0,0 +1,83 @@
+use riscv_emu::{Bus, Cpu, Memory};
+
+/// トラップを発生させる命令を 1 つ埋め込んだプログラムを実行し、ハンドラで読み取った (mcause, mepc, mtval, mstatus) を返します。
+fn run_trap(trigger: u32) -> (u64, u64, u64, u64) {
+    let code: Vec<u32> = vec![
+        0x00000297, // auipc t0, 0
+        0x01828293, // addi  t0, t0, 24   (t0 = handler)
+        0x30529073, // csrw  mtvec, t0
+        0x00001337, // lui   t1, 1        (t1 = 0x1000)
+        trigger,    //                    (0x8000_0010)
+        0x0000006f, // j     .
+        // handler:
+        0x34202573, // csrr  a0, mcause
+        0x341025f3, // csrr  a1, mepc
+        0x34302673, // csrr  a2, mtval
+        0x300026f3, // csrr  a3, mstatus
+        0x0000006f, // j     .
+    ];
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..32 {
+        cpu.cycle();
+    }
+    (cpu.read_register(10), cpu.read_register(11), cpu.read_register(12), cpu.read_register(13))
+}
+
+#[test]
+fn test_ecall_trap() {
+    let (mcause, mepc, mtval, mstatus) = run_trap(0x00000073); // ecall
+    assert_eq!(mcause, 11);
+    assert_eq!(mepc, 0x8000_0010);
+    assert_eq!(mtval, 0);
+    assert_eq!((mstatus >> 11) & 0b11, 0b11); // MPP = M
+}
+
+#[test]
+fn test_ebreak_trap() {
+    let (mcause, mepc, mtval, _) = run_trap(0x00100073); // ebreak
+    assert_eq!(mcause, 3);
+    assert_eq!(mepc, 0x8000_0010);
+    assert_eq!(mtval, 0x8000_0010);
+}
+
+#[test]
+fn test_illegal_instruction_trap() {
+    let (mcause, mepc, mtval, _) = run_trap(0xffffffff);
+    assert_eq!(mcause, 2);
+    assert_eq!(mepc, 0x8000_0010);
+    assert_eq!(mtval, 0xffffffff);
+}
+
+#[test]
+fn test_load_access_fault_trap() {
+    let (mcause, mepc, mtval, _) = run_trap(0x00033303); // ld t1, 0(t1)
+    assert_eq!(mcause, 5);
+    assert_eq!(mepc, 0x8000_0010);
+    assert_eq!(mtval, 0x1000);
+}
+
+#[test]
+fn test_csr_access_trap() {
+    // NOTE: 読み取り専用 CSR への書き込み
+    let (mcause, _, mtval, _) = run_trap(0xf1401073); // csrw mhartid, zero
+    assert_eq!(mcause, 2);
+    assert_eq!(mtval, 0xf1401073);
+
+    // NOTE: 未実装の CSR へのアクセス
+    let (mcause, _, mtval, _) = run_trap(0x7c002773); // csrr a4, 0x7c0
+    assert_eq!(mcause, 2);
+    assert_eq!(mtval, 0x7c002773);
+}
+
+#[test]
+fn test_amo_access_fault_trap() {
+    // NOTE: AMO の読み込みで発生したフォールトはストアのフォールトとして報告される
+    let (mcause, _, mtval, _) = run_trap(0x00e3372f); // amoadd.d a4, a4, (t1)
+    assert_eq!(mcause, 7);
+    assert_eq!(mtval, 0x1000);
+}
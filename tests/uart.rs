@@ -0,0 +1,154 @@
+use riscv_emu::{Bus, Cpu, Device, Memory, MemoryBackend, Uart};
+
+const BASE: u64 = Uart::BASE;
+
+/// MemoryBackend に接続した UART を配置したバスを作成します。
+fn uart_bus(backend: &MemoryBackend) -> Bus {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device_with_irq(BASE, Uart::SIZE, Uart::IRQ, Box::new(Uart::new(Box::new(backend.clone())))).unwrap();
+    bus
+}
+
+#[test]
+fn test_uart_from_cpu() {
+    let backend = MemoryBackend::new();
+    backend.push_input(b"x");
+    let mut bus = uart_bus(&backend);
+
+    let code: [u32; 8] = [
+        0x100002b7, // lui t0, 0x10000
+        0x04800313, // li  t1, 'H'
+        0x00628023, // sb  t1, 0(t0)   (THR)
+        0x06900313, // li  t1, 'i'
+        0x00628023, // sb  t1, 0(t0)   (THR)
+        0x0052c503, // lbu a0, 5(t0)   (LSR)
+        0x0002c583, // lbu a1, 0(t0)   (RBR)
+        0x0052c603, // lbu a2, 5(t0)   (LSR)
+    ];
+    for (i, word) in code.iter().enumerate() {
+        bus.write(0x8000_0000 + i as u64 * 4, *word as u64, 4).unwrap();
+    }
+
+    let mut cpu = Cpu::new(bus);
+    for _ in 0..code.len() {
+        cpu.cycle();
+    }
+    assert_eq!(backend.take_output(), b"Hi");
+    assert_eq!(cpu.read_register(10), 0x61); // NOTE: DR | THRE | TEMT
+    assert_eq!(cpu.read_register(11), b'x' as u64);
+    assert_eq!(cpu.read_register(12), 0x60);
+}
+
+#[test]
+fn test_uart_registers() {
+    let backend = MemoryBackend::new();
+    let mut bus = uart_bus(&backend);
+
+    // NOTE: DLAB = 1 の間は、オフセット 0, 1 が分周器ラッチになる
+    bus.write(BASE + 3, 0x83, 1).unwrap();
+    bus.write(BASE, 0x0c, 1).unwrap();
+    bus.write(BASE + 1, 0x01, 1).unwrap();
+    assert_eq!((bus.read(BASE, 1).unwrap(), bus.read(BASE + 1, 1).unwrap()), (0x0c, 0x01));
+    bus.write(BASE + 3, 0x03, 1).unwrap();
+    assert_eq!(bus.read(BASE + 1, 1).unwrap(), 0);
+    assert!(backend.take_output().is_empty());
+
+    bus.write(BASE + 7, 0x5a, 1).unwrap();
+    assert_eq!(bus.read(BASE + 7, 1).unwrap(), 0x5a);
+    assert_eq!(bus.read(BASE + 6, 1).unwrap(), 0xb0);
+    assert!(bus.read(BASE, 4).is_err());
+
+    // NOTE: 割り込みが保留されていなければ IIR.0 = 1 で、割り込み線もアサートされない
+    bus.write(BASE + 2, 0x01, 1).unwrap();
+    assert_eq!(bus.read(BASE + 2, 1).unwrap(), 0xc1);
+    assert_eq!(bus.asserted_irqs().count(), 0);
+
+    // NOTE: ETBEI を有効にすると、送信保持レジスタが空の割り込みが保留され、IIR の読み込みでクリアされる
+    bus.write(BASE + 1, 0x02, 1).unwrap();
+    assert_eq!(bus.asserted_irqs().collect::<Vec<_>>(), [Uart::IRQ]);
+    assert_eq!(bus.read(BASE + 2, 1).unwrap(), 0xc2);
+    assert_eq!(bus.read(BASE + 2, 1).unwrap(), 0xc1);
+    bus.write(BASE, b'a' as u64, 1).unwrap();
+    assert_eq!(bus.read(BASE + 2, 1).unwrap(), 0xc2);
+    assert_eq!(backend.take_output(), b"a");
+}
+
+#[test]
+fn test_uart_fifo() {
+    let backend = MemoryBackend::new();
+    let mut uart = Uart::new(Box::new(backend.clone()));
+
+    // NOTE: FIFO 有効、トリガレベル 4 で受信データの割り込みを有効にする
+    uart.write(2, 0x41, 1).unwrap();
+    uart.write(1, 0x05, 1).unwrap();
+    backend.push_input(b"abc");
+    uart.tick();
+    assert!(!uart.irq());
+
+    // NOTE: トリガレベル未満でも、しばらく読み込まれなければキャラクタタイムアウトになる
+    for _ in 0..1024 {
+        uart.tick();
+    }
+    assert!(uart.irq());
+    assert_eq!(uart.read(2, 1).unwrap(), 0xcc);
+    assert_eq!(uart.read(0, 1).unwrap(), b'a' as u64);
+    assert!(!uart.irq());
+
+    backend.push_input(&[b'x'; 20]);
+    for _ in 0..300 {
+        uart.tick();
+    }
+    assert_eq!(uart.read(2, 1).unwrap(), 0xc4);
+    assert_eq!(backend.take_output(), b"");
+
+    // NOTE: ループバックモードでは、送信したデータを受信し、FIFO が溢れるとオーバーランエラーになる
+    uart.write(4, 0x1f, 1).unwrap();
+    assert_eq!(uart.read(6, 1).unwrap(), 0xf0);
+    uart.write(0, b'!' as u64, 1).unwrap();
+    assert_eq!(uart.read(2, 1).unwrap(), 0xc6);
+    assert_eq!(uart.read(5, 1).unwrap(), 0x63);
+    assert_eq!(uart.read(5, 1).unwrap(), 0x61);
+
+    // NOTE: FCR で受信 FIFO をクリアできる
+    uart.write(2, 0x43, 1).unwrap();
+    assert_eq!(uart.read(5, 1).unwrap(), 0x60);
+    assert!(!uart.irq());
+    assert!(backend.take_output().is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_backend_slow_client() {
+    use std::io::Read;
+
+    use riscv_emu::{UartBackend, UnixSocketBackend};
+
+    let path = std::env::temp_dir().join(format!("riscv-emu-uart-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut backend = UnixSocketBackend::bind(&path).unwrap();
+    let mut client = std::os::unix::net::UnixStream::connect(&path).unwrap();
+    client.set_nonblocking(true).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // NOTE: クライアントが受信しない間にソケットのバッファを超えて送信しても、接続は切れない
+    let chunk: Vec<u8> = (0..=255).collect();
+    for _ in 0..4096 {
+        backend.write_bytes(&chunk);
+    }
+    let mut received = Vec::new();
+    let mut buf = [0; 4096];
+    for _ in 0..1024 {
+        if let Ok(len) = client.read(&mut buf) {
+            received.extend_from_slice(&buf[..len]);
+        }
+        // NOTE: 受信の際に、送信待ちのバイト列が再送される
+        assert_eq!(backend.read_bytes(&mut [0]), 0);
+    }
+    // NOTE: 送信待ちの上限までは欠けずに届く
+    assert!(received.len() > 64 * 1024);
+    assert!(received[..64 * 1024].chunks(256).all(|c| c == chunk.as_slice()));
+
+    backend.write(b'!');
+    assert_eq!(client.read(&mut buf).unwrap(), 1);
+    assert_eq!(buf[0], b'!');
+}
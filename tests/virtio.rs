@@ -0,0 +1,736 @@
+use std::path::PathBuf;
+
+#[cfg(unix)]
+use riscv_emu::{UnixDatagramBackend, Virtio9p};
+use riscv_emu::{Bus, DiskMode, EntropySource, LoopbackBackend, Memory, MemoryBackend, NetBackend, PcapBackend, Plic, VirtioBlk, VirtioConsole, VirtioMmio, VirtioNet, VirtioRng};
+
+const DESC: u64 = 0x8001_0000;
+const AVAIL: u64 = 0x8001_1000;
+const USED: u64 = 0x8001_2000;
+const HEADER: u64 = 0x8002_0000;
+const DATA: u64 = 0x8002_1000;
+const STATUS: u64 = 0x8002_2000;
+const QUEUE_SIZE: u64 = 8;
+
+/// セクタ i がすべて i で埋められた、sectors セクタのディスクイメージを作成します。
+fn disk_image(name: &str, sectors: u8) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("riscv-emu-{}-{}.img", name, std::process::id()));
+    let data: Vec<u8> = (0..sectors).flat_map(|i| [i; 512]).collect();
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+/// virtio-mmio のレジスタ offset に書き込みます。
+fn write_reg(bus: &mut Bus, offset: u64, value: u64) {
+    bus.write(VirtioMmio::BASE + offset, value, 4).unwrap();
+}
+
+/// virtio-mmio のレジスタ offset を読み込みます。
+fn read_reg(bus: &mut Bus, offset: u64) -> u64 {
+    bus.read(VirtioMmio::BASE + offset, 4).unwrap()
+}
+
+/// PLIC と virtio-blk を配置し、ドライバの初期化 (機能の交渉とキュー 0 の設定) を済ませます。
+fn setup(blk: VirtioBlk) -> Bus {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device(Plic::BASE, Plic::SIZE, Box::new(Plic::new(32))).unwrap();
+    bus.map_device_with_irq(VirtioMmio::BASE, VirtioMmio::SIZE, VirtioMmio::IRQ, Box::new(VirtioMmio::new(Box::new(blk)))).unwrap();
+    // NOTE: コンテキスト 0 で割り込み源 1 を許可する
+    bus.write(Plic::BASE + 4, 1, 4).unwrap();
+    bus.write(Plic::BASE + 0x2000, 1 << VirtioMmio::IRQ, 4).unwrap();
+
+    write_reg(&mut bus, 0x070, 1 | 2); // ACKNOWLEDGE | DRIVER
+    write_reg(&mut bus, 0x024, 1);
+    write_reg(&mut bus, 0x020, 1); // VIRTIO_F_VERSION_1
+    write_reg(&mut bus, 0x070, 1 | 2 | 8); // FEATURES_OK
+    assert_eq!(read_reg(&mut bus, 0x070), 1 | 2 | 8);
+
+    write_reg(&mut bus, 0x030, 0);
+    assert_eq!(read_reg(&mut bus, 0x034), 256);
+    write_reg(&mut bus, 0x038, QUEUE_SIZE);
+    for (offset, addr) in [(0x080, DESC), (0x090, AVAIL), (0x0a0, USED)] {
+        write_reg(&mut bus, offset, addr & 0xFFFF_FFFF);
+        write_reg(&mut bus, offset + 4, addr >> 32);
+    }
+    write_reg(&mut bus, 0x044, 1);
+    write_reg(&mut bus, 0x070, 1 | 2 | 8 | 4); // DRIVER_OK
+    bus
+}
+
+/// 種類 kind のリクエストをキューに追加して通知し、used リングに返された (書き込まれたバイト数, ステータス) を取得します。
+fn request(bus: &mut Bus, index: u16, kind: u32, sector: u64, len: u32) -> (u64, u8) {
+    let desc = |i: u64| DESC + i * 16;
+    // NOTE: ヘッダ -> データ -> ステータスの 3 つのディスクリプタを連結する
+    bus.write(HEADER, kind as u64, 4).unwrap();
+    bus.write(HEADER + 8, sector, 8).unwrap();
+    let data_flags = if kind == 1 { 1 } else { 1 | 2 };
+    for (i, (addr, len, flags, next)) in [(HEADER, 16, 1, 1), (DATA, len, data_flags, 2), (STATUS, 1, 2, 0)].into_iter().enumerate() {
+        bus.write(desc(i as u64), addr, 8).unwrap();
+        bus.write(desc(i as u64) + 8, len as u64, 4).unwrap();
+        bus.write(desc(i as u64) + 12, flags, 2).unwrap();
+        bus.write(desc(i as u64) + 14, next, 2).unwrap();
+    }
+    bus.write(AVAIL + 4 + (index as u64 % QUEUE_SIZE) * 2, 0, 2).unwrap();
+    bus.write(AVAIL + 2, index as u64 + 1, 2).unwrap();
+    write_reg(bus, 0x050, 0);
+    bus.tick();
+
+    assert_eq!(bus.read(USED + 2, 2).unwrap(), index as u64 + 1);
+    let elem = USED + 4 + (index as u64 % QUEUE_SIZE) * 8;
+    assert_eq!(bus.read(elem, 4).unwrap(), 0);
+    (bus.read(elem + 4, 4).unwrap(), bus.read(STATUS, 1).unwrap() as u8)
+}
+
+#[test]
+fn test_virtio_blk_copy_on_write() {
+    let path = disk_image("cow", 8);
+    let blk = VirtioBlk::open(&path, DiskMode::CopyOnWrite).unwrap();
+    assert_eq!(blk.capacity(), 8);
+    let mut bus = setup(blk);
+    assert_eq!(bus.read(VirtioMmio::BASE + 0x100, 8).unwrap(), 8);
+
+    // NOTE: セクタ 2, 3 を読み込む
+    assert_eq!(request(&mut bus, 0, 0, 2, 1024), (1025, 0));
+    assert_eq!((bus.read(DATA, 1).unwrap(), bus.read(DATA + 1023, 1).unwrap()), (2, 3));
+
+    // NOTE: 完了すると InterruptStatus がセットされ、PLIC を経由して MEIP が通知される
+    assert_eq!(read_reg(&mut bus, 0x060), 1);
+    bus.tick();
+    assert_eq!(bus.interrupts(), 1 << 11);
+    assert_eq!(bus.read(Plic::BASE + 0x20_0004, 4).unwrap(), VirtioMmio::IRQ as u64);
+    write_reg(&mut bus, 0x064, 1);
+    bus.write(Plic::BASE + 0x20_0004, VirtioMmio::IRQ as u64, 4).unwrap();
+    bus.tick();
+    assert_eq!(bus.interrupts(), 0);
+
+    // NOTE: セクタ 1 に書き込むと、読み込みにはオーバーレイの内容が反映される
+    bus.write(DATA, 0xaa, 1).unwrap();
+    assert_eq!(request(&mut bus, 1, 1, 1, 512), (1, 0));
+    assert_eq!(request(&mut bus, 2, 0, 0, 1024), (1025, 0));
+    assert_eq!((bus.read(DATA, 1).unwrap(), bus.read(DATA + 512, 1).unwrap()), (0, 0xaa));
+
+    // NOTE: 範囲外へのアクセスや、セクタ単位でない転送は失敗する
+    assert_eq!(request(&mut bus, 3, 0, 7, 1024).1, 1);
+    assert_eq!(request(&mut bus, 4, 0, 0, 100).1, 1);
+    assert_eq!(request(&mut bus, 5, 4, 0, 0).1, 0);
+    assert_eq!(request(&mut bus, 6, 3, 0, 0).1, 2);
+
+    // NOTE: ディスクイメージは変更されない
+    assert_eq!(std::fs::read(&path).unwrap()[512], 1);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_virtio_blk_modes() {
+    let path = disk_image("modes", 4);
+    let mut bus = setup(VirtioBlk::open(&path, DiskMode::ReadWrite).unwrap());
+    bus.write(DATA, 0x55, 1).unwrap();
+    assert_eq!(request(&mut bus, 0, 1, 3, 512), (1, 0));
+    assert_eq!(std::fs::read(&path).unwrap()[3 * 512], 0x55);
+
+    // NOTE: GET_ID はデバイスの ID を返す
+    assert_eq!(request(&mut bus, 1, 8, 0, 20), (21, 0));
+    assert_eq!(bus.read(DATA, 8).unwrap().to_le_bytes(), *b"riscv-em");
+
+    // NOTE: 読み取り専用のデバイスは VIRTIO_BLK_F_RO を提供し、書き込みは失敗する
+    let mut bus = setup(VirtioBlk::open(&path, DiskMode::ReadOnly).unwrap());
+    assert_eq!(read_reg(&mut bus, 0x010) & (1 << 5), 1 << 5);
+    assert_eq!(request(&mut bus, 0, 1, 0, 512), (1, 1));
+    assert_eq!(std::fs::read(&path).unwrap()[0], 0);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_virtio_blk_malformed_requests() {
+    let path = disk_image("malformed", 4);
+    let mut bus = setup(VirtioBlk::open(&path, DiskMode::ReadOnly).unwrap());
+    std::fs::remove_file(path).unwrap();
+
+    // NOTE: 範囲外のディスクリプタから始まるリクエストは、何も書き込まずに返され、後続のリクエストは処理される
+    bus.write(AVAIL + 4, 9, 2).unwrap();
+    bus.write(AVAIL + 2, 1, 2).unwrap();
+    assert_eq!(request(&mut bus, 1, 0, 0, 512), (513, 0));
+    assert_eq!((bus.read(USED + 4, 4).unwrap(), bus.read(USED + 8, 4).unwrap()), (9, 0));
+
+    // NOTE: RAM に収まらないバッファを含むリクエストも、何も書き込まずに返される
+    bus.write(STATUS, 0xff, 1).unwrap();
+    assert_eq!(request(&mut bus, 2, 0, 0, 0x4000_0000), (0, 0xff));
+    assert_eq!(request(&mut bus, 3, 0, 1, 512), (513, 0));
+    assert_eq!(bus.read(DATA, 1).unwrap(), 1);
+}
+
+#[test]
+fn test_virtio_mmio_registers() {
+    let path = disk_image("registers", 1);
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    let mmio = VirtioMmio::new(Box::new(VirtioBlk::open(&path, DiskMode::ReadOnly).unwrap()));
+    bus.map_device(VirtioMmio::BASE, VirtioMmio::SIZE, Box::new(mmio)).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(read_reg(&mut bus, 0x000), 0x7472_6976);
+    assert_eq!(read_reg(&mut bus, 0x004), 2);
+    assert_eq!(read_reg(&mut bus, 0x008), 2);
+    assert!(bus.read(VirtioMmio::BASE, 1).is_err());
+
+    // NOTE: VIRTIO_F_VERSION_1 は常に提供される
+    write_reg(&mut bus, 0x014, 1);
+    assert_eq!(read_reg(&mut bus, 0x010), 1);
+
+    // NOTE: VIRTIO_F_VERSION_1 を受け入れない (レガシーな) ドライバや、提供していない機能は拒否される
+    write_reg(&mut bus, 0x070, 1 | 2 | 8);
+    assert_eq!(read_reg(&mut bus, 0x070), 1 | 2);
+    write_reg(&mut bus, 0x070, 0);
+    write_reg(&mut bus, 0x020, 1 << 6);
+    write_reg(&mut bus, 0x024, 1);
+    write_reg(&mut bus, 0x020, 1);
+    write_reg(&mut bus, 0x070, 1 | 2 | 8);
+    assert_eq!(read_reg(&mut bus, 0x070), 1 | 2);
+
+    // NOTE: 存在しないキューは選択できず、キューのサイズは 2 の冪乗でなければならない
+    write_reg(&mut bus, 0x030, 1);
+    assert_eq!(read_reg(&mut bus, 0x034), 0);
+    write_reg(&mut bus, 0x030, 0);
+    write_reg(&mut bus, 0x038, 3);
+    write_reg(&mut bus, 0x044, 1);
+    assert_eq!(read_reg(&mut bus, 0x044), 1);
+
+    // NOTE: Status に 0 を書き込むとリセットされる
+    write_reg(&mut bus, 0x070, 0);
+    assert_eq!((read_reg(&mut bus, 0x070), read_reg(&mut bus, 0x044)), (0, 0));
+}
+
+/// キュー q のディスクリプタテーブルなどを配置するアドレス
+const fn queue_area(q: usize) -> u64 {
+    0x8004_0000 + q as u64 * 0x1000
+}
+
+/// キューを手で操作する、テスト用の簡易的なドライバ
+///
+/// キューのサイズは 8 で、リクエストはデバイスが読み込むバッファと書き込むバッファの、最大 2 つのディスクリプタからなります。
+struct Driver {
+    bus: Bus,
+    /// 次に追加する available リングの位置
+    avail_idx: Vec<u16>,
+    /// 次に使うディスクリプタの番号
+    next_desc: Vec<u16>,
+    /// 次に読み込む used リングの位置
+    used_idx: Vec<u16>,
+}
+impl Driver {
+    /// VIRTIO_F_VERSION_1 と features を受け入れ、queues 個のキューを設定します。
+    fn new(mut bus: Bus, features: u64, queues: usize) -> Self {
+        let features = features | (1 << 32);
+        write_reg(&mut bus, 0x070, 1 | 2);
+        for sel in 0..2 {
+            write_reg(&mut bus, 0x024, sel);
+            write_reg(&mut bus, 0x020, (features >> (sel * 32)) & 0xFFFF_FFFF);
+        }
+        write_reg(&mut bus, 0x070, 1 | 2 | 8);
+        assert_eq!(read_reg(&mut bus, 0x070), 1 | 2 | 8);
+        for q in 0..queues {
+            write_reg(&mut bus, 0x030, q as u64);
+            write_reg(&mut bus, 0x038, QUEUE_SIZE);
+            for (offset, addr) in [(0x080, queue_area(q)), (0x090, queue_area(q) + 0x400), (0x0a0, queue_area(q) + 0x800)] {
+                write_reg(&mut bus, offset, addr & 0xFFFF_FFFF);
+                write_reg(&mut bus, offset + 4, addr >> 32);
+            }
+            write_reg(&mut bus, 0x044, 1);
+        }
+        write_reg(&mut bus, 0x070, 1 | 2 | 8 | 4);
+        Self { bus, avail_idx: vec![0; queues], next_desc: vec![0; queues], used_idx: vec![0; queues] }
+    }
+
+    /// キュー q のディスクリプタ desc が指すバッファのアドレス (ディスクリプタごとに 0x200 バイト)
+    fn buffer(&self, q: usize, desc: u16) -> u64 {
+        0x8008_0000 + q as u64 * 0x1000 + desc as u64 * 0x200
+    }
+
+    /// キュー q に、デバイスが読み込むバッファ data を追加して通知します。
+    fn send(&mut self, q: usize, data: &[u8]) {
+        self.add(q, data, 0);
+        write_reg(&mut self.bus, 0x050, q as u64);
+    }
+
+    /// キュー q に、デバイスが書き込む len バイトのバッファを追加して通知します。
+    fn give(&mut self, q: usize, len: u32) {
+        self.add(q, &[], len);
+        write_reg(&mut self.bus, 0x050, q as u64);
+    }
+
+    /// キュー q に request と、デバイスが書き込む len バイトのバッファを追加して通知し、その応答を取得します。
+    fn transact(&mut self, q: usize, request: &[u8], len: u32) -> Vec<u8> {
+        self.add(q, request, len);
+        write_reg(&mut self.bus, 0x050, q as u64);
+        self.bus.tick();
+        self.receive(q).unwrap()
+    }
+
+    /// キュー q にディスクリプタを 1 つ書き込み、その番号を返します。
+    fn write_desc(&mut self, q: usize, len: u32, flags: u64) -> u16 {
+        let index = self.next_desc[q] % QUEUE_SIZE as u16;
+        self.next_desc[q] = self.next_desc[q].wrapping_add(1);
+        let desc = queue_area(q) + index as u64 * 16;
+        self.bus.write(desc, self.buffer(q, index), 8).unwrap();
+        self.bus.write(desc + 8, len as u64, 4).unwrap();
+        self.bus.write(desc + 12, flags, 2).unwrap();
+        index
+    }
+
+    /// キュー q に、readable (空でなければ) と len バイトの書き込み先 (0 でなければ) からなるリクエストを追加します。
+    fn add(&mut self, q: usize, readable: &[u8], len: u32) {
+        let mut head = None;
+        if !readable.is_empty() || len == 0 {
+            let index = self.write_desc(q, readable.len() as u32, if len > 0 { 1 } else { 0 });
+            for (i, byte) in readable.iter().enumerate() {
+                self.bus.write(self.buffer(q, index) + i as u64, *byte as u64, 1).unwrap();
+            }
+            head = Some(index);
+        }
+        if len > 0 {
+            let index = self.write_desc(q, len, 2);
+            if let Some(head) = head {
+                self.bus.write(queue_area(q) + head as u64 * 16 + 14, index as u64, 2).unwrap();
+            }
+            head.get_or_insert(index);
+        }
+        let slot = self.avail_idx[q] as u64 % QUEUE_SIZE;
+        let avail = queue_area(q) + 0x400;
+        self.bus.write(avail + 4 + slot * 2, head.unwrap() as u64, 2).unwrap();
+        self.avail_idx[q] = self.avail_idx[q].wrapping_add(1);
+        self.bus.write(avail + 2, self.avail_idx[q] as u64, 2).unwrap();
+    }
+
+    /// キュー q の used リングから、デバイスが返したバッファ (書き込み先がなければ読み込んだバッファ) の内容を取り出します。
+    fn receive(&mut self, q: usize) -> Option<Vec<u8>> {
+        let used = queue_area(q) + 0x800;
+        if self.bus.read(used + 2, 2).unwrap() as u16 == self.used_idx[q] {
+            return None;
+        }
+        let elem = used + 4 + (self.used_idx[q] as u64 % QUEUE_SIZE) * 8;
+        let mut index = self.bus.read(elem, 4).unwrap() as u16;
+        let len = self.bus.read(elem + 4, 4).unwrap();
+        self.used_idx[q] = self.used_idx[q].wrapping_add(1);
+        // NOTE: 連結されていれば、次のディスクリプタが書き込み先
+        let desc = queue_area(q) + index as u64 * 16;
+        if self.bus.read(desc + 12, 2).unwrap() & 1 != 0 {
+            index = self.bus.read(desc + 14, 2).unwrap() as u16;
+        }
+        let addr = self.buffer(q, index);
+        Some((0..len).map(|i| self.bus.read(addr + i, 1).unwrap() as u8).collect())
+    }
+}
+
+/// virtio-console の制御メッセージを作成します。
+fn control(id: u32, event: u16, value: u16) -> Vec<u8> {
+    [&id.to_le_bytes()[..], &event.to_le_bytes(), &value.to_le_bytes()].concat()
+}
+
+#[test]
+fn test_virtio_console_multiport() {
+    let console = MemoryBackend::new();
+    let log = MemoryBackend::new();
+    let mut device = VirtioConsole::new(Box::new(console.clone()));
+    assert_eq!(device.add_port("log", Box::new(log.clone())), 1);
+
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device(VirtioMmio::BASE, VirtioMmio::SIZE, Box::new(VirtioMmio::new(Box::new(device)))).unwrap();
+    assert_eq!(read_reg(&mut bus, 0x008), 3);
+    assert_eq!(read_reg(&mut bus, 0x104), 2); // max_nr_ports
+    let mut driver = Driver::new(bus, 1 << 1, 6);
+
+    // NOTE: ドライバの準備が完了すると、ポートが追加される
+    for _ in 0..4 {
+        driver.give(2, 64);
+    }
+    driver.send(3, &control(0, 0, 1));
+    driver.bus.tick();
+    assert_eq!(driver.receive(3), Some(vec![]));
+    assert_eq!(driver.receive(2), Some(control(0, 1, 1)));
+    assert_eq!(driver.receive(2), Some(control(1, 1, 1)));
+
+    // NOTE: ポートの準備が完了すると、名前とホスト側の接続が通知される
+    driver.send(3, &control(1, 3, 1));
+    driver.bus.tick();
+    assert_eq!(driver.receive(2), Some([control(1, 7, 1), b"log".to_vec()].concat()));
+    assert_eq!(driver.receive(2), Some(control(1, 6, 1)));
+    assert_eq!(driver.receive(2), None);
+
+    // NOTE: 各ポートの送信キューに書き込んだ内容は、それぞれのバックエンドに出力される
+    driver.send(1, b"console");
+    driver.send(5, b"log message");
+    driver.bus.tick();
+    assert_eq!((console.take_output(), log.take_output()), (b"console".to_vec(), b"log message".to_vec()));
+
+    // NOTE: ホストからの入力は、受信キューのバッファに収まる分ずつ渡される
+    // NOTE: ホストからの読み込みは一定の間隔でしか行わない
+    log.push_input(b"0123456789");
+    driver.give(4, 4);
+    driver.bus.tick();
+    assert_eq!(driver.receive(4), None);
+    for _ in 0..256 {
+        driver.bus.tick();
+    }
+    assert_eq!(driver.receive(4), Some(b"0123".to_vec()));
+    driver.give(4, 64);
+    driver.bus.tick();
+    assert_eq!(driver.receive(4), Some(b"456789".to_vec()));
+    assert_eq!(driver.receive(0), None);
+}
+
+#[test]
+fn test_virtio_console_single_port() {
+    let console = MemoryBackend::new();
+    let mut device = VirtioConsole::new(Box::new(console.clone()));
+    device.add_port("unused", Box::new(MemoryBackend::new()));
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device_with_irq(VirtioMmio::BASE, VirtioMmio::SIZE, VirtioMmio::IRQ, Box::new(VirtioMmio::new(Box::new(device)))).unwrap();
+
+    // NOTE: MULTIPORT を受け入れない場合は、ポート 0 のみが使える
+    let mut driver = Driver::new(bus, 0, 2);
+    console.push_input(b"hello");
+    driver.give(0, 64);
+    driver.bus.tick();
+    assert_eq!(driver.receive(0), Some(b"hello".to_vec()));
+    assert_eq!(driver.bus.asserted_irqs().collect::<Vec<_>>(), [VirtioMmio::IRQ]);
+
+    // NOTE: emerg_wr への書き込みは、ポート 0 に出力される
+    driver.bus.write(VirtioMmio::BASE + 0x108, b'!' as u64, 4).unwrap();
+    driver.send(1, b"world");
+    driver.bus.tick();
+    assert_eq!(console.take_output(), b"!world");
+}
+
+/// virtio-rng を配置し、キュー 0 から len バイトの乱数を 2 回要求します。
+fn random_bytes(source: EntropySource, len: u32) -> (Vec<u8>, Vec<u8>) {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device(VirtioMmio::BASE, VirtioMmio::SIZE, Box::new(VirtioMmio::new(Box::new(VirtioRng::new(source).unwrap())))).unwrap();
+    assert_eq!(read_reg(&mut bus, 0x008), 4);
+    let mut driver = Driver::new(bus, 0, 1);
+    driver.give(0, len);
+    driver.give(0, len);
+    driver.bus.tick();
+    (driver.receive(0).unwrap(), driver.receive(0).unwrap())
+}
+
+#[test]
+fn test_virtio_rng() {
+    // NOTE: 同じシード値なら同じ列が、異なるシード値なら異なる列が得られる
+    let (first, second) = random_bytes(EntropySource::Seeded(42), 20);
+    assert_eq!(first.len(), 20);
+    assert_ne!(first, second);
+    assert_eq!(random_bytes(EntropySource::Seeded(42), 20), (first.clone(), second));
+    assert_ne!(random_bytes(EntropySource::Seeded(43), 20).0, first);
+
+    let (first, second) = random_bytes(EntropySource::HostRandom, 64);
+    assert_eq!((first.len(), second.len()), (64, 64));
+    assert_ne!(first, second);
+}
+
+/// 9P のメッセージ (size[4] type[1] tag[2] body) を作成します。
+#[cfg(unix)]
+fn p9_message(kind: u8, body: &[&[u8]]) -> Vec<u8> {
+    let body = body.concat();
+    [&(7 + body.len() as u32).to_le_bytes()[..], &[kind], &1u16.to_le_bytes(), &body].concat()
+}
+
+/// 9P の文字列 (len[2] + UTF-8) を作成します。
+#[cfg(unix)]
+fn p9_string(s: &str) -> Vec<u8> {
+    [&(s.len() as u16).to_le_bytes()[..], s.as_bytes()].concat()
+}
+
+/// virtio-9p を配置し、バージョンの交渉とルートディレクトリへの fid 0 の割り当てを済ませます。
+#[cfg(unix)]
+fn setup_9p(root: &std::path::Path, read_only: bool) -> Driver {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    let device = Virtio9p::new(root, "share", read_only).unwrap();
+    bus.map_device(VirtioMmio::BASE, VirtioMmio::SIZE, Box::new(VirtioMmio::new(Box::new(device)))).unwrap();
+    assert_eq!(read_reg(&mut bus, 0x008), 9);
+    // NOTE: 設定領域は tag_len (u16) とマウントタグ
+    assert_eq!(bus.read(VirtioMmio::BASE + 0x100, 2).unwrap(), 5);
+    assert_eq!(bus.read(VirtioMmio::BASE + 0x102, 4).unwrap().to_le_bytes()[..4], *b"shar");
+    let mut driver = Driver::new(bus, 1, 1);
+
+    let response = driver.transact(0, &p9_message(100, &[&8192u32.to_le_bytes(), &p9_string("9P2000.L")]), 0x200);
+    assert_eq!(response, p9_message(101, &[&8192u32.to_le_bytes(), &p9_string("9P2000.L")]));
+    let attach = p9_message(104, &[&0u32.to_le_bytes(), &u32::MAX.to_le_bytes(), &p9_string("root"), &p9_string(""), &0u32.to_le_bytes()]);
+    assert_eq!(driver.transact(0, &attach, 0x200)[4], 105);
+    driver
+}
+
+/// fid から newfid へ names をたどる Twalk を作成します。
+#[cfg(unix)]
+fn p9_walk(fid: u32, newfid: u32, names: &[&str]) -> Vec<u8> {
+    let names: Vec<Vec<u8>> = names.iter().map(|name| p9_string(name)).collect();
+    p9_message(110, &[&fid.to_le_bytes(), &newfid.to_le_bytes(), &(names.len() as u16).to_le_bytes(), &names.concat()])
+}
+
+/// Rlerror のエラー番号を取得します。(Rlerror でなければ None)
+#[cfg(unix)]
+fn p9_error(response: &[u8]) -> Option<u32> {
+    (response[4] == 7).then(|| u32::from_le_bytes(response[7..11].try_into().unwrap()))
+}
+
+#[test]
+#[cfg(unix)]
+fn test_virtio_9p() {
+    let root = std::env::temp_dir().join(format!("riscv-emu-9p-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    std::fs::write(root.join("hello.txt"), b"hello world").unwrap();
+    let mut driver = setup_9p(&root, false);
+
+    // NOTE: ファイルをたどって開き、読み込む
+    let response = driver.transact(0, &p9_walk(0, 1, &["hello.txt"]), 0x200);
+    assert_eq!((response[4], &response[7..9]), (111, &[1, 0][..]));
+    assert_eq!(driver.transact(0, &p9_message(12, &[&1u32.to_le_bytes(), &0u32.to_le_bytes()]), 0x200)[4], 13);
+    let response = driver.transact(0, &p9_message(116, &[&1u32.to_le_bytes(), &6u64.to_le_bytes(), &100u32.to_le_bytes()]), 0x200);
+    assert_eq!((response[4], &response[7..11], &response[11..]), (117, &5u32.to_le_bytes()[..], &b"world"[..]));
+    let response = driver.transact(0, &p9_message(24, &[&1u32.to_le_bytes(), &u64::MAX.to_le_bytes()]), 0x200);
+    assert_eq!((response[4], u64::from_le_bytes(response[56..64].try_into().unwrap())), (25, 11));
+
+    // NOTE: 使用中の fid を newfid にはできない
+    assert_eq!(p9_error(&driver.transact(0, &p9_walk(0, 1, &["sub"]), 0x200)), Some(17));
+    assert_eq!(driver.transact(0, &p9_message(116, &[&1u32.to_le_bytes(), &0u64.to_le_bytes(), &5u32.to_le_bytes()]), 0x200)[11..], *b"hello");
+
+    // NOTE: 存在しないファイルや、共有ディレクトリより上へはたどれない
+    assert_eq!(p9_error(&driver.transact(0, &p9_walk(0, 4, &["missing"]), 0x200)), Some(2));
+    let response = driver.transact(0, &p9_walk(0, 4, &[".."]), 0x200);
+    let root_attr = driver.transact(0, &p9_message(24, &[&0u32.to_le_bytes(), &u64::MAX.to_le_bytes()]), 0x200);
+    assert_eq!(response[9..22], root_attr[15..28]);
+
+    // NOTE: ファイルを作成して書き込み、長さを変更する
+    assert_eq!(driver.transact(0, &p9_walk(0, 2, &[]), 0x200)[4], 111);
+    let create = p9_message(14, &[&2u32.to_le_bytes(), &p9_string("new.txt"), &2u32.to_le_bytes(), &0o644u32.to_le_bytes(), &0u32.to_le_bytes()]);
+    assert_eq!(driver.transact(0, &create, 0x200)[4], 15);
+    let write = p9_message(118, &[&2u32.to_le_bytes(), &0u64.to_le_bytes(), &4u32.to_le_bytes(), b"data"]);
+    assert_eq!(driver.transact(0, &write, 0x200)[7..11], 4u32.to_le_bytes());
+    assert_eq!(std::fs::read(root.join("new.txt")).unwrap(), b"data");
+    let setattr = p9_message(26, &[&2u32.to_le_bytes(), &8u32.to_le_bytes(), &[0; 12], &2u64.to_le_bytes(), &[0; 32]]);
+    assert_eq!(driver.transact(0, &setattr, 0x200)[4], 27);
+    assert_eq!(std::fs::read(root.join("new.txt")).unwrap(), b"da");
+
+    // NOTE: ディレクトリの作成, 名前の変更, 削除
+    let mkdir = p9_message(72, &[&0u32.to_le_bytes(), &p9_string("dir"), &0o755u32.to_le_bytes(), &0u32.to_le_bytes()]);
+    assert_eq!(driver.transact(0, &mkdir, 0x200)[4], 73);
+    assert!(root.join("dir").is_dir());
+    let renameat = p9_message(74, &[&0u32.to_le_bytes(), &p9_string("new.txt"), &0u32.to_le_bytes(), &p9_string("renamed.txt")]);
+    assert_eq!(driver.transact(0, &renameat, 0x200)[4], 75);
+    assert!(root.join("renamed.txt").exists() && !root.join("new.txt").exists());
+    let unlink = p9_message(76, &[&0u32.to_le_bytes(), &p9_string("renamed.txt"), &0u32.to_le_bytes()]);
+    assert_eq!(driver.transact(0, &unlink, 0x200)[4], 77);
+    let rmdir = p9_message(76, &[&0u32.to_le_bytes(), &p9_string("dir"), &0x200u32.to_le_bytes()]);
+    assert_eq!(driver.transact(0, &rmdir, 0x200)[4], 77);
+    assert!(!root.join("renamed.txt").exists() && !root.join("dir").exists());
+
+    // NOTE: ディレクトリのエントリを読み込む
+    assert_eq!(driver.transact(0, &p9_walk(0, 3, &[]), 0x200)[4], 111);
+    assert_eq!(driver.transact(0, &p9_message(12, &[&3u32.to_le_bytes(), &0u32.to_le_bytes()]), 0x200)[4], 13);
+    let response = driver.transact(0, &p9_message(40, &[&3u32.to_le_bytes(), &0u64.to_le_bytes(), &400u32.to_le_bytes()]), 0x200);
+    assert_eq!(response[4], 41);
+    let mut names = Vec::new();
+    let mut data = &response[11..];
+    while !data.is_empty() {
+        let len = u16::from_le_bytes(data[22..24].try_into().unwrap()) as usize;
+        names.push(String::from_utf8(data[24..24 + len].to_vec()).unwrap());
+        data = &data[24 + len..];
+    }
+    names.sort();
+    assert_eq!(names, [".", "..", "hello.txt", "sub"]);
+
+    assert_eq!(driver.transact(0, &p9_message(120, &[&3u32.to_le_bytes()]), 0x200)[4], 121);
+    assert_eq!(p9_error(&driver.transact(0, &p9_message(120, &[&3u32.to_le_bytes()]), 0x200)), Some(9));
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_virtio_9p_rename_moves_fids() {
+    let root = std::env::temp_dir().join(format!("riscv-emu-9p-rename-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("dir")).unwrap();
+    std::fs::write(root.join("dir/file"), b"content").unwrap();
+    let mut driver = setup_9p(&root, false);
+    let size = |driver: &mut Driver, fid: u32| {
+        let response = driver.transact(0, &p9_message(24, &[&fid.to_le_bytes(), &u64::MAX.to_le_bytes()]), 0x200);
+        p9_error(&response).map_or_else(|| Ok(u64::from_le_bytes(response[56..64].try_into().unwrap())), Err)
+    };
+    assert_eq!(driver.transact(0, &p9_walk(0, 1, &["dir"]), 0x200)[4], 111);
+    assert_eq!(driver.transact(0, &p9_walk(0, 2, &["dir", "file"]), 0x200)[4], 111);
+    assert_eq!(driver.transact(0, &p9_walk(0, 3, &["dir"]), 0x200)[4], 111);
+
+    // NOTE: Trenameat で名前を変更したディレクトリと、その中を指す fid も移動先を指す
+    let renameat = p9_message(74, &[&0u32.to_le_bytes(), &p9_string("dir"), &0u32.to_le_bytes(), &p9_string("moved")]);
+    assert_eq!(driver.transact(0, &renameat, 0x200)[4], 75);
+    assert_eq!(size(&mut driver, 2), Ok(7));
+
+    // NOTE: Trename でも、名前を変更した fid 以外の fid が移動先を指す
+    let rename = p9_message(20, &[&3u32.to_le_bytes(), &0u32.to_le_bytes(), &p9_string("again")]);
+    assert_eq!(driver.transact(0, &rename, 0x200)[4], 21);
+    assert_eq!(driver.transact(0, &p9_walk(1, 4, &["file"]), 0x200)[4], 111);
+    assert_eq!((size(&mut driver, 2), size(&mut driver, 4)), (Ok(7), Ok(7)));
+    assert!(root.join("again/file").exists());
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_virtio_9p_symlinks() {
+    let base = std::env::temp_dir().join(format!("riscv-emu-9p-symlink-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    let (root, outside) = (base.join("root"), base.join("outside"));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::create_dir_all(&outside).unwrap();
+    std::fs::write(outside.join("secret"), b"secret").unwrap();
+    std::os::unix::fs::symlink(&outside, root.join("dir")).unwrap();
+    std::os::unix::fs::symlink(outside.join("secret"), root.join("file")).unwrap();
+    let mut driver = setup_9p(&root, false);
+
+    // NOTE: 共有ディレクトリの外を指すシンボリックリンクの先へはたどれない
+    let response = driver.transact(0, &p9_walk(0, 1, &["dir", "secret"]), 0x200);
+    assert_eq!((response[4], &response[7..9]), (111, &[1, 0][..]));
+    assert_eq!(p9_error(&driver.transact(0, &p9_message(120, &[&1u32.to_le_bytes()]), 0x200)), Some(9));
+
+    // NOTE: シンボリックリンク自体は開けず、Treadlink で内容を読み込む
+    assert_eq!(driver.transact(0, &p9_walk(0, 1, &["dir"]), 0x200)[4], 111);
+    assert_eq!(p9_error(&driver.transact(0, &p9_message(12, &[&1u32.to_le_bytes(), &0u32.to_le_bytes()]), 0x200)), Some(40));
+    assert_eq!(p9_error(&driver.transact(0, &p9_message(40, &[&1u32.to_le_bytes(), &0u64.to_le_bytes(), &400u32.to_le_bytes()]), 0x200)), Some(20));
+    let response = driver.transact(0, &p9_message(22, &[&1u32.to_le_bytes()]), 0x200);
+    assert_eq!(response, p9_message(23, &[&p9_string(outside.to_str().unwrap())]));
+
+    // NOTE: シンボリックリンクの先に作成・書き込みはできない
+    let create = p9_message(14, &[&1u32.to_le_bytes(), &p9_string("new"), &2u32.to_le_bytes(), &0o644u32.to_le_bytes(), &0u32.to_le_bytes()]);
+    assert_eq!(p9_error(&driver.transact(0, &create, 0x200)), Some(40));
+    let mkdir = p9_message(72, &[&1u32.to_le_bytes(), &p9_string("new"), &0o755u32.to_le_bytes(), &0u32.to_le_bytes()]);
+    assert_eq!(p9_error(&driver.transact(0, &mkdir, 0x200)), Some(40));
+    assert_eq!(driver.transact(0, &p9_walk(0, 2, &[]), 0x200)[4], 111);
+    let create = p9_message(14, &[&2u32.to_le_bytes(), &p9_string("file"), &0o1001u32.to_le_bytes(), &0o644u32.to_le_bytes(), &0u32.to_le_bytes()]);
+    assert_eq!(p9_error(&driver.transact(0, &create, 0x200)), Some(40));
+    assert_eq!(driver.transact(0, &p9_walk(0, 3, &["file"]), 0x200)[4], 111);
+    let setattr = p9_message(26, &[&3u32.to_le_bytes(), &8u32.to_le_bytes(), &[0; 12], &0u64.to_le_bytes(), &[0; 32]]);
+    assert_eq!(p9_error(&driver.transact(0, &setattr, 0x200)), Some(40));
+    assert_eq!(std::fs::read(outside.join("secret")).unwrap(), b"secret");
+    assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 1);
+
+    // NOTE: シンボリックリンク自体の削除はできる
+    let unlink = p9_message(76, &[&0u32.to_le_bytes(), &p9_string("file"), &0u32.to_le_bytes()]);
+    assert_eq!(driver.transact(0, &unlink, 0x200)[4], 77);
+    assert!(outside.join("secret").exists() && !root.join("file").exists());
+    std::fs::remove_dir_all(base).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_virtio_9p_read_only() {
+    let root = std::env::temp_dir().join(format!("riscv-emu-9p-ro-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("file"), b"content").unwrap();
+    let mut driver = setup_9p(&root, true);
+
+    // NOTE: 読み込みは許可され、書き込みを伴う操作は EROFS で失敗する
+    assert_eq!(driver.transact(0, &p9_walk(0, 1, &["file"]), 0x200)[4], 111);
+    assert_eq!(p9_error(&driver.transact(0, &p9_message(12, &[&1u32.to_le_bytes(), &2u32.to_le_bytes()]), 0x200)), Some(30));
+    assert_eq!(driver.transact(0, &p9_message(12, &[&1u32.to_le_bytes(), &0u32.to_le_bytes()]), 0x200)[4], 13);
+    let write = p9_message(118, &[&1u32.to_le_bytes(), &0u64.to_le_bytes(), &1u32.to_le_bytes(), b"x"]);
+    assert_eq!(p9_error(&driver.transact(0, &write, 0x200)), Some(30));
+    let create = p9_message(14, &[&0u32.to_le_bytes(), &p9_string("new"), &2u32.to_le_bytes(), &0o644u32.to_le_bytes(), &0u32.to_le_bytes()]);
+    assert_eq!(p9_error(&driver.transact(0, &create, 0x200)), Some(30));
+    let unlink = p9_message(76, &[&0u32.to_le_bytes(), &p9_string("file"), &0u32.to_le_bytes()]);
+    assert_eq!(p9_error(&driver.transact(0, &unlink, 0x200)), Some(30));
+    assert_eq!(std::fs::read(root.join("file")).unwrap(), b"content");
+    assert!(!root.join("new").exists());
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+/// backend に接続した virtio-net を配置し、受信キューと送信キューを設定します。
+fn setup_net(mac: [u8; 6], backend: Box<dyn NetBackend>) -> Driver {
+    let mut bus = Bus::new(Memory::new(1024 * 1024));
+    bus.map_device(VirtioMmio::BASE, VirtioMmio::SIZE, Box::new(VirtioMmio::new(Box::new(VirtioNet::new(mac, backend))))).unwrap();
+    assert_eq!(read_reg(&mut bus, 0x008), 1);
+    Driver::new(bus, (1 << 5) | (1 << 16), 2)
+}
+
+#[test]
+fn test_virtio_net_loopback() {
+    let pcap = std::env::temp_dir().join(format!("riscv-emu-net-{}.pcap", std::process::id()));
+    let (a, b) = LoopbackBackend::pair();
+    let mut alice = setup_net([0x52, 0x54, 0, 0, 0, 1], Box::new(PcapBackend::create(Box::new(a), &pcap).unwrap()));
+    let mut bob = setup_net([0x52, 0x54, 0, 0, 0, 2], Box::new(b));
+    // NOTE: 設定領域は MAC アドレスとリンクの状態
+    assert_eq!(alice.bus.read(VirtioMmio::BASE + 0x100, 4).unwrap(), 0x0000_5452);
+    assert_eq!(bob.bus.read(VirtioMmio::BASE + 0x105, 1).unwrap(), 2);
+    assert_eq!(bob.bus.read(VirtioMmio::BASE + 0x106, 2).unwrap(), 1);
+
+    // NOTE: 送信するフレームの前には 12 バイトのヘッダが付く
+    let frame = [&[0x52, 0x54, 0, 0, 0, 2, 0x52, 0x54, 0, 0, 0, 1, 0x88, 0xb5][..], b"payload"].concat();
+    alice.send(1, &[&[0; 12][..], &frame].concat());
+    alice.bus.tick();
+    assert_eq!(alice.receive(1), Some(vec![]));
+
+    // NOTE: 受信キューにバッファが追加されるまで、フレームは保持される
+    bob.bus.tick();
+    assert_eq!(bob.receive(0), None);
+    bob.give(0, 0x200);
+    bob.bus.tick();
+    let received = bob.receive(0).unwrap();
+    assert_eq!((&received[..12], &received[12..]), (&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0][..], &frame[..]));
+
+    // NOTE: 逆方向の通信も記録される
+    bob.send(1, &[&[0; 12][..], b"reply"].concat());
+    bob.bus.tick();
+    alice.give(0, 0x200);
+    alice.bus.tick();
+    // NOTE: バックエンドからの受信は一定の間隔でしか行わない
+    assert_eq!(alice.receive(0), None);
+    for _ in 0..256 {
+        alice.bus.tick();
+    }
+    assert_eq!(&alice.receive(0).unwrap()[12..], b"reply");
+
+    // NOTE: pcap ファイルは 24 バイトのヘッダと、16 バイトのヘッダを持つレコードからなる
+    let data = std::fs::read(&pcap).unwrap();
+    assert_eq!(data[..4], 0xa1b2_c3d4u32.to_le_bytes());
+    assert_eq!(data[20..24], 1u32.to_le_bytes());
+    assert_eq!(data[32..36], (frame.len() as u32).to_le_bytes());
+    assert_eq!(data[40..40 + frame.len()], frame);
+    assert_eq!(data[40 + frame.len() + 16..], *b"reply");
+    std::fs::remove_file(pcap).unwrap();
+}
+
+#[test]
+fn test_pcap_snaplen() {
+    let pcap = std::env::temp_dir().join(format!("riscv-emu-snaplen-{}.pcap", std::process::id()));
+    let (a, mut b) = LoopbackBackend::pair();
+    let mut backend = PcapBackend::create(Box::new(a), &pcap).unwrap();
+    backend.send(&vec![0x55; 70000]);
+    assert_eq!(b.receive().map(|frame| frame.len()), Some(70000));
+
+    // NOTE: snaplen を超えるフレームは先頭だけが記録され、元の長さは orig_len に残る
+    let data = std::fs::read(&pcap).unwrap();
+    assert_eq!(data[16..20], 65535u32.to_le_bytes());
+    assert_eq!((&data[32..36], &data[36..40]), (&65535u32.to_le_bytes()[..], &70000u32.to_le_bytes()[..]));
+    assert_eq!(data.len(), 24 + 16 + 65535);
+    std::fs::remove_file(pcap).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn test_virtio_net_unix_datagram() {
+    let dir = std::env::temp_dir();
+    let path_a = dir.join(format!("riscv-emu-net-a-{}.sock", std::process::id()));
+    let path_b = dir.join(format!("riscv-emu-net-b-{}.sock", std::process::id()));
+    let _ = (std::fs::remove_file(&path_a), std::fs::remove_file(&path_b));
+    let mut a = UnixDatagramBackend::bind(&path_a, &path_b).unwrap();
+    let mut b = UnixDatagramBackend::bind(&path_b, &path_a).unwrap();
+
+    assert_eq!(b.receive(), None);
+    a.send(b"first");
+    a.send(b"second");
+    assert_eq!(b.receive().as_deref(), Some(&b"first"[..]));
+    assert_eq!(b.receive().as_deref(), Some(&b"second"[..]));
+    b.send(b"reply");
+    assert_eq!(a.receive().as_deref(), Some(&b"reply"[..]));
+    std::fs::remove_file(path_a).unwrap();
+    std::fs::remove_file(path_b).unwrap();
+}